@@ -6,6 +6,8 @@ class AdapterConfig(BaseModel):
     bind: str = "127.0.0.1:6000"
     core_grpc: str = "127.0.0.1:50051"
     version: str = "0.1.0"
+    # Pre-shared registration token (core.registry.auth.tokens).
+    token: str = ""
 
 
 def load_config() -> AdapterConfig:
@@ -16,5 +18,6 @@ def load_config() -> AdapterConfig:
         bind=os.getenv("PAGI_ADAPTER_BIND", "127.0.0.1:6000"),
         core_grpc=os.getenv("PAGI_CORE_GRPC", "127.0.0.1:50051"),
         version=os.getenv("PAGI_ADAPTER_VERSION", "0.1.0"),
+        token=os.getenv("PAGI_ADAPTER_TOKEN", ""),
     )
 
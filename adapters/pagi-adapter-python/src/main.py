@@ -1,6 +1,7 @@
 import asyncio
 import json
 import logging
+import signal
 
 import grpc
 
@@ -72,7 +73,13 @@ class AdapterService:
         )
 
 
-async def register_with_core(cfg) -> None:
+def _auth_metadata(cfg):
+    """Registration token as gRPC metadata, if one is configured."""
+    return (("authorization", f"Bearer {cfg.token}"),) if cfg.token else ()
+
+
+async def register_with_core(cfg) -> int:
+    """Register with the core; returns the lease TTL in ms (0 = no heartbeats required)."""
     agent_pb2, agent_pb2_grpc = _import_contracts()
 
     channel = grpc.aio.insecure_channel(cfg.core_grpc)
@@ -80,7 +87,7 @@ async def register_with_core(cfg) -> None:
 
     req = agent_pb2.RegisterAdapterRequest(
         adapter_id=cfg.adapter_id,
-        endpoint=f"http://{cfg.bind}",
+        endpoint=cfg.bind if cfg.bind.startswith("unix:") else f"http://{cfg.bind}",
         capabilities=agent_pb2.AdapterCapabilities(
             streaming=False,
             token_count=True,
@@ -90,9 +97,55 @@ async def register_with_core(cfg) -> None:
         version=cfg.version,
     )
 
-    resp = await stub.Register(req)
+    resp = await stub.Register(req, metadata=_auth_metadata(cfg))
     if not resp.ok:
         raise RuntimeError("core rejected adapter registration")
+    return resp.lease_ttl_ms
+
+
+async def heartbeat_forever(cfg, lease_ttl_ms: int) -> None:
+    """Renew the lease at a third of its TTL; re-register if the core evicted us."""
+    agent_pb2, agent_pb2_grpc = _import_contracts()
+
+    channel = grpc.aio.insecure_channel(cfg.core_grpc)
+    stub = agent_pb2_grpc.AdapterRegistryStub(channel)
+
+    while lease_ttl_ms > 0:
+        await asyncio.sleep(lease_ttl_ms / 3000.0)
+        try:
+            resp = await stub.Heartbeat(
+                agent_pb2.HeartbeatRequest(adapter_id=cfg.adapter_id), metadata=_auth_metadata(cfg)
+            )
+            lease_ttl_ms = resp.lease_ttl_ms
+        except grpc.aio.AioRpcError as e:
+            if e.code() == grpc.StatusCode.NOT_FOUND:
+                log.warning("evicted by core; re-registering")
+                lease_ttl_ms = await register_with_core(cfg)
+            else:
+                log.warning("heartbeat failed: %s", e.details())
+
+
+async def drain_and_deregister(cfg, timeout_s: float = 30.0) -> None:
+    """Stop receiving new traffic, wait for in-flight requests to finish, then deregister."""
+    agent_pb2, agent_pb2_grpc = _import_contracts()
+
+    channel = grpc.aio.insecure_channel(cfg.core_grpc)
+    stub = agent_pb2_grpc.AdapterRegistryStub(channel)
+
+    deadline = asyncio.get_running_loop().time() + timeout_s
+    try:
+        while True:
+            resp = await stub.Drain(
+                agent_pb2.DrainAdapterRequest(adapter_id=cfg.adapter_id), metadata=_auth_metadata(cfg)
+            )
+            if resp.in_flight == 0 or asyncio.get_running_loop().time() >= deadline:
+                break
+            await asyncio.sleep(0.2)
+        await stub.Deregister(
+            agent_pb2.DeregisterAdapterRequest(adapter_id=cfg.adapter_id), metadata=_auth_metadata(cfg)
+        )
+    except grpc.aio.AioRpcError as e:
+        log.warning("drain failed: %s", e.details())
 
 
 async def serve() -> None:
@@ -107,10 +160,20 @@ async def serve() -> None:
     server.add_insecure_port(cfg.bind)
 
     await server.start()
-    await register_with_core(cfg)
+    lease_ttl_ms = await register_with_core(cfg)
     log.info("registered with core")
-
-    await server.wait_for_termination()
+    heartbeat = asyncio.create_task(heartbeat_forever(cfg, lease_ttl_ms))
+
+    stop = asyncio.Event()
+    asyncio.get_running_loop().add_signal_handler(signal.SIGTERM, stop.set)
+
+    try:
+        await stop.wait()
+        log.info("SIGTERM received; draining")
+        await drain_and_deregister(cfg)
+    finally:
+        heartbeat.cancel()
+        await server.stop(grace=5)
 
 
 if __name__ == "__main__":
@@ -6,6 +6,8 @@ class ProviderConfig(BaseModel):
     bind: str = "127.0.0.1:6003"
     core_grpc: str = "127.0.0.1:50051"
     version: str = "0.1.0"
+    # Pre-shared registration token (core.registry.auth.tokens).
+    token: str = ""
     base_url: str = "http://127.0.0.1:11434/v1"
     default_model: str = "llama3.2:3b"
 
@@ -18,6 +20,7 @@ def load_config() -> ProviderConfig:
         bind=os.getenv("PAGI_ADAPTER_BIND", "127.0.0.1:6003"),
         core_grpc=os.getenv("PAGI_CORE_GRPC", "127.0.0.1:50051"),
         version=os.getenv("PAGI_ADAPTER_VERSION", "0.1.0"),
+        token=os.getenv("PAGI_ADAPTER_TOKEN", ""),
         base_url=os.getenv("OLLAMA_BASE_URL", "http://127.0.0.1:11434/v1"),
         default_model=os.getenv("PAGI_DEFAULT_MODEL", "llama3.2:3b"),
     )
@@ -24,7 +24,13 @@ class ProviderService:
         return await call_ollama(request, base_url=self.cfg.base_url, default_model=self.cfg.default_model)
 
 
-async def register_with_core(cfg) -> None:
+def _auth_metadata(cfg):
+    """Registration token as gRPC metadata, if one is configured."""
+    return (("authorization", f"Bearer {cfg.token}"),) if cfg.token else ()
+
+
+async def register_with_core(cfg) -> int:
+    """Register with the core; returns the lease TTL in ms (0 = no heartbeats required)."""
     agent_pb2, agent_pb2_grpc = _import_contracts()
 
     channel = grpc.aio.insecure_channel(cfg.core_grpc)
@@ -32,7 +38,7 @@ async def register_with_core(cfg) -> None:
 
     req = agent_pb2.RegisterAdapterRequest(
         adapter_id=cfg.adapter_id,
-        endpoint=f"http://{cfg.bind}",
+        endpoint=cfg.bind if cfg.bind.startswith("unix:") else f"http://{cfg.bind}",
         capabilities=agent_pb2.AdapterCapabilities(
             streaming=False,
             token_count=False,
@@ -41,9 +47,32 @@ async def register_with_core(cfg) -> None:
         ),
         version=cfg.version,
     )
-    resp = await stub.Register(req)
+    resp = await stub.Register(req, metadata=_auth_metadata(cfg))
     if not resp.ok:
         raise RuntimeError("core rejected adapter registration")
+    return resp.lease_ttl_ms
+
+
+async def heartbeat_forever(cfg, lease_ttl_ms: int) -> None:
+    """Renew the lease at a third of its TTL; re-register if the core evicted us."""
+    agent_pb2, agent_pb2_grpc = _import_contracts()
+
+    channel = grpc.aio.insecure_channel(cfg.core_grpc)
+    stub = agent_pb2_grpc.AdapterRegistryStub(channel)
+
+    while lease_ttl_ms > 0:
+        await asyncio.sleep(lease_ttl_ms / 3000.0)
+        try:
+            resp = await stub.Heartbeat(
+                agent_pb2.HeartbeatRequest(adapter_id=cfg.adapter_id), metadata=_auth_metadata(cfg)
+            )
+            lease_ttl_ms = resp.lease_ttl_ms
+        except grpc.aio.AioRpcError as e:
+            if e.code() == grpc.StatusCode.NOT_FOUND:
+                log.warning("evicted by core; re-registering")
+                lease_ttl_ms = await register_with_core(cfg)
+            else:
+                log.warning("heartbeat failed: %s", e.details())
 
 
 async def serve() -> None:
@@ -58,10 +87,14 @@ async def serve() -> None:
     server.add_insecure_port(cfg.bind)
 
     await server.start()
-    await register_with_core(cfg)
+    lease_ttl_ms = await register_with_core(cfg)
     log.info("registered with core")
+    heartbeat = asyncio.create_task(heartbeat_forever(cfg, lease_ttl_ms))
 
-    await server.wait_for_termination()
+    try:
+        await server.wait_for_termination()
+    finally:
+        heartbeat.cancel()
 
 
 if __name__ == "__main__":
@@ -93,7 +93,7 @@ async def call_ollama(req, *, base_url: str, default_model: str):
 
     if req.constraints.max_tokens:
         kwargs["max_tokens"] = int(req.constraints.max_tokens)
-    if req.constraints.temperature:
+    if req.constraints.HasField("temperature"):
         kwargs["temperature"] = float(req.constraints.temperature)
 
     started = time.time()
@@ -6,6 +6,8 @@ class ProviderConfig(BaseModel):
     bind: str = "127.0.0.1:6001"
     core_grpc: str = "127.0.0.1:50051"
     version: str = "0.1.0"
+    # Pre-shared registration token (core.registry.auth.tokens).
+    token: str = ""
     default_model: str = "gpt-4o-mini"
 
 
@@ -17,6 +19,7 @@ def load_config() -> ProviderConfig:
         bind=os.getenv("PAGI_ADAPTER_BIND", "127.0.0.1:6001"),
         core_grpc=os.getenv("PAGI_CORE_GRPC", "127.0.0.1:50051"),
         version=os.getenv("PAGI_ADAPTER_VERSION", "0.1.0"),
+        token=os.getenv("PAGI_ADAPTER_TOKEN", ""),
         default_model=os.getenv("PAGI_DEFAULT_MODEL", "gpt-4o-mini"),
     )
 
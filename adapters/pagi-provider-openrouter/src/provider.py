@@ -97,7 +97,7 @@ async def call_openrouter(req, *, default_model: str, base_url: str):
 
     if req.constraints.max_tokens:
         kwargs["max_tokens"] = int(req.constraints.max_tokens)
-    if req.constraints.temperature:
+    if req.constraints.HasField("temperature"):
         kwargs["temperature"] = float(req.constraints.temperature)
 
     headers = {
@@ -0,0 +1,101 @@
+//! Forwarding latency against a local mock adapter.
+//!
+//! `connect_per_request` reproduces the old behaviour (dial a new channel for every call);
+//! `pooled_channel` goes through `AdapterRegistryState::forward`, which reuses one channel.
+//!
+//! Run with `cargo bench --bench forward`.
+
+use std::net::SocketAddr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status};
+
+use pagi_gateway_core::canonical::CanonicalAIRequest;
+use pagi_gateway_core::config::{ProvidersConfig, RegistryConfig, RequestReplayConfig};
+use pagi_gateway_core::middleware::observability::Metrics;
+use pagi_gateway_core::proto::adapter_service_client::AdapterServiceClient;
+use pagi_gateway_core::proto::adapter_service_server::{AdapterService, AdapterServiceServer};
+use pagi_gateway_core::proto::{AdapterInfo, CanonicalAiChunk, CanonicalAiRequest, CanonicalAiResponse};
+use pagi_gateway_core::registry::AdapterRegistryState;
+
+struct MockAdapter;
+
+#[tonic::async_trait]
+impl AdapterService for MockAdapter {
+    async fn process(&self, request: Request<CanonicalAiRequest>) -> Result<Response<CanonicalAiResponse>, Status> {
+        let r = request.into_inner();
+        Ok(Response::new(CanonicalAiResponse {
+            request_id: r.request_id,
+            adapter_id: "mock".to_string(),
+            json: r#"{"text":"ok"}"#.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    type ProcessStreamStream = tokio_stream::Iter<std::vec::IntoIter<Result<CanonicalAiChunk, Status>>>;
+
+    async fn process_stream(
+        &self,
+        _request: Request<CanonicalAiRequest>,
+    ) -> Result<Response<Self::ProcessStreamStream>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+}
+
+async fn spawn_mock() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(AdapterServiceServer::new(MockAdapter))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr
+}
+
+fn forward(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let addr = rt.block_on(spawn_mock());
+    let endpoint = format!("http://{addr}");
+
+    let registry = AdapterRegistryState::new(
+        RequestReplayConfig::default(),
+        RegistryConfig::default(),
+        ProvidersConfig::default(),
+        Metrics::new(),
+    );
+    rt.block_on(registry.register(AdapterInfo {
+        adapter_id: "mock".to_string(),
+        endpoint: endpoint.clone(),
+        ..Default::default()
+    }))
+    .unwrap();
+
+    let mut group = c.benchmark_group("forward");
+
+    group.bench_function("connect_per_request", |b| {
+        b.to_async(&rt).iter(|| {
+            let endpoint = endpoint.clone();
+            async move {
+                let mut client = AdapterServiceClient::connect(endpoint).await.unwrap();
+                let req = CanonicalAiRequest { request_id: "bench".to_string(), ..Default::default() };
+                client.process(req).await.unwrap();
+            }
+        })
+    });
+
+    group.bench_function("pooled_channel", |b| {
+        b.to_async(&rt).iter(|| {
+            let registry = registry.clone();
+            async move {
+                registry.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, forward);
+criterion_main!(benches);
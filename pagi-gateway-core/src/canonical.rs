@@ -35,6 +35,34 @@ pub struct Message {
     /// Optional tool_call_id to associate tool results.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+
+    /// Tool invocations requested by an assistant turn (conversation history).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Message {
+    /// Concatenated text parts, ignoring non-text content.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|p| match p {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A single tool invocation emitted by the model.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+
+    /// Tool arguments as a JSON-encoded string (as produced by the model).
+    #[serde(default)]
+    pub arguments: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -51,7 +79,7 @@ pub struct Tool {
     pub strict: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct GenerationConstraints {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
@@ -78,22 +106,6 @@ pub struct GenerationConstraints {
     pub stream: bool,
 }
 
-impl Default for GenerationConstraints {
-    fn default() -> Self {
-        Self {
-            max_tokens: None,
-            temperature: None,
-            top_p: None,
-            top_k: None,
-            stop_sequences: vec![],
-            presence_penalty: None,
-            frequency_penalty: None,
-            reasoning_effort: None,
-            stream: false,
-        }
-    }
-}
-
 /// Canonical request used by the Rust core to avoid N² protocol/adaptor translation.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CanonicalAIRequest {
@@ -157,11 +169,128 @@ impl CanonicalAIRequest {
             content: vec![ContentPart::Text { text }],
             name: None,
             tool_call_id: None,
+            tool_calls: vec![],
         });
         req
     }
 }
 
+impl Default for CanonicalAIRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why generation ended, normalized across providers.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    Stop,
+    Length,
+    ToolCalls,
+    ContentFilter,
+    Error,
+}
+
+impl FinishReason {
+    /// The snake_case name, as used in stream chunks.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Error => "error",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Choice {
+    pub index: u32,
+    pub message: Message,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+
+    /// The stop sequence that ended generation, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+
+    /// Subset of `prompt_tokens` served from a provider-side cache.
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Canonical response returned by adapters, rendered back into each ingress protocol.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CanonicalAIResponse {
+    pub request_id: String,
+    pub adapter_id: String,
+
+    /// Model that actually served the request (may differ from `preferred_model`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+
+    /// Opaque adapter payload, kept as an escape hatch for adapter-specific fields.
+    #[serde(default)]
+    pub json: String,
+}
+
+impl CanonicalAIResponse {
+    /// Concatenated text of the first choice.
+    pub fn text(&self) -> String {
+        self.choices.first().map(|c| c.message.text()).unwrap_or_default()
+    }
+
+    /// Fill `choices`/`model` from the opaque `json` payload for adapters that predate the
+    /// structured response fields.
+    ///
+    /// Provider adapters put the completion in `text` (and the served model in `actual_model`),
+    /// the reference Python adapter uses `echo_text`; anything else is passed through verbatim.
+    pub fn fill_from_legacy_json(&mut self) {
+        if !self.choices.is_empty() || self.json.is_empty() {
+            return;
+        }
+        let payload: serde_json::Value = serde_json::from_str(&self.json).unwrap_or(serde_json::Value::Null);
+        let field = |keys: &[&str]| keys.iter().find_map(|k| payload.get(*k).and_then(|v| v.as_str())).map(str::to_string);
+
+        let text = field(&["text", "echo_text"]).unwrap_or_else(|| self.json.clone());
+        if self.model.is_none() {
+            self.model = field(&["actual_model", "model"]);
+        }
+        self.choices.push(Choice {
+            index: 0,
+            message: Message {
+                role: MessageRole::Assistant,
+                content: vec![ContentPart::Text { text }],
+                name: None,
+                tool_call_id: None,
+                tool_calls: vec![],
+            },
+            finish_reason: Some(FinishReason::Stop),
+            stop_sequence: None,
+        });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,5 +303,20 @@ mod tests {
         assert_eq!(back.agent_id.as_deref(), Some("a"));
         assert_eq!(back.messages.len(), 1);
     }
-}
 
+    #[test]
+    fn legacy_json_fills_choices() {
+        let mut resp = CanonicalAIResponse {
+            request_id: "r".to_string(),
+            adapter_id: "openrouter".to_string(),
+            model: None,
+            choices: vec![],
+            usage: None,
+            json: r#"{"text":"hi","actual_model":"m"}"#.to_string(),
+        };
+        resp.fill_from_legacy_json();
+        assert_eq!(resp.text(), "hi");
+        assert_eq!(resp.model.as_deref(), Some("m"));
+        assert_eq!(resp.choices[0].finish_reason, Some(FinishReason::Stop));
+    }
+}
@@ -1,24 +1,657 @@
-use serde::Deserialize;
+use std::collections::HashMap;
 
-#[derive(Debug, Clone, Deserialize)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub version: String,
     pub core: CoreConfig,
     #[serde(default)]
     pub adapters: Vec<AdapterConfig>,
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+}
+
+/// The `providers` section, keyed by adapter id, in declaration order.
+///
+/// Drives default routing: providers marked `default` are tried first, then those marked
+/// `failover`, each group in the order they appear in the file.
+#[derive(Debug, Clone, Default)]
+pub struct ProvidersConfig(pub Vec<(String, ProviderConfig)>);
+
+impl<'de> Deserialize<'de> for ProvidersConfig {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        // `serde_yaml::Mapping` preserves key order, unlike `HashMap`/`BTreeMap`.
+        let map = serde_yaml::Mapping::deserialize(d)?;
+        let mut out = Vec::with_capacity(map.len());
+        for (k, v) in map {
+            let id = k.as_str().ok_or_else(|| serde::de::Error::custom("provider id must be a string"))?.to_string();
+            let cfg = ProviderConfig::deserialize(v).map_err(serde::de::Error::custom)?;
+            out.push((id, cfg));
+        }
+        Ok(Self(out))
+    }
+}
+
+impl Serialize for ProvidersConfig {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+impl ProvidersConfig {
+    pub fn get(&self, id: &str) -> Option<&ProviderConfig> {
+        self.0.iter().find(|(k, _)| k == id).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProviderConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Tried first by default routing.
+    #[serde(default)]
+    pub default: bool,
+    /// Tried after the default providers fail.
+    #[serde(default)]
+    pub failover: bool,
+    /// Consumed by the provider adapter, not the core.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Consumed by the provider adapter, not the core.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Rewrites `preferred_model` when a request is sent to this provider.
+    #[serde(default)]
+    pub models_mapping: HashMap<String, String>,
+    /// Relative share of traffic under the `weighted_random` routing policy.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_true() -> bool {
+    true
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CoreConfig {
     pub bind_http: String,
     pub bind_grpc: String,
+    /// Also serve the registry gRPC API on this Unix socket path, for co-located adapters.
+    #[serde(default)]
+    pub bind_grpc_uds: Option<String>,
     #[serde(default)]
     pub request_replay: RequestReplayConfig,
     #[serde(default)]
     pub observability: ObservabilityConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub graphql: GraphqlConfig,
+}
+
+/// Limits on `/graphql`, for exposing it publicly.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GraphqlConfig {
+    /// Deepest field nesting a query may have. The default leaves room for GraphiQL's
+    /// introspection query.
+    #[serde(default = "default_graphql_max_depth")]
+    pub max_depth: usize,
+    /// Most fields a query may select, counting each selection once.
+    #[serde(default = "default_graphql_max_complexity")]
+    pub max_complexity: usize,
+    /// Answer `__schema`/`__type`; turn off in production.
+    #[serde(default = "default_true")]
+    pub introspection: bool,
+    /// Largest request body, and largest subscription WebSocket message, in bytes.
+    #[serde(default = "default_graphql_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Automatic persisted queries kept in memory (least recently used evicted); 0 disables them.
+    #[serde(default = "default_graphql_persisted_queries")]
+    pub persisted_queries: usize,
+    /// Bearer token that unlocks the operator queries (`adapters`, `adapterStats`, `requests`,
+    /// `config`).
+    #[serde(default)]
+    pub operator_token: Option<TokenConfig>,
+    /// JWT scope, in the `scope` or `scp` claim, that unlocks the operator queries. With neither
+    /// this nor `operator_token` set, nobody can run them.
+    #[serde(default)]
+    pub operator_scope: Option<String>,
+}
+
+fn default_graphql_max_depth() -> usize {
+    15
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+fn default_graphql_max_complexity() -> usize {
+    500
+}
+
+fn default_graphql_max_body_bytes() -> usize {
+    1 << 20
+}
+
+fn default_graphql_persisted_queries() -> usize {
+    1000
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: default_graphql_max_depth(),
+            max_complexity: default_graphql_max_complexity(),
+            introspection: true,
+            max_body_bytes: default_graphql_max_body_bytes(),
+            persisted_queries: default_graphql_persisted_queries(),
+            operator_token: None,
+            operator_scope: None,
+        }
+    }
+}
+
+/// Request quotas, enforced independently per client IP, API key, tenant and agent id. A
+/// dimension set to `null` is not limited.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// Proxies (CIDRs) whose `x-forwarded-for` is believed. The client IP is the right-most
+    /// address not in this list; without it the socket peer is used.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default = "default_ip_quota")]
+    pub ip: Option<QuotaConfig>,
+    #[serde(default)]
+    pub api_key: Option<QuotaConfig>,
+    #[serde(default)]
+    pub tenant: Option<QuotaConfig>,
+    #[serde(default)]
+    pub agent_id: Option<QuotaConfig>,
+    /// Quotas for specific values, keyed `<dimension>:<value>` (e.g. `tenant:acme`), replacing
+    /// that dimension's default for them.
+    #[serde(default)]
+    pub overrides: HashMap<String, QuotaConfig>,
+    /// Token budgets per tenant.
+    #[serde(default)]
+    pub tokens: Option<TokenLimitConfig>,
+}
+
+/// Per-tenant token budgets. Requests reserve their estimated prompt plus `max_tokens` up
+/// front and are settled against reported usage.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenLimitConfig {
+    /// Budget for tenants not listed in `tenants` (and for callers without a tenant).
+    #[serde(default)]
+    pub default: TokenQuotaConfig,
+    #[serde(default)]
+    pub tenants: HashMap<String, TokenQuotaConfig>,
+    /// Completion tokens reserved for requests that set no `max_tokens`.
+    #[serde(default = "default_reserved_max_tokens")]
+    pub default_max_tokens: u32,
+}
+
+/// Unset windows are unlimited.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TokenQuotaConfig {
+    #[serde(default)]
+    pub per_minute: Option<u64>,
+    #[serde(default)]
+    pub per_day: Option<u64>,
+}
+
+fn default_reserved_max_tokens() -> u32 {
+    1024
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            ip: default_ip_quota(),
+            api_key: None,
+            tenant: None,
+            agent_id: None,
+            overrides: HashMap::new(),
+            tokens: None,
+        }
+    }
+}
+
+/// `requests` per `period_secs`, allowing bursts of up to `burst` (default `requests`).
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct QuotaConfig {
+    pub requests: u32,
+    #[serde(default = "default_quota_period_secs")]
+    pub period_secs: u64,
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+fn default_ip_quota() -> Option<QuotaConfig> {
+    Some(QuotaConfig { requests: 50, period_secs: 1, burst: None })
+}
+
+fn default_quota_period_secs() -> u64 {
+    1
+}
+
+/// Client authentication for the HTTP ingresses (REST, OpenAI, Anthropic, GraphQL). With
+/// neither `jwt` nor `api_keys` set every request is anonymous.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+    #[serde(default)]
+    pub api_keys: Option<ApiKeysConfig>,
+    /// Reject requests without credentials. When false they pass through anonymously, but
+    /// credentials that are present must still be valid.
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self { jwt: None, api_keys: None, required: true }
+    }
+}
+
+/// Gateway-issued API keys (`pagi_...`), for callers that cannot mint JWTs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiKeysConfig {
+    /// JSON file holding the hashed keys; created on first write.
+    #[serde(default = "default_api_keys_path")]
+    pub store_path: String,
+    /// Bearer token for the `/admin/api-keys` endpoints; they are disabled without one.
+    #[serde(default)]
+    pub admin_token: Option<TokenConfig>,
+}
+
+fn default_api_keys_path() -> String {
+    "./api_keys.json".to_string()
+}
+
+/// JWTs signed by a key in a JWKS, read from `jwks_path` or fetched from `jwks_url`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JwtConfig {
+    #[serde(default)]
+    pub jwks_path: Option<String>,
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Reload the JWKS this often; a token with an unknown `kid` also triggers a reload.
+    #[serde(default = "default_jwks_refresh_secs")]
+    pub refresh_secs: u64,
+    /// Required `iss`, if set.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Accepted `aud` values; empty skips the audience check.
+    #[serde(default)]
+    pub audience: Vec<String>,
+    #[serde(default = "default_jwt_algorithms")]
+    pub algorithms: Vec<jsonwebtoken::Algorithm>,
+    /// Clock skew allowed on `exp` and `nbf`.
+    #[serde(default = "default_jwt_leeway_secs")]
+    pub leeway_secs: u64,
+    /// Claim copied to `metadata.user_id`.
+    #[serde(default = "default_user_claim")]
+    pub user_claim: String,
+    /// Claim copied to `metadata.tenant`.
+    #[serde(default = "default_tenant_claim")]
+    pub tenant_claim: String,
+}
+
+fn default_jwks_refresh_secs() -> u64 {
+    300
+}
+
+fn default_jwt_algorithms() -> Vec<jsonwebtoken::Algorithm> {
+    use jsonwebtoken::Algorithm;
+    vec![Algorithm::HS256, Algorithm::RS256, Algorithm::ES256]
+}
+
+fn default_jwt_leeway_secs() -> u64 {
+    30
+}
+
+fn default_user_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_tenant_claim() -> String {
+    "tenant".to_string()
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            jwks_path: None,
+            jwks_url: None,
+            refresh_secs: default_jwks_refresh_secs(),
+            issuer: None,
+            audience: Vec::new(),
+            algorithms: default_jwt_algorithms(),
+            leeway_secs: default_jwt_leeway_secs(),
+            user_claim: default_user_claim(),
+            tenant_claim: default_tenant_claim(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct RegistryConfig {
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    #[serde(default)]
+    pub forwarding: ForwardingConfig,
+    #[serde(default)]
+    pub hedging: HedgingConfig,
+    #[serde(default)]
+    pub auth: RegistrationAuthConfig,
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+}
+
+/// Per-adapter in-flight limits and the wait queue in front of them.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConcurrencyConfig {
+    /// Calls in flight per adapter before new ones queue; 0 means unlimited.
+    #[serde(default)]
+    pub max_in_flight: usize,
+    /// `max_in_flight` overrides by adapter id.
+    #[serde(default)]
+    pub adapters: HashMap<String, usize>,
+    /// Waiting calls per adapter; when the queue is full the next candidate is tried.
+    #[serde(default = "default_queue_depth")]
+    pub queue_depth: usize,
+    /// How long a call may wait for a slot before giving up with 503.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+}
+
+fn default_queue_depth() -> usize {
+    64
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    5_000
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            adapters: HashMap::new(),
+            queue_depth: default_queue_depth(),
+            queue_timeout_ms: default_queue_timeout_ms(),
+        }
+    }
+}
+
+/// Who may call the mutating `AdapterRegistry` RPCs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegistrationAuthConfig {
+    /// Pre-shared token per adapter id, sent as `authorization: Bearer <token>`.
+    #[serde(default)]
+    pub tokens: HashMap<String, TokenConfig>,
+    /// Whether adapter ids without a token entry may register at all.
+    #[serde(default = "default_true")]
+    pub allow_unlisted: bool,
+    /// Serve the registry's TCP listener with mTLS.
+    #[serde(default)]
+    pub tls: Option<RegistryTlsConfig>,
+}
+
+impl Default for RegistrationAuthConfig {
+    fn default() -> Self {
+        Self { tokens: HashMap::new(), allow_unlisted: true, tls: None }
+    }
+}
+
+/// A secret given inline (`token`) or read from an environment variable (`token_env`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenConfig {
+    #[serde(default, serialize_with = "redact")]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub token_env: Option<String>,
+}
+
+/// Serialize a secret as `"<redacted>"`, so the effective config can be shown.
+fn redact<S: Serializer>(secret: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(|_| "<redacted>").serialize(s)
+}
+
+impl TokenConfig {
+    pub fn resolve(&self) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| self.token_env.as_ref().and_then(|k| std::env::var(k).ok()))
+            .filter(|t| !t.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegistryTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// CA that adapter client certificates must chain to.
+    pub client_ca_path: String,
+    /// Require the client certificate's DNS or URI SAN to equal the adapter id.
+    #[serde(default = "default_true")]
+    pub require_san_match: bool,
+}
+
+/// Hedged unary forwards. When enabled, requests can opt out with `metadata.hedge: "false"`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HedgingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hedge once the primary is slower than this quantile of its recent latencies.
+    #[serde(default = "default_hedge_percentile")]
+    pub percentile: f64,
+    /// Hedge delay until an adapter has enough latency samples.
+    #[serde(default = "default_hedge_delay_ms")]
+    pub delay_ms: u64,
+    /// Never hedge sooner than this.
+    #[serde(default = "default_hedge_min_delay_ms")]
+    pub min_delay_ms: u64,
+}
+
+fn default_hedge_percentile() -> f64 {
+    0.95
+}
+
+fn default_hedge_delay_ms() -> u64 {
+    500
+}
+
+fn default_hedge_min_delay_ms() -> u64 {
+    10
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            percentile: default_hedge_percentile(),
+            delay_ms: default_hedge_delay_ms(),
+            min_delay_ms: default_hedge_min_delay_ms(),
+        }
+    }
+}
+
+/// Deadlines and retries for adapter calls.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ForwardingConfig {
+    /// Total deadline when the client sends no `x-pagi-timeout-ms`.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Upper bound on any deadline, including client-requested ones.
+    #[serde(default = "default_max_timeout_ms")]
+    pub max_timeout_ms: u64,
+    /// Timeout for a single adapter call (0 = the rest of the deadline).
+    #[serde(default = "default_attempt_timeout_ms")]
+    pub attempt_timeout_ms: u64,
+    /// Extra passes over the candidate list after retryable failures.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_backoff_base_ms")]
+    pub backoff_base_ms: u64,
+    #[serde(default = "default_backoff_max_ms")]
+    pub backoff_max_ms: u64,
+    /// Retry tokens earned per request.
+    #[serde(default = "default_budget_ratio")]
+    pub budget_ratio: f64,
+    /// Retry tokens earned per second regardless of traffic.
+    #[serde(default = "default_budget_min_per_sec")]
+    pub budget_min_per_sec: f64,
+}
+
+fn default_timeout_ms() -> u64 {
+    60_000
+}
+
+fn default_max_timeout_ms() -> u64 {
+    300_000
+}
+
+fn default_attempt_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_backoff_base_ms() -> u64 {
+    50
+}
+
+fn default_backoff_max_ms() -> u64 {
+    2_000
+}
+
+fn default_budget_ratio() -> f64 {
+    0.2
+}
+
+fn default_budget_min_per_sec() -> f64 {
+    1.0
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_timeout_ms(),
+            max_timeout_ms: default_max_timeout_ms(),
+            attempt_timeout_ms: default_attempt_timeout_ms(),
+            max_retries: default_max_retries(),
+            backoff_base_ms: default_backoff_base_ms(),
+            backoff_max_ms: default_backoff_max_ms(),
+            budget_ratio: default_budget_ratio(),
+            budget_min_per_sec: default_budget_min_per_sec(),
+        }
+    }
+}
+
+/// Per-adapter circuit breaker. Setting both `consecutive_failures` and `error_rate` to 0
+/// disables it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BreakerConfig {
+    /// Open after this many failures in a row.
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// Open once this fraction of the last `window` calls failed.
+    #[serde(default = "default_error_rate")]
+    pub error_rate: f64,
+    #[serde(default = "default_breaker_window")]
+    pub window: u32,
+    /// How long an open breaker rejects traffic before letting a probe through.
+    #[serde(default = "default_cooldown_ms")]
+    pub cooldown_ms: u64,
+}
+
+fn default_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_error_rate() -> f64 {
+    0.5
+}
+
+fn default_breaker_window() -> u32 {
+    20
+}
+
+fn default_cooldown_ms() -> u64 {
+    30_000
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: default_consecutive_failures(),
+            error_rate: default_error_rate(),
+            window: default_breaker_window(),
+            cooldown_ms: default_cooldown_ms(),
+        }
+    }
+}
+
+/// Which routing policy orders adapters. `pipelines` maps a name (selected per request with
+/// `metadata.pipeline`) to its own policy; everything else uses `policy`.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub policy: RoutingPolicyKind,
+    #[serde(default)]
+    pub pipelines: HashMap<String, RoutingPolicyKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingPolicyKind {
+    /// `default` providers, then `failover` providers, then any other adapter.
+    #[default]
+    Priority,
+    RoundRobin,
+    /// Random order weighted by `providers.<id>.weight`.
+    WeightedRandom,
+    /// Fewest in-flight requests first.
+    LeastOutstanding,
+    /// Only adapters with the capabilities the request needs, in priority order.
+    CapabilityMatch,
+}
+
+/// Adapter lease settings. A `lease_ttl_secs` of 0 disables heartbeat tracking entirely.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HealthConfig {
+    #[serde(default)]
+    pub lease_ttl_secs: u64,
+    #[serde(default = "default_evict_after_secs")]
+    pub evict_after_secs: u64,
+}
+
+fn default_evict_after_secs() -> u64 {
+    120
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self { lease_ttl_secs: 0, evict_after_secs: default_evict_after_secs() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct ObservabilityConfig {
     #[serde(default = "default_metrics_path")]
     pub metrics_path: String,
@@ -28,7 +661,12 @@ fn default_metrics_path() -> String {
     "/metrics".to_string()
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// An adapter declared in config and pre-seeded into the registry at startup.
+///
+/// `kind` selects the transport: `grpc` (`endpoint` is a URL, which may be `unix:///path`),
+/// `unix` (`endpoint` is a socket path) or `in_process` (`endpoint` names a built-in adapter
+/// such as `echo`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AdapterConfig {
     pub id: String,
     pub kind: String,
@@ -37,7 +675,7 @@ pub struct AdapterConfig {
     pub capabilities: AdapterCapabilities,
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct AdapterCapabilities {
     #[serde(default)]
     pub streaming: bool,
@@ -49,7 +687,7 @@ pub struct AdapterCapabilities {
     pub embed_cache: bool,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RequestReplayConfig {
     #[serde(default)]
     pub enabled: bool,
@@ -67,6 +705,17 @@ impl Default for RequestReplayConfig {
     }
 }
 
+impl AdapterCapabilities {
+    pub fn to_proto(&self) -> crate::proto::AdapterCapabilities {
+        crate::proto::AdapterCapabilities {
+            streaming: self.streaming,
+            token_count: self.token_count,
+            model_route: self.model_route,
+            embed_cache: self.embed_cache,
+        }
+    }
+}
+
 impl Config {
     pub fn from_path(path: &str) -> anyhow::Result<Self> {
         let bytes = std::fs::read(path)?;
@@ -74,3 +723,19 @@ impl Config {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_example_config() {
+        let cfg: Config = serde_yaml::from_str(include_str!("../../config/pagi.yaml")).unwrap();
+        let ids: Vec<_> = cfg.providers.0.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["openrouter", "ollama"]);
+        let ollama = cfg.providers.get("ollama").unwrap();
+        assert!(ollama.failover && !ollama.default);
+        assert_eq!(ollama.models_mapping.get("openai/gpt-4o-mini").map(String::as_str), Some("llama3.2:8b"));
+        // The shipped adapters must be able to register without extra setup.
+        assert!(cfg.core.registry.auth.tokens.is_empty());
+    }
+}
@@ -1,13 +1,20 @@
 use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
 
 use anyhow::Context;
+use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
+use tokio::net::UnixListener;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tokio_stream::wrappers::UnixListenerStream;
 use tracing::{error, info};
 
-use pagi_gateway_core::config::Config;
+use pagi_gateway_core::config::{Config, RegistryTlsConfig};
+use pagi_gateway_core::middleware::auth::Authenticator;
+use pagi_gateway_core::middleware::rate_limit::{RateLimits, RemoteAddr};
 use pagi_gateway_core::middleware::observability::Metrics;
-use pagi_gateway_core::protocols::{graphql, rest};
+use pagi_gateway_core::protocols::{admin, anthropic, graphql, grpc, openai, rest, ws};
 use pagi_gateway_core::registry::{AdapterRegistryState, AdapterRegistrySvc};
 
 #[tokio::main]
@@ -23,24 +30,53 @@ async fn main() -> anyhow::Result<()> {
     info!(%config_path, "loaded config");
 
     let metrics = Metrics::new();
-    let registry_state = AdapterRegistryState::new(cfg.core.request_replay.clone());
+    let registry_state = AdapterRegistryState::new(
+        cfg.core.request_replay.clone(),
+        cfg.core.registry.clone(),
+        cfg.providers.clone(),
+        metrics.clone(),
+    );
+    for adapter in &cfg.adapters {
+        registry_state
+            .register_static(adapter)
+            .await
+            .with_context(|| format!("loading static adapter {:?}", adapter.id))?;
+        info!(adapter_id=%adapter.id, kind=%adapter.kind, endpoint=%adapter.endpoint, "static adapter loaded");
+    }
+    registry_state.spawn_health_sweeper();
     let registry_state_for_http = registry_state.clone();
 
     let http_addr: SocketAddr = cfg.core.bind_http.parse().context("invalid core.bind_http")?;
     let grpc_addr: SocketAddr = cfg.core.bind_grpc.parse().context("invalid core.bind_grpc")?;
 
-    let graphql_schema = graphql::build_schema(registry_state.clone());
+    let auth = Authenticator::new(&cfg.core.auth).context("invalid core.auth")?;
+    auth.preload().await.context("loading core.auth signing keys")?;
+    if auth.enabled() {
+        info!(jwt = cfg.core.auth.jwt.is_some(), api_keys = cfg.core.auth.api_keys.is_some(), "client authentication enabled");
+    }
+
+    let limits = RateLimits::new(&cfg.core.rate_limit).context("invalid core.rate_limit")?;
+    limits.spawn_housekeeping();
+
+    let graphql_schema = graphql::build_schema(&cfg, registry_state.clone(), metrics.clone(), auth.clone(), limits.clone());
+    let gateway_svc = grpc::GatewaySvc::new(registry_state.clone(), metrics.clone(), auth.clone(), limits.clone());
 
-    let make_svc = make_service_fn(move |_conn| {
+    let make_svc = make_service_fn(move |conn: &AddrStream| {
+        let remote = RemoteAddr(conn.remote_addr());
         let registry_state = registry_state_for_http.clone();
         let metrics = metrics.clone();
         let graphql_schema = graphql_schema.clone();
+        let auth = auth.clone();
+        let limits = limits.clone();
         async move {
-            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+            Ok::<_, hyper::Error>(service_fn(move |mut req: Request<Body>| {
+                req.extensions_mut().insert(remote);
                 let registry_state = registry_state.clone();
                 let metrics = metrics.clone();
                 let graphql_schema = graphql_schema.clone();
-                async move { handle_http(req, registry_state, metrics, graphql_schema).await }
+                let auth = auth.clone();
+                let limits = limits.clone();
+                async move { handle_http(req, registry_state, metrics, auth, limits, graphql_schema).await }
             }))
         }
     });
@@ -48,11 +84,37 @@ async fn main() -> anyhow::Result<()> {
     let http_server = Server::bind(&http_addr).serve(make_svc);
     info!(%http_addr, "http listening");
 
-    let grpc_server = tonic::transport::Server::builder()
+    let mut grpc_builder = tonic::transport::Server::builder();
+    if let Some(tls) = &cfg.core.registry.auth.tls {
+        grpc_builder = grpc_builder.tls_config(registry_tls(tls).context("loading core.registry.auth.tls")?)?;
+        info!(require_san_match = tls.require_san_match, "grpc registry requires client certificates; gateway service does not");
+    }
+    let grpc_server = grpc_builder
         .add_service(AdapterRegistrySvc::new(registry_state.clone()))
+        .add_service(gateway_svc)
         .serve(grpc_addr);
     info!(%grpc_addr, "grpc listening");
 
+    let uds_incoming = match &cfg.core.bind_grpc_uds {
+        Some(path) => {
+            let incoming = bind_uds(path).with_context(|| format!("binding core.bind_grpc_uds {path:?}"))?;
+            info!(%path, "grpc listening on unix socket");
+            Some(incoming)
+        }
+        None => None,
+    };
+    let grpc_uds_server = async {
+        match uds_incoming {
+            Some(incoming) => {
+                tonic::transport::Server::builder()
+                    .add_service(AdapterRegistrySvc::new(registry_state.clone()))
+                    .serve_with_incoming(incoming)
+                    .await
+            }
+            None => std::future::pending().await,
+        }
+    };
+
     tokio::select! {
         r = http_server => {
             if let Err(e) = r { error!(error=%e, "http server error"); }
@@ -60,6 +122,9 @@ async fn main() -> anyhow::Result<()> {
         r = grpc_server => {
             if let Err(e) = r { error!(error=%e, "grpc server error"); }
         }
+        r = grpc_uds_server => {
+            if let Err(e) = r { error!(error=%e, "grpc unix socket server error"); }
+        }
         _ = tokio::signal::ctrl_c() => {
             info!("shutdown requested");
         }
@@ -72,13 +137,21 @@ async fn handle_http(
     req: Request<Body>,
     registry: AdapterRegistryState,
     metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
     graphql_schema: graphql::SchemaType,
 ) -> Result<Response<Body>, hyper::Error> {
     match (req.method().as_str(), req.uri().path()) {
         ("GET", "/healthz") => Ok(Response::new(Body::from("ok"))),
         ("GET", "/metrics") => Ok(metrics.render()),
-        ("POST", "/v1/ai:call") | ("POST", "/api/call") => rest::handle_call(req, registry, metrics).await,
-        ("GET", "/graphql") | ("POST", "/graphql") => graphql::handle(req, graphql_schema).await,
+        ("POST", "/v1/ai:call") | ("POST", "/api/call") => rest::handle_call(req, registry, metrics, auth, limits).await,
+        ("POST", "/v1/chat/completions") => openai::handle_chat_completions(req, registry, metrics, auth, limits).await,
+        ("POST", "/v1/messages") => anthropic::handle_messages(req, registry, metrics, auth, limits).await,
+        ("GET", ws::PATH) => ws::handle(req, registry, metrics, auth, limits).await,
+        ("GET", "/graphql") | ("POST", "/graphql") => graphql::handle(req, graphql_schema, metrics, auth, limits).await,
+        (_, path) if path == admin::API_KEYS_PATH || path.starts_with("/admin/api-keys/") => {
+            admin::handle_api_keys(req, auth).await
+        }
         _ => {
             let mut r = Response::new(Body::from("not found"));
             *r.status_mut() = hyper::StatusCode::NOT_FOUND;
@@ -87,6 +160,30 @@ async fn handle_http(
     }
 }
 
+/// Server identity plus the CA that adapter client certificates must chain to.
+fn registry_tls(tls: &RegistryTlsConfig) -> anyhow::Result<ServerTlsConfig> {
+    let cert = std::fs::read(&tls.cert_path).with_context(|| format!("reading {}", tls.cert_path))?;
+    let key = std::fs::read(&tls.key_path).with_context(|| format!("reading {}", tls.key_path))?;
+    let ca = std::fs::read(&tls.client_ca_path).with_context(|| format!("reading {}", tls.client_ca_path))?;
+    // Optional at the TLS layer so GatewayService clients need no certificate; the registry
+    // RPCs refuse callers without one (see `registry::auth`).
+    Ok(ServerTlsConfig::new()
+        .identity(Identity::from_pem(cert, key))
+        .client_ca_root(Certificate::from_pem(ca))
+        .client_auth_optional(true))
+}
+
+/// Bind a Unix socket listener, replacing a stale socket left by a previous run.
+fn bind_uds(path: &str) -> anyhow::Result<UnixListenerStream> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            anyhow::bail!("{path} exists and is not a socket");
+        }
+        std::fs::remove_file(path)?;
+    }
+    Ok(UnixListenerStream::new(UnixListener::bind(path)?))
+}
+
 fn parse_config_path_from_args() -> Option<String> {
     let mut args = std::env::args().skip(1);
     while let Some(a) = args.next() {
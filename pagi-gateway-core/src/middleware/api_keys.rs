@@ -0,0 +1,275 @@
+//! Gateway-issued API keys.
+//!
+//! A key looks like `pagi_<id>_<secret>`. Only its SHA-256 hash is stored; the secret is 32
+//! random bytes, so a fast hash is enough (there is nothing to brute-force). Keys live in a JSON
+//! file that is rewritten atomically on every change, and carry scopes limiting which agents,
+//! models and adapters they may use.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::registry::Priority;
+
+pub const KEY_PREFIX: &str = "pagi_";
+
+/// What a key may do. Empty lists allow anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyScopes {
+    #[serde(default)]
+    pub agent_ids: Vec<String>,
+    /// Requests must name one of these as `preferred_model`.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Routing only considers these adapters.
+    #[serde(default)]
+    pub adapters: Vec<String>,
+}
+
+impl KeyScopes {
+    /// Why `req` is outside these scopes, if it is.
+    pub fn check(&self, req: &CanonicalAIRequest) -> Result<(), String> {
+        if !self.agent_ids.is_empty() && !req.agent_id.as_ref().is_some_and(|a| self.agent_ids.contains(a)) {
+            return Err("agent_id not allowed for this key".to_string());
+        }
+        if !self.models.is_empty() && !req.preferred_model.as_ref().is_some_and(|m| self.models.contains(m)) {
+            return Err("model not allowed for this key".to_string());
+        }
+        if let Some(pinned) = req.metadata.get("adapter_id") {
+            if !self.adapters.is_empty() && !self.adapters.contains(pinned) {
+                return Err("adapter not allowed for this key".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key as shown by the admin API; never includes the secret or its hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// Becomes `metadata.user_id`; defaults to `key:<id>`.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub scopes: KeyScopes,
+    /// Queue class for this key's requests; overrides `metadata.priority`.
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    pub created_at: u64,
+    /// Unix seconds.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub revoked_at: Option<u64>,
+}
+
+impl ApiKey {
+    pub fn active(&self, now: u64) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|t| now < t)
+    }
+}
+
+/// Body of `POST /admin/api-keys`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NewApiKey {
+    pub name: String,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub scopes: KeyScopes,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredKey {
+    #[serde(flatten)]
+    key: ApiKey,
+    /// Hex SHA-256 of the full key string.
+    hash: String,
+}
+
+pub struct ApiKeyStore {
+    path: PathBuf,
+    keys: RwLock<HashMap<String, StoredKey>>,
+    rng: SystemRandom,
+}
+
+impl ApiKeyStore {
+    /// Open the store at `path`; a missing file is an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let keys: Vec<StoredKey> = match std::fs::read(&path) {
+            Ok(raw) => serde_json::from_slice(&raw).with_context(|| format!("parsing {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let keys = keys.into_iter().map(|k| (k.key.id.clone(), k)).collect();
+        Ok(Self { path, keys: RwLock::new(keys), rng: SystemRandom::new() })
+    }
+
+    /// The active key matching `presented`, if any.
+    pub fn verify(&self, presented: &str) -> Option<ApiKey> {
+        let (id, _) = presented.strip_prefix(KEY_PREFIX)?.split_once('_')?;
+        let keys = self.keys.read().unwrap();
+        let stored = keys.get(id)?;
+        let matches: bool = hash(presented).as_bytes().ct_eq(stored.hash.as_bytes()).into();
+        (matches && stored.key.active(now())).then(|| stored.key.clone())
+    }
+
+    /// Create a key, returning its record and the plaintext key (shown only this once).
+    pub fn create(&self, new: NewApiKey) -> anyhow::Result<(ApiKey, String)> {
+        let id = self.random_hex(6)?;
+        let secret = self.random_hex(32)?;
+        let plaintext = format!("{KEY_PREFIX}{id}_{secret}");
+        let key = ApiKey {
+            id: id.clone(),
+            name: new.name,
+            user_id: new.user_id,
+            tenant: new.tenant,
+            scopes: new.scopes,
+            priority: new.priority,
+            created_at: now(),
+            expires_at: new.expires_at,
+            revoked_at: None,
+        };
+        let mut keys = self.keys.write().unwrap();
+        let mut next = keys.clone();
+        next.insert(id, StoredKey { key: key.clone(), hash: hash(&plaintext) });
+        self.persist(&next)?;
+        *keys = next;
+        Ok((key, plaintext))
+    }
+
+    pub fn list(&self) -> Vec<ApiKey> {
+        let mut out: Vec<ApiKey> = self.keys.read().unwrap().values().map(|k| k.key.clone()).collect();
+        out.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        out
+    }
+
+    /// Revoke key `id`. Revoked keys stay listed. Returns false if there is no such key.
+    /// Like [`create`](Self::create), nothing changes unless the file was written.
+    pub fn revoke(&self, id: &str) -> anyhow::Result<bool> {
+        let mut keys = self.keys.write().unwrap();
+        let mut next = keys.clone();
+        let Some(stored) = next.get_mut(id) else {
+            return Ok(false);
+        };
+        stored.key.revoked_at.get_or_insert_with(now);
+        self.persist(&next)?;
+        *keys = next;
+        Ok(true)
+    }
+
+    /// Write `keys` to the store file; callers swap them in only once this succeeded.
+    fn persist(&self, keys: &HashMap<String, StoredKey>) -> anyhow::Result<()> {
+        let mut list: Vec<&StoredKey> = keys.values().collect();
+        list.sort_by(|a, b| a.key.id.cmp(&b.key.id));
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&list)?).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path).with_context(|| format!("replacing {}", self.path.display()))
+    }
+
+    fn random_hex(&self, bytes: usize) -> anyhow::Result<String> {
+        let mut buf = vec![0u8; bytes];
+        self.rng.fill(&mut buf).map_err(|_| anyhow::anyhow!("system random source failed"))?;
+        Ok(to_hex(&buf))
+    }
+}
+
+/// Whether a bearer credential is an API key rather than a JWT.
+pub fn is_api_key(credential: &str) -> bool {
+    credential.starts_with(KEY_PREFIX)
+}
+
+fn hash(key: &str) -> String {
+    to_hex(digest(&SHA256, key.as_bytes()).as_ref())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> (PathBuf, ApiKeyStore) {
+        let path = std::env::temp_dir().join(format!("pagi-keys-{}-{name}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = ApiKeyStore::open(&path).unwrap();
+        (path, store)
+    }
+
+    #[test]
+    fn keys_survive_reopen_and_revocation_sticks() {
+        let (path, store) = temp_store("lifecycle");
+        let (key, plaintext) = store.create(NewApiKey { name: "ci".to_string(), ..Default::default() }).unwrap();
+        assert!(is_api_key(&plaintext));
+        assert_eq!(store.verify(&plaintext).unwrap().id, key.id);
+        assert!(store.verify(&format!("{plaintext}x")).is_none());
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains(plaintext.rsplit('_').next().unwrap()), "secret must not be stored");
+
+        let reopened = ApiKeyStore::open(&path).unwrap();
+        assert!(reopened.verify(&plaintext).is_some());
+        assert!(reopened.revoke(&key.id).unwrap());
+        assert!(reopened.verify(&plaintext).is_none());
+        assert!(ApiKeyStore::open(&path).unwrap().verify(&plaintext).is_none());
+    }
+
+    #[test]
+    fn failed_writes_change_nothing() {
+        let (path, store) = temp_store("unwritable");
+        let (key, plaintext) = store.create(NewApiKey { name: "ci".to_string(), ..Default::default() }).unwrap();
+
+        // A directory in the way of the temp file makes every write fail.
+        std::fs::create_dir_all(path.with_extension("tmp")).unwrap();
+        assert!(store.create(NewApiKey { name: "lost".to_string(), ..Default::default() }).is_err());
+        assert!(store.revoke(&key.id).is_err());
+        assert_eq!(store.list().len(), 1);
+        assert!(store.verify(&plaintext).is_some(), "a revocation that was not saved must not apply");
+        std::fs::remove_dir(path.with_extension("tmp")).unwrap();
+    }
+
+    #[test]
+    fn expired_keys_are_rejected() {
+        let (_, store) = temp_store("expiry");
+        let new = NewApiKey { name: "old".to_string(), expires_at: Some(now() - 1), ..Default::default() };
+        let (_, plaintext) = store.create(new).unwrap();
+        assert!(store.verify(&plaintext).is_none());
+    }
+
+    #[test]
+    fn scopes_limit_agents_and_models() {
+        let scopes = KeyScopes { agent_ids: vec!["support".to_string()], models: vec!["gpt-4o".to_string()], ..Default::default() };
+        let mut req = CanonicalAIRequest::new();
+        req.agent_id = Some("support".to_string());
+        assert!(scopes.check(&req).is_err());
+        req.preferred_model = Some("gpt-4o".to_string());
+        assert!(scopes.check(&req).is_ok());
+        req.agent_id = Some("billing".to_string());
+        assert!(scopes.check(&req).is_err());
+    }
+}
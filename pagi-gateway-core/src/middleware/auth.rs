@@ -1,9 +1,281 @@
-use hyper::Request;
+//! Client authentication for the HTTP ingresses.
+//!
+//! Callers present either a JWT (`Authorization: Bearer <jwt>`) or a gateway API key
+//! (`Authorization: Bearer pagi_...` or `x-api-key`). Every ingress calls
+//! [`Authenticator::authenticate`] before doing any work, then [`Authenticator::apply`] on the
+//! canonical request it built. Verified identity reaches adapters as `metadata.user_id` and
+//! `metadata.tenant`; while auth is on, clients cannot set those keys themselves.
 
-/// JWT auth placeholder.
-///
-/// For MVP this accepts missing/invalid tokens; hook your validator here.
-pub fn authorize<B>(_req: &Request<B>) -> bool {
-    true
+use std::sync::Arc;
+
+use anyhow::Context;
+use hyper::{HeaderMap, StatusCode};
+use serde_json::{Map, Value};
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::AuthConfig;
+use crate::registry::ForwardOptions;
+
+use super::api_keys::{is_api_key, ApiKey, ApiKeyStore};
+use super::jwt::JwtValidator;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+    #[error("bearer token required")]
+    MissingToken,
+    #[error("invalid token: {0}")]
+    InvalidToken(String),
+    #[error("token signed by an unknown key")]
+    UnknownKey,
+    #[error("signing keys unavailable")]
+    KeysUnavailable,
+    #[error("invalid or expired api key")]
+    InvalidApiKey,
+    #[error("{0}")]
+    Forbidden(String),
+}
+
+impl AuthError {
+    /// 403 for a valid caller acting outside its scopes, 503 when we could not check the token
+    /// at all, otherwise 401.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::KeysUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// [`status`](Self::status) as a metrics label.
+    pub fn status_label(&self) -> &'static str {
+        match self {
+            AuthError::Forbidden(_) => "403",
+            AuthError::KeysUnavailable => "503",
+            _ => "401",
+        }
+    }
+}
+
+/// The verified caller of one request.
+#[derive(Debug, Clone, Default)]
+pub struct Principal {
+    pub user_id: String,
+    pub tenant: Option<String>,
+    /// Every claim of the token, for ingresses that need more than the id. Empty for API keys.
+    pub claims: Map<String, Value>,
+    /// Set when the caller used an API key.
+    pub api_key: Option<ApiKey>,
+}
+
+/// Shared by all ingresses; cheap to clone. The default authenticates nobody and lets every
+/// request through anonymously.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    jwt: Option<Arc<JwtValidator>>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+    admin_token: Option<Arc<[u8]>>,
+    required: bool,
+}
+
+impl Authenticator {
+    pub fn new(cfg: &AuthConfig) -> anyhow::Result<Self> {
+        let jwt = cfg.jwt.as_ref().map(JwtValidator::new).transpose()?.map(Arc::new);
+        let api_keys = cfg
+            .api_keys
+            .as_ref()
+            .map(|k| ApiKeyStore::open(&k.store_path).context("opening api key store"))
+            .transpose()?
+            .map(Arc::new);
+        let admin_token = cfg.api_keys.as_ref().and_then(|k| k.admin_token.as_ref()).and_then(|t| {
+            let token = t.resolve();
+            if token.is_none() {
+                warn!("no admin token configured; api key admin endpoints are disabled");
+            }
+            token.map(|t| Arc::from(t.into_bytes()))
+        });
+        Ok(Self { jwt, api_keys, admin_token, required: cfg.required })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.jwt.is_some() || self.api_keys.is_some()
+    }
+
+    pub fn api_keys(&self) -> Option<&ApiKeyStore> {
+        self.api_keys.as_deref()
+    }
+
+    /// Whether the request carries the API key admin token.
+    pub fn is_admin(&self, headers: &HeaderMap) -> bool {
+        match (&self.admin_token, bearer(headers)) {
+            (Some(expected), Some(presented)) => expected.ct_eq(presented.as_bytes()).into(),
+            _ => false,
+        }
+    }
+
+    /// Load signing keys ahead of the first request.
+    pub async fn preload(&self) -> anyhow::Result<()> {
+        match &self.jwt {
+            Some(jwt) => jwt.preload().await,
+            None => Ok(()),
+        }
+    }
+
+    /// The caller identified by the request's credentials, or `None` for an anonymous request
+    /// that is allowed through.
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Principal>, AuthError> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+        let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok()).map(str::trim);
+        let Some(credential) = api_key.or_else(|| bearer(headers)) else {
+            return if self.required { Err(AuthError::MissingToken) } else { Ok(None) };
+        };
+
+        if api_key.is_some() || is_api_key(credential) {
+            let store = self.api_keys.as_ref().ok_or(AuthError::InvalidApiKey)?;
+            let key = store.verify(credential).ok_or(AuthError::InvalidApiKey)?;
+            return Ok(Some(Principal {
+                user_id: key.user_id.clone().unwrap_or_else(|| format!("key:{}", key.id)),
+                tenant: key.tenant.clone(),
+                claims: Map::new(),
+                api_key: Some(key),
+            }));
+        }
+
+        let Some(jwt) = &self.jwt else {
+            return Err(AuthError::InvalidToken("only api keys are accepted".to_string()));
+        };
+        let claims = jwt.verify(credential).await?;
+
+        let cfg = jwt.config();
+        let user_id = claim(&claims, &cfg.user_claim)
+            .ok_or_else(|| AuthError::InvalidToken(format!("missing {} claim", cfg.user_claim)))?;
+        let tenant = claim(&claims, &cfg.tenant_claim);
+        Ok(Some(Principal { user_id, tenant, claims, api_key: None }))
+    }
+
+    /// Stamp the caller's identity onto `req`, replacing anything the client put there, and
+    /// hold the request to the caller's API key scopes.
+    pub fn apply(
+        &self,
+        principal: Option<&Principal>,
+        req: &mut CanonicalAIRequest,
+        opts: &mut ForwardOptions,
+    ) -> Result<(), AuthError> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        req.metadata.remove("user_id");
+        req.metadata.remove("tenant");
+        if let Some(p) = principal {
+            req.metadata.insert("user_id".to_string(), p.user_id.clone());
+            if let Some(t) = &p.tenant {
+                req.metadata.insert("tenant".to_string(), t.clone());
+            }
+            if let Some(key) = &p.api_key {
+                key.scopes.check(req).map_err(AuthError::Forbidden)?;
+                if let Some(priority) = key.priority {
+                    req.metadata.insert("priority".to_string(), priority.as_str().to_string());
+                }
+                if !key.scopes.adapters.is_empty() {
+                    opts.adapters = Some(key.scopes.adapters.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let v = headers.get(hyper::header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = v.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim()).filter(|t| !t.is_empty())
 }
 
+/// A string (or numeric) claim.
+fn claim(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    match claims.get(name)? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiKeysConfig, JwtConfig};
+    use crate::middleware::api_keys::{KeyScopes, NewApiKey};
+    use crate::middleware::jwt::tests::{now, token, write_jwks};
+
+    fn authenticator(required: bool) -> Authenticator {
+        let jwt = JwtConfig { jwks_path: Some(write_jwks("auth", &[("k1", "s3cret")])), ..Default::default() };
+        Authenticator::new(&AuthConfig { jwt: Some(jwt), required, ..Default::default() }).unwrap()
+    }
+
+    fn headers(auth: Option<String>) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        if let Some(a) = auth {
+            h.insert(hyper::header::AUTHORIZATION, a.parse().unwrap());
+        }
+        h
+    }
+
+    #[tokio::test]
+    async fn verified_claims_replace_client_metadata() {
+        let auth = authenticator(true);
+        assert_eq!(auth.authenticate(&headers(None)).await.unwrap_err(), AuthError::MissingToken);
+
+        let jwt = token("k1", "s3cret", serde_json::json!({ "sub": "u1", "tenant": "acme", "exp": now() + 60 }));
+        let principal = auth.authenticate(&headers(Some(format!("Bearer {jwt}")))).await.unwrap().unwrap();
+        assert_eq!(principal.user_id, "u1");
+
+        let mut req = CanonicalAIRequest::new();
+        req.metadata.insert("user_id".to_string(), "spoofed".to_string());
+        req.metadata.insert("tenant".to_string(), "spoofed".to_string());
+        auth.apply(Some(&principal), &mut req, &mut ForwardOptions::default()).unwrap();
+        assert_eq!(req.metadata["user_id"], "u1");
+        assert_eq!(req.metadata["tenant"], "acme");
+    }
+
+    #[tokio::test]
+    async fn optional_auth_still_rejects_bad_tokens() {
+        let auth = authenticator(false);
+        assert!(auth.authenticate(&headers(None)).await.unwrap().is_none());
+        let err = auth.authenticate(&headers(Some("Bearer not-a-jwt".to_string()))).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_scoped() {
+        let path = std::env::temp_dir().join(format!("pagi-keys-{}-auth.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let cfg = AuthConfig {
+            api_keys: Some(ApiKeysConfig { store_path: path.to_string_lossy().into_owned(), admin_token: None }),
+            ..Default::default()
+        };
+        let auth = Authenticator::new(&cfg).unwrap();
+        let scopes = KeyScopes { adapters: vec!["ollama".to_string()], ..Default::default() };
+        let new = NewApiKey { name: "batch".to_string(), tenant: Some("acme".to_string()), scopes, ..Default::default() };
+        let (key, plaintext) = auth.api_keys().unwrap().create(new).unwrap();
+
+        let mut h = HeaderMap::new();
+        h.insert("x-api-key", plaintext.parse().unwrap());
+        let principal = auth.authenticate(&h).await.unwrap().unwrap();
+        assert_eq!(principal.user_id, format!("key:{}", key.id));
+
+        let mut req = CanonicalAIRequest::new();
+        let mut opts = ForwardOptions::default();
+        auth.apply(Some(&principal), &mut req, &mut opts).unwrap();
+        assert_eq!(req.metadata["tenant"], "acme");
+        assert_eq!(opts.adapters, Some(vec!["ollama".to_string()]));
+
+        req.metadata.insert("adapter_id".to_string(), "openrouter".to_string());
+        let err = auth.apply(Some(&principal), &mut req, &mut opts).unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+
+        assert_eq!(auth.authenticate(&headers(Some("Bearer pagi_nope_nope".to_string()))).await.unwrap_err(), AuthError::InvalidApiKey);
+    }
+}
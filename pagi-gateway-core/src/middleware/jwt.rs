@@ -0,0 +1,297 @@
+//! JWT verification against a JWKS.
+//!
+//! The key set is loaded from `jwks_path` or `jwks_url` and cached for `refresh_secs`. A token
+//! whose `kid` is not in the cached set forces an early reload (at most once per
+//! [`MIN_REFRESH`]), so keys rotated at the issuer are picked up without a restart. If a reload
+//! fails the previous keys stay in use.
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+use hyper_rustls::HttpsConnector;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::config::JwtConfig;
+
+use super::auth::AuthError;
+
+/// Unknown `kid`s trigger a reload no more often than this.
+const MIN_REFRESH: Duration = Duration::from_secs(10);
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum JwksSource {
+    File(String),
+    Url(hyper::Uri, Box<Client<HttpsConnector<HttpConnector>, Body>>),
+}
+
+impl JwksSource {
+    async fn load(&self) -> anyhow::Result<JwkSet> {
+        let raw = match self {
+            JwksSource::File(path) => tokio::fs::read(path).await.with_context(|| format!("reading {path}"))?,
+            JwksSource::Url(uri, client) => {
+                let resp = tokio::time::timeout(FETCH_TIMEOUT, client.get(uri.clone()))
+                    .await
+                    .context("timed out fetching JWKS")??;
+                anyhow::ensure!(resp.status().is_success(), "JWKS fetch returned {}", resp.status());
+                hyper::body::to_bytes(resp.into_body()).await?.to_vec()
+            }
+        };
+        serde_json::from_slice(&raw).context("parsing JWKS")
+    }
+}
+
+struct Cached {
+    keys: Arc<JwkSet>,
+    loaded_at: Instant,
+}
+
+pub(crate) struct JwtValidator {
+    cfg: JwtConfig,
+    source: JwksSource,
+    ttl: Duration,
+    min_refresh: Duration,
+    cache: RwLock<Option<Cached>>,
+    /// Serializes reloads so a burst of unknown `kid`s causes one fetch.
+    reload: tokio::sync::Mutex<()>,
+}
+
+impl JwtValidator {
+    pub(crate) fn new(cfg: &JwtConfig) -> anyhow::Result<Self> {
+        let source = match (&cfg.jwks_path, &cfg.jwks_url) {
+            (Some(path), None) => JwksSource::File(path.clone()),
+            (None, Some(url)) => {
+                let https = hyper_rustls::HttpsConnectorBuilder::new()
+                    .with_webpki_roots()
+                    .https_or_http()
+                    .enable_http1()
+                    .build();
+                JwksSource::Url(url.parse().context("invalid jwks_url")?, Box::new(Client::builder().build(https)))
+            }
+            _ => anyhow::bail!("exactly one of jwks_path and jwks_url must be set"),
+        };
+        anyhow::ensure!(!cfg.algorithms.is_empty(), "no JWT algorithms allowed");
+        Ok(Self {
+            cfg: cfg.clone(),
+            source,
+            ttl: Duration::from_secs(cfg.refresh_secs),
+            min_refresh: MIN_REFRESH,
+            cache: RwLock::new(None),
+            reload: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(crate) fn config(&self) -> &JwtConfig {
+        &self.cfg
+    }
+
+    /// Load the key set now, so a misconfigured source fails at startup.
+    pub(crate) async fn preload(&self) -> anyhow::Result<()> {
+        let keys = self.source.load().await?;
+        info!(keys = keys.keys.len(), "loaded JWKS");
+        self.store(keys);
+        Ok(())
+    }
+
+    /// Verify `token` and return its claims.
+    pub(crate) async fn verify(&self, token: &str) -> Result<Map<String, Value>, AuthError> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        if !self.cfg.algorithms.contains(&header.alg) {
+            return Err(AuthError::InvalidToken(format!("algorithm {:?} not allowed", header.alg)));
+        }
+        let keys = self.keys(header.kid.as_deref()).await?;
+        let candidates: Vec<&Jwk> = match header.kid.as_deref() {
+            Some(kid) => keys.find(kid).into_iter().collect(),
+            None => keys.keys.iter().collect(),
+        };
+        let candidates: Vec<&Jwk> = candidates.into_iter().filter(|k| usable(k, header.alg)).collect();
+        if candidates.is_empty() {
+            return Err(AuthError::UnknownKey);
+        }
+
+        let validation = self.validation(header.alg);
+        let mut last = AuthError::UnknownKey;
+        for jwk in candidates {
+            let key = DecodingKey::from_jwk(jwk).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+            match jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                // Without a `kid` several keys may fit; only a signature mismatch means "try the next".
+                Err(e) if matches!(e.kind(), ErrorKind::InvalidSignature) => last = AuthError::InvalidToken(e.to_string()),
+                Err(e) => return Err(AuthError::InvalidToken(e.to_string())),
+            }
+        }
+        Err(last)
+    }
+
+    fn validation(&self, alg: Algorithm) -> Validation {
+        let mut v = Validation::new(alg);
+        v.leeway = self.cfg.leeway_secs;
+        v.validate_nbf = true;
+        v.set_required_spec_claims(&["exp"]);
+        if let Some(iss) = &self.cfg.issuer {
+            v.set_issuer(&[iss]);
+        }
+        if self.cfg.audience.is_empty() {
+            v.validate_aud = false;
+        } else {
+            v.set_audience(&self.cfg.audience);
+        }
+        v
+    }
+
+    /// The cached key set, reloaded first if it is stale or lacks `kid`.
+    async fn keys(&self, kid: Option<&str>) -> Result<Arc<JwkSet>, AuthError> {
+        let now = Instant::now();
+        let cached = self.cached();
+        if let Some((keys, loaded_at)) = &cached {
+            let age = now.saturating_duration_since(*loaded_at);
+            let has_kid = kid.is_none_or(|k| keys.find(k).is_some());
+            if age < self.ttl && (has_kid || age < self.min_refresh) {
+                return Ok(keys.clone());
+            }
+        }
+
+        let _guard = self.reload.lock().await;
+        // Another request may have reloaded while we waited.
+        if let Some((keys, loaded_at)) = self.cached() {
+            if cached.as_ref().is_none_or(|(_, t)| loaded_at > *t) {
+                return Ok(keys);
+            }
+        }
+        match self.source.load().await {
+            Ok(keys) => {
+                info!(keys = keys.keys.len(), "reloaded JWKS");
+                Ok(self.store(keys))
+            }
+            Err(e) => {
+                warn!(error=%e, "JWKS reload failed");
+                match cached {
+                    Some((keys, _)) => Ok(keys),
+                    None => Err(AuthError::KeysUnavailable),
+                }
+            }
+        }
+    }
+
+    fn cached(&self) -> Option<(Arc<JwkSet>, Instant)> {
+        self.cache.read().unwrap().as_ref().map(|c| (c.keys.clone(), c.loaded_at))
+    }
+
+    fn store(&self, keys: JwkSet) -> Arc<JwkSet> {
+        let keys = Arc::new(keys);
+        *self.cache.write().unwrap() = Some(Cached { keys: keys.clone(), loaded_at: Instant::now() });
+        keys
+    }
+}
+
+/// Whether `jwk` can verify a token signed with `alg`.
+fn usable(jwk: &Jwk, alg: Algorithm) -> bool {
+    if let Some(key_alg) = jwk.common.key_algorithm {
+        if key_alg.to_string() != format!("{alg:?}") {
+            return false;
+        }
+    }
+    matches!(
+        (&jwk.algorithm, alg),
+        (AlgorithmParameters::OctetKey(_), Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+            | (
+                AlgorithmParameters::RSA(_),
+                Algorithm::RS256
+                    | Algorithm::RS384
+                    | Algorithm::RS512
+                    | Algorithm::PS256
+                    | Algorithm::PS384
+                    | Algorithm::PS512
+            )
+            | (AlgorithmParameters::EllipticCurve(_), Algorithm::ES256 | Algorithm::ES384)
+            | (AlgorithmParameters::OctetKeyPair(_), Algorithm::EdDSA)
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Write a JWKS holding one HS256 key per `(kid, secret)` and return its path.
+    pub(crate) fn write_jwks(name: &str, keys: &[(&str, &str)]) -> String {
+        use base64::Engine;
+        let keys: Vec<Value> = keys
+            .iter()
+            .map(|(kid, secret)| {
+                serde_json::json!({
+                    "kty": "oct",
+                    "kid": kid,
+                    "alg": "HS256",
+                    "k": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret),
+                })
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("pagi-jwks-{}-{name}.json", std::process::id()));
+        std::fs::write(&path, serde_json::json!({ "keys": keys }).to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    pub(crate) fn token(kid: &str, secret: &str, claims: Value) -> String {
+        let header = Header { kid: Some(kid.to_string()), ..Header::new(Algorithm::HS256) };
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    pub(crate) fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn validator(path: String) -> JwtValidator {
+        let cfg = JwtConfig {
+            jwks_path: Some(path),
+            issuer: Some("https://issuer.test".to_string()),
+            audience: vec!["pagi".to_string()],
+            leeway_secs: 0,
+            ..Default::default()
+        };
+        JwtValidator::new(&cfg).unwrap()
+    }
+
+    #[tokio::test]
+    async fn checks_signature_and_registered_claims() {
+        let v = validator(write_jwks("claims", &[("k1", "secret-one")]));
+        let good = serde_json::json!({ "sub": "u1", "iss": "https://issuer.test", "aud": "pagi", "exp": now() + 60 });
+        let claims = v.verify(&token("k1", "secret-one", good.clone())).await.unwrap();
+        assert_eq!(claims["sub"], "u1");
+
+        assert!(v.verify(&token("k1", "wrong", good.clone())).await.is_err());
+        let mut expired = good.clone();
+        expired["exp"] = (now() - 10).into();
+        assert!(v.verify(&token("k1", "secret-one", expired)).await.is_err());
+        let mut early = good.clone();
+        early["nbf"] = (now() + 60).into();
+        assert!(v.verify(&token("k1", "secret-one", early)).await.is_err());
+        let mut other_aud = good.clone();
+        other_aud["aud"] = "someone-else".into();
+        assert!(v.verify(&token("k1", "secret-one", other_aud)).await.is_err());
+        let mut other_iss = good;
+        other_iss["iss"] = "https://evil.test".into();
+        assert!(v.verify(&token("k1", "secret-one", other_iss)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_kid_reloads_rotated_keys() {
+        let path = write_jwks("rotate", &[("k1", "secret-one")]);
+        let mut v = validator(path.clone());
+        v.min_refresh = Duration::ZERO;
+        let claims = serde_json::json!({ "sub": "u1", "iss": "https://issuer.test", "aud": "pagi", "exp": now() + 60 });
+        v.verify(&token("k1", "secret-one", claims.clone())).await.unwrap();
+        assert!(matches!(v.verify(&token("k2", "secret-two", claims.clone())).await, Err(AuthError::UnknownKey)));
+
+        write_jwks("rotate", &[("k2", "secret-two")]);
+        v.verify(&token("k2", "secret-two", claims)).await.unwrap();
+    }
+}
@@ -1,4 +1,7 @@
+pub mod api_keys;
 pub mod auth;
+pub mod jwt;
 pub mod observability;
 pub mod rate_limit;
+pub mod token_limit;
 
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use hyper::{Body, Response};
-use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, Registry, TextEncoder};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Registry, TextEncoder};
+
+use crate::canonical::Usage;
+use crate::registry::breaker::BreakerState;
 
 #[derive(Clone)]
 pub struct Metrics {
@@ -12,6 +15,25 @@ struct Inner {
     registry: Registry,
     pub requests_total: IntCounterVec,
     pub request_latency: HistogramVec,
+    pub tokens_total: IntCounterVec,
+    pub adapter_healthy: IntGaugeVec,
+    pub adapter_evictions: IntCounterVec,
+    pub adapter_breaker: IntGaugeVec,
+    pub retries_total: IntCounterVec,
+    pub adapter_attempts: IntCounterVec,
+    pub hedges_total: IntCounterVec,
+    pub hedge_wins: IntCounterVec,
+    pub registration_rejected: IntCounterVec,
+    pub adapter_queue_depth: IntGaugeVec,
+    pub adapter_queue_wait: HistogramVec,
+    pub adapter_queue_rejected: IntCounterVec,
+    pub request_violations: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Metrics {
@@ -29,13 +51,115 @@ impl Metrics {
         )
         .expect("metric");
 
+        let tokens_total = IntCounterVec::new(
+            prometheus::Opts::new("pagi_tokens_total", "Tokens reported by adapters"),
+            &["adapter", "kind"],
+        )
+        .expect("metric");
+
+        let adapter_healthy = IntGaugeVec::new(
+            prometheus::Opts::new("pagi_adapter_healthy", "1 if the adapter holds a valid lease, else 0"),
+            &["adapter"],
+        )
+        .expect("metric");
+        let adapter_evictions = IntCounterVec::new(
+            prometheus::Opts::new("pagi_adapter_evictions_total", "Adapters evicted after missing their lease"),
+            &["adapter"],
+        )
+        .expect("metric");
+
+        let adapter_breaker = IntGaugeVec::new(
+            prometheus::Opts::new("pagi_adapter_breaker_state", "Circuit breaker state: 0 closed, 1 open, 2 half-open"),
+            &["adapter"],
+        )
+        .expect("metric");
+
+        let retries_total = IntCounterVec::new(
+            prometheus::Opts::new("pagi_retries_total", "Forward retries, by whether the retry budget allowed them"),
+            &["outcome"],
+        )
+        .expect("metric");
+
+        let adapter_attempts = IntCounterVec::new(
+            prometheus::Opts::new("pagi_adapter_attempts_total", "Calls made to each adapter, including retries and hedges"),
+            &["adapter"],
+        )
+        .expect("metric");
+        let hedges_total = IntCounterVec::new(
+            prometheus::Opts::new("pagi_hedges_total", "Hedged calls, by slow primary and hedge target"),
+            &["primary", "hedge"],
+        )
+        .expect("metric");
+        let hedge_wins = IntCounterVec::new(
+            prometheus::Opts::new("pagi_hedge_wins_total", "Hedged calls where the hedge target answered first"),
+            &["primary", "hedge"],
+        )
+        .expect("metric");
+
+        let registration_rejected = IntCounterVec::new(
+            prometheus::Opts::new("pagi_registration_rejected_total", "Registry RPCs refused by adapter authentication"),
+            &["rpc", "reason"],
+        )
+        .expect("metric");
+
+        let adapter_queue_depth = IntGaugeVec::new(
+            prometheus::Opts::new("pagi_adapter_queue_depth", "Calls waiting for an adapter concurrency slot"),
+            &["adapter"],
+        )
+        .expect("metric");
+        let adapter_queue_wait = HistogramVec::new(
+            HistogramOpts::new("pagi_adapter_queue_wait_seconds", "Time queued calls waited for an adapter slot"),
+            &["adapter", "priority"],
+        )
+        .expect("metric");
+        let adapter_queue_rejected = IntCounterVec::new(
+            prometheus::Opts::new("pagi_adapter_queue_rejected_total", "Calls that got no adapter slot, by reason"),
+            &["adapter", "reason"],
+        )
+        .expect("metric");
+
         registry.register(Box::new(requests_total.clone())).expect("register");
+        let request_violations = IntCounterVec::new(
+            prometheus::Opts::new("pagi_request_violations_total", "Requests refused for breaking an ingress limit, by reason"),
+            &["protocol", "reason"],
+        )
+        .expect("metric");
+        registry.register(Box::new(request_violations.clone())).expect("register");
+        registry.register(Box::new(adapter_queue_depth.clone())).expect("register");
+        registry.register(Box::new(adapter_queue_wait.clone())).expect("register");
+        registry.register(Box::new(adapter_queue_rejected.clone())).expect("register");
+        registry.register(Box::new(registration_rejected.clone())).expect("register");
+        registry.register(Box::new(adapter_attempts.clone())).expect("register");
+        registry.register(Box::new(hedges_total.clone())).expect("register");
+        registry.register(Box::new(hedge_wins.clone())).expect("register");
+        registry.register(Box::new(retries_total.clone())).expect("register");
+        registry.register(Box::new(adapter_breaker.clone())).expect("register");
+        registry.register(Box::new(adapter_healthy.clone())).expect("register");
+        registry.register(Box::new(adapter_evictions.clone())).expect("register");
+        registry.register(Box::new(tokens_total.clone())).expect("register");
         registry
             .register(Box::new(request_latency.clone()))
             .expect("register");
 
         Self {
-            inner: Arc::new(Inner { registry, requests_total, request_latency }),
+            inner: Arc::new(Inner {
+                registry,
+                requests_total,
+                request_latency,
+                tokens_total,
+                adapter_healthy,
+                adapter_evictions,
+                adapter_breaker,
+                retries_total,
+                adapter_attempts,
+                hedges_total,
+                hedge_wins,
+                registration_rejected,
+                adapter_queue_depth,
+                adapter_queue_wait,
+                adapter_queue_rejected,
+                request_violations,
+            }),
         }
     }
 
@@ -57,4 +181,74 @@ impl Metrics {
     pub fn observe_latency(&self, protocol: &'static str, seconds: f64) {
         self.inner.request_latency.with_label_values(&[protocol]).observe(seconds);
     }
+
+    pub fn observe_usage(&self, adapter_id: &str, usage: Usage) {
+        for (kind, n) in [
+            ("prompt", usage.prompt_tokens),
+            ("completion", usage.completion_tokens),
+            ("cached", usage.cached_tokens),
+        ] {
+            self.inner.tokens_total.with_label_values(&[adapter_id, kind]).inc_by(n as u64);
+        }
+    }
+
+    pub fn set_adapter_healthy(&self, adapter_id: &str, healthy: bool) {
+        self.inner.adapter_healthy.with_label_values(&[adapter_id]).set(healthy as i64);
+    }
+
+    pub fn inc_adapter_evictions(&self, adapter_id: &str) {
+        self.inner.adapter_evictions.with_label_values(&[adapter_id]).inc();
+    }
+
+    pub fn set_adapter_breaker(&self, adapter_id: &str, state: BreakerState) {
+        let v = match state {
+            BreakerState::Closed => 0,
+            BreakerState::Open => 1,
+            BreakerState::HalfOpen => 2,
+        };
+        self.inner.adapter_breaker.with_label_values(&[adapter_id]).set(v);
+    }
+
+    pub fn inc_retries(&self, outcome: &'static str) {
+        self.inner.retries_total.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn inc_adapter_attempts(&self, adapter_id: &str) {
+        self.inner.adapter_attempts.with_label_values(&[adapter_id]).inc();
+    }
+
+    pub fn inc_hedges(&self, primary: &str, hedge: &str) {
+        self.inner.hedges_total.with_label_values(&[primary, hedge]).inc();
+    }
+
+    pub fn inc_hedge_wins(&self, primary: &str, hedge: &str) {
+        self.inner.hedge_wins.with_label_values(&[primary, hedge]).inc();
+    }
+
+    pub fn inc_registration_rejected(&self, rpc: &'static str, reason: &'static str) {
+        self.inner.registration_rejected.with_label_values(&[rpc, reason]).inc();
+    }
+
+    pub fn set_adapter_queue_depth(&self, adapter_id: &str, depth: usize) {
+        self.inner.adapter_queue_depth.with_label_values(&[adapter_id]).set(depth as i64);
+    }
+
+    pub fn observe_adapter_queue_wait(&self, adapter_id: &str, priority: &'static str, seconds: f64) {
+        self.inner.adapter_queue_wait.with_label_values(&[adapter_id, priority]).observe(seconds);
+    }
+
+    pub fn inc_adapter_queue_rejected(&self, adapter_id: &str, reason: &'static str) {
+        self.inner.adapter_queue_rejected.with_label_values(&[adapter_id, reason]).inc();
+    }
+
+    pub fn inc_violations(&self, protocol: &'static str, reason: &'static str) {
+        self.inner.request_violations.with_label_values(&[protocol, reason]).inc();
+    }
+
+    /// Drop per-adapter gauges once an adapter leaves the registry.
+    pub fn remove_adapter(&self, adapter_id: &str) {
+        let _ = self.inner.adapter_healthy.remove_label_values(&[adapter_id]);
+        let _ = self.inner.adapter_breaker.remove_label_values(&[adapter_id]);
+        let _ = self.inner.adapter_queue_depth.remove_label_values(&[adapter_id]);
+    }
 }
@@ -1,18 +1,385 @@
-use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+//! Request rate limiting.
+//!
+//! [`RateLimits`] is built once at startup and shared by every ingress. Each configured
+//! dimension (client IP, API key, tenant, agent id) has its own keyed GCRA limiter; a request is
+//! admitted only if every dimension that applies to it has capacity. Responses carry
+//! `RateLimit-Limit`/`RateLimit-Remaining`/`RateLimit-Reset` for the tightest dimension, and
+//! rejections add `Retry-After`.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use governor::clock::{Clock, DefaultClock};
+use governor::middleware::StateInformationMiddleware;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use hyper::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use hyper::{HeaderMap, Request};
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::{QuotaConfig, RateLimitConfig};
+
+use super::auth::Principal;
+use super::token_limit::{TokenLimitError, TokenLimiter, TokenReservation};
+
+/// The peer address of an HTTP connection, stored in request extensions by the server.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Ip,
+    ApiKey,
+    Tenant,
+    AgentId,
+    /// Token budget (see [`super::token_limit`]).
+    Tokens,
+}
+
+impl Dimension {
+    pub fn label(self) -> &'static str {
+        match self {
+            Dimension::Ip => "ip",
+            Dimension::ApiKey => "api_key",
+            Dimension::Tenant => "tenant",
+            Dimension::AgentId => "agent_id",
+            Dimension::Tokens => "tokens",
+        }
+    }
+}
+
+/// Quota state after an admitted request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the quota is fully replenished.
+    pub reset: Duration,
+}
+
+impl RateLimitStatus {
+    /// Whichever of `a` and `b` has less headroom.
+    pub fn tighter(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if b.remaining < a.remaining { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn set_headers(&self, headers: &mut HeaderMap) {
+        set_header(headers, "ratelimit-limit", self.limit);
+        set_header(headers, "ratelimit-remaining", self.remaining);
+        set_header(headers, "ratelimit-reset", ceil_secs(self.reset));
+    }
+}
+
+/// A request was over quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("{} rate limit exceeded", .dimension.label())]
+pub struct RateLimited {
+    pub dimension: Dimension,
+    pub limit: u32,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    pub fn set_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RETRY_AFTER, HeaderValue::from(ceil_secs(self.retry_after)));
+        set_header(headers, "ratelimit-limit", self.limit);
+        set_header(headers, "ratelimit-remaining", 0);
+        set_header(headers, "ratelimit-reset", ceil_secs(self.retry_after));
+    }
+}
+
+type Limiter = DefaultKeyedRateLimiter<String, StateInformationMiddleware>;
+
+#[derive(Default)]
+struct DimensionLimits {
+    default: Option<Limiter>,
+    overrides: HashMap<String, Limiter>,
+}
+
+impl DimensionLimits {
+    fn limiter(&self, value: &str) -> Option<&Limiter> {
+        self.overrides.get(value).or(self.default.as_ref())
+    }
+}
+
+struct Inner {
+    trusted: Vec<Cidr>,
+    dimensions: HashMap<Dimension, DimensionLimits>,
+    tokens: Option<TokenLimiter>,
+}
+
+#[derive(Clone)]
+pub struct RateLimits {
+    inner: Arc<Inner>,
+}
+
+impl RateLimits {
+    pub fn new(cfg: &RateLimitConfig) -> anyhow::Result<Self> {
+        let trusted = cfg
+            .trusted_proxies
+            .iter()
+            .map(|c| Cidr::parse(c).with_context(|| format!("invalid trusted proxy {c:?}")))
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut dimensions: HashMap<Dimension, DimensionLimits> = HashMap::new();
+        for (dim, quota) in [
+            (Dimension::Ip, cfg.ip),
+            (Dimension::ApiKey, cfg.api_key),
+            (Dimension::Tenant, cfg.tenant),
+            (Dimension::AgentId, cfg.agent_id),
+        ] {
+            if let Some(q) = quota {
+                dimensions.entry(dim).or_default().default = Some(limiter(&q)?);
+            }
+        }
+        for (key, quota) in &cfg.overrides {
+            let (dim, value) = key.split_once(':').with_context(|| format!("override {key:?} is not <dimension>:<value>"))?;
+            let dim = [Dimension::Ip, Dimension::ApiKey, Dimension::Tenant, Dimension::AgentId]
+                .into_iter()
+                .find(|d| d.label() == dim)
+                .with_context(|| format!("unknown rate limit dimension {dim:?}"))?;
+            dimensions.entry(dim).or_default().overrides.insert(value.to_string(), limiter(quota)?);
+        }
+        let tokens = cfg.tokens.as_ref().map(TokenLimiter::new);
+        Ok(Self { inner: Arc::new(Inner { trusted, dimensions, tokens }) })
+    }
+
+    /// The client address: the socket peer, or, when the peer is a trusted proxy, the right-most
+    /// `x-forwarded-for` entry that is not itself a trusted proxy.
+    pub fn client_ip<B>(&self, req: &Request<B>) -> Option<IpAddr> {
+        let peer = req.extensions().get::<RemoteAddr>()?.0;
+        Some(self.client_ip_of(peer, req.headers()))
+    }
+
+    /// [`Self::client_ip`] for a connection whose peer is `peer`.
+    pub fn client_ip_of(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.ip().to_canonical();
+        if !self.trusted(peer) {
+            return peer;
+        }
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .map(|ip| ip.to_canonical())
+            .collect::<Vec<_>>();
+        forwarded.into_iter().rev().find(|ip| !self.trusted(*ip)).unwrap_or(peer)
+    }
+
+    /// Charge the client IP's quota. Runs before authentication so unauthenticated floods are
+    /// limited too.
+    pub fn check_ip<B>(&self, req: &Request<B>) -> Result<Option<RateLimitStatus>, RateLimited> {
+        self.check_peer(req.extensions().get::<RemoteAddr>().map(|r| r.0), req.headers())
+    }
+
+    /// [`Self::check_ip`] for requests that do not come through hyper (gRPC). A missing peer
+    /// (Unix socket) is not limited.
+    pub fn check_peer(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> Result<Option<RateLimitStatus>, RateLimited> {
+        match peer {
+            Some(peer) => self.check(&[(Dimension::Ip, self.client_ip_of(peer, headers).to_string())]),
+            None => Ok(None),
+        }
+    }
+
+    /// Charge the API key, tenant and agent quotas of an authenticated, canonicalized request.
+    pub fn check_caller(
+        &self,
+        principal: Option<&Principal>,
+        req: &CanonicalAIRequest,
+    ) -> Result<Option<RateLimitStatus>, RateLimited> {
+        let mut keys = Vec::new();
+        if let Some(p) = principal {
+            if let Some(key) = &p.api_key {
+                keys.push((Dimension::ApiKey, key.id.clone()));
+            }
+            if let Some(t) = &p.tenant {
+                keys.push((Dimension::Tenant, t.clone()));
+            }
+        }
+        if let Some(agent) = &req.agent_id {
+            keys.push((Dimension::AgentId, agent.clone()));
+        }
+        self.check(&keys)
+    }
+
+    /// Reserve the caller's tenant token budget for `req`; settle the reservation with the
+    /// response's usage.
+    pub fn reserve_tokens(
+        &self,
+        principal: Option<&Principal>,
+        req: &CanonicalAIRequest,
+    ) -> Result<TokenReservation, TokenLimitError> {
+        match &self.inner.tokens {
+            Some(tokens) => tokens.reserve(principal.and_then(|p| p.tenant.as_deref()), req),
+            None => Ok(TokenReservation::none()),
+        }
+    }
+
+    pub fn check(&self, keys: &[(Dimension, String)]) -> Result<Option<RateLimitStatus>, RateLimited> {
+        let mut tightest = None;
+        for (dim, value) in keys {
+            let Some(limiter) = self.inner.dimensions.get(dim).and_then(|d| d.limiter(value)) else {
+                continue;
+            };
+            match limiter.check_key(value) {
+                Ok(snapshot) => {
+                    let quota = snapshot.quota();
+                    let limit = quota.burst_size().get();
+                    let remaining = snapshot.remaining_burst_capacity();
+                    let status = RateLimitStatus { limit, remaining, reset: quota.replenish_interval() * (limit - remaining) };
+                    tightest = RateLimitStatus::tighter(tightest, Some(status));
+                }
+                Err(not_until) => {
+                    return Err(RateLimited {
+                        dimension: *dim,
+                        limit: not_until.quota().burst_size().get(),
+                        retry_after: not_until.wait_time_from(DefaultClock::default().now()),
+                    });
+                }
+            }
+        }
+        Ok(tightest)
+    }
+
+    /// Periodically drop state for keys that are back at full capacity, so the limiters do not
+    /// grow with every IP or agent id ever seen.
+    pub fn spawn_housekeeping(&self) {
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                tick.tick().await;
+                for dim in inner.dimensions.values() {
+                    dim.default.iter().chain(dim.overrides.values()).for_each(|l| l.retain_recent());
+                }
+                if let Some(tokens) = &inner.tokens {
+                    tokens.prune();
+                }
+            }
+        });
+    }
+
+    fn trusted(&self, ip: IpAddr) -> bool {
+        self.inner.trusted.iter().any(|c| c.contains(ip))
+    }
+}
+
+fn limiter(q: &QuotaConfig) -> anyhow::Result<Limiter> {
+    let requests = NonZeroU32::new(q.requests).context("quota requests must be > 0")?;
+    let period = Duration::from_secs(q.period_secs.max(1));
+    let burst = NonZeroU32::new(q.burst.unwrap_or(q.requests)).context("quota burst must be > 0")?;
+    let quota = Quota::with_period(period / requests.get()).context("quota period too short")?.allow_burst(burst);
+    Ok(RateLimiter::keyed(quota).with_middleware::<StateInformationMiddleware>())
+}
+
+fn set_header(headers: &mut HeaderMap, name: &'static str, value: impl Into<HeaderValue>) {
+    headers.insert(HeaderName::from_static(name), value.into());
+}
+
+fn ceil_secs(d: Duration) -> u64 {
+    d.as_secs() + u64::from(d.subsec_nanos() > 0)
+}
 
-pub struct IpRateLimiter {
-    limiter: DefaultKeyedRateLimiter<String>,
+/// An IPv4 or IPv6 network in CIDR notation; a bare address is a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cidr {
+    net: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a.parse::<IpAddr>()?, Some(p.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        anyhow::ensure!(prefix <= max, "prefix length {prefix} out of range");
+        Ok(Self { net: addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
 }
 
-impl IpRateLimiter {
-    pub fn new_per_second(max: u32) -> Self {
-        let quota = Quota::per_second(NonZeroU32::new(max.max(1)).unwrap());
-        let limiter = RateLimiter::keyed(quota);
-        Self { limiter }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(yaml: &str) -> RateLimits {
+        RateLimits::new(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn req_from(peer: &str, xff: Option<&str>) -> Request<()> {
+        let mut req = Request::new(());
+        req.extensions_mut().insert(RemoteAddr(format!("{peer}:1234").parse().unwrap()));
+        if let Some(x) = xff {
+            req.headers_mut().insert("x-forwarded-for", x.parse().unwrap());
+        }
+        req
+    }
+
+    #[test]
+    fn xff_is_only_trusted_from_proxies() {
+        let l = limits("trusted_proxies: ['10.0.0.0/8']");
+        let ip = |peer, xff| l.client_ip(&req_from(peer, xff)).unwrap().to_string();
+        assert_eq!(ip("203.0.113.9", Some("1.2.3.4")), "203.0.113.9");
+        assert_eq!(ip("10.1.2.3", Some("1.2.3.4, 10.9.9.9")), "1.2.3.4");
+        assert_eq!(ip("10.1.2.3", Some("6.6.6.6, 1.2.3.4")), "1.2.3.4");
+        assert_eq!(ip("10.1.2.3", None), "10.1.2.3");
+    }
+
+    #[test]
+    fn quota_is_shared_across_calls() {
+        let l = limits("ip: { requests: 2, period_secs: 60 }");
+        let req = req_from("203.0.113.9", None);
+        assert_eq!(l.check_ip(&req).unwrap().unwrap().remaining, 1);
+        assert_eq!(l.check_ip(&req).unwrap().unwrap().remaining, 0);
+        let limited = l.check_ip(&req).unwrap_err();
+        assert_eq!(limited.dimension, Dimension::Ip);
+        assert!(limited.retry_after > Duration::from_secs(1));
+        // Another client has its own bucket.
+        assert!(l.check_ip(&req_from("203.0.113.10", None)).is_ok());
+    }
+
+    #[test]
+    fn overrides_replace_dimension_default() {
+        let l = limits("ip: null\ntenant: { requests: 1, period_secs: 60 }\noverrides: { 'tenant:acme': { requests: 100 } }");
+        let key = |t: &str| [(Dimension::Tenant, t.to_string())];
+        assert!(l.check(&key("small")).is_ok());
+        assert!(l.check(&key("small")).is_err());
+        for _ in 0..10 {
+            assert!(l.check(&key("acme")).is_ok());
+        }
     }
 
-    pub fn check(&self, key: String) -> bool {
-        self.limiter.check_key(&key).is_ok()
+    #[test]
+    fn cidr_matching() {
+        let c = Cidr::parse("192.168.0.0/16").unwrap();
+        assert!(c.contains("192.168.4.1".parse().unwrap()));
+        assert!(!c.contains("192.169.0.1".parse().unwrap()));
+        assert!(Cidr::parse("::1").unwrap().contains("::1".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
     }
 }
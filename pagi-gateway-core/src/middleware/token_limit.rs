@@ -0,0 +1,299 @@
+//! Token quotas per tenant.
+//!
+//! Before forwarding, a request reserves its estimated prompt tokens plus its `max_tokens` (or
+//! `default_max_tokens`) against the tenant's per-minute and per-day budgets; requests that do
+//! not fit are rejected, with a 429 while the window is too full and a 413 when the request is
+//! larger than the whole window's budget. Once the response is in, the reservation is settled against the
+//! adapter's reported usage, and a request that failed before any adapter answered gives its
+//! reservation back. Windows are fixed UTC minutes and days. Callers without a tenant share one
+//! budget.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::canonical::{CanonicalAIRequest, ContentPart, Usage};
+use crate::config::{TokenLimitConfig, TokenQuotaConfig};
+
+use super::rate_limit::{Dimension, RateLimited};
+
+/// Bucket for callers without a tenant.
+const ANONYMOUS: &str = "-";
+
+/// Rough tokens per character of English text; real tokenizers vary by model.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Role markers and separators each message costs on top of its text.
+const MESSAGE_OVERHEAD: u32 = 4;
+
+/// Flat charge for a non-text content part (image, audio, file).
+const ATTACHMENT_TOKENS: u32 = 256;
+
+/// Estimated prompt tokens of `req`: its message text, tool definitions and attachments.
+pub fn estimate_prompt_tokens(req: &CanonicalAIRequest) -> u32 {
+    let text = |s: &str| s.len().div_ceil(CHARS_PER_TOKEN) as u32;
+    let messages: u32 = req
+        .messages
+        .iter()
+        .map(|m| {
+            let content: u32 = m
+                .content
+                .iter()
+                .map(|p| match p {
+                    ContentPart::Text { text: t } => text(t),
+                    _ => ATTACHMENT_TOKENS,
+                })
+                .sum();
+            MESSAGE_OVERHEAD + content
+        })
+        .sum();
+    let tools = if req.tools.is_empty() { 0 } else { text(&serde_json::to_string(&req.tools).unwrap_or_default()) };
+    messages + tools
+}
+
+/// Estimated tokens of generated text, for streams that report no usage.
+pub fn estimate_completion_tokens(text_len: usize) -> u32 {
+    text_len.div_ceil(CHARS_PER_TOKEN) as u32
+}
+
+/// Why a token reservation was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TokenLimitError {
+    /// Over budget for now; the request fits once the window rolls over.
+    #[error(transparent)]
+    Limited(#[from] RateLimited),
+    /// Larger than a window's entire budget, so retrying can never help.
+    #[error("request exceeds token quota: needs about {needed} tokens, limit is {limit}")]
+    TooLarge { needed: u64, limit: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+    Minute,
+    Day,
+}
+
+impl Window {
+    fn secs(self) -> u64 {
+        match self {
+            Window::Minute => 60,
+            Window::Day => 86_400,
+        }
+    }
+
+    fn limit(self, q: &TokenQuotaConfig) -> Option<u64> {
+        match self {
+            Window::Minute => q.per_minute,
+            Window::Day => q.per_day,
+        }
+    }
+}
+
+/// Tokens charged in the current minute and day, each tagged with its window index.
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    minute: (u64, u64),
+    day: (u64, u64),
+}
+
+impl Counters {
+    fn slot(&mut self, w: Window) -> &mut (u64, u64) {
+        match w {
+            Window::Minute => &mut self.minute,
+            Window::Day => &mut self.day,
+        }
+    }
+
+    /// Tokens used in `w` at `now`, resetting the counter if its window has passed.
+    fn used(&mut self, w: Window, now: u64) -> &mut u64 {
+        let idx = now / w.secs();
+        let slot = self.slot(w);
+        if slot.0 != idx {
+            *slot = (idx, 0);
+        }
+        &mut slot.1
+    }
+}
+
+struct State {
+    cfg: TokenLimitConfig,
+    counters: Mutex<HashMap<String, Counters>>,
+}
+
+#[derive(Clone)]
+pub struct TokenLimiter {
+    state: Arc<State>,
+}
+
+impl TokenLimiter {
+    pub fn new(cfg: &TokenLimitConfig) -> Self {
+        Self { state: Arc::new(State { cfg: cfg.clone(), counters: Mutex::new(HashMap::new()) }) }
+    }
+
+    /// Reserve tokens for `req` on behalf of `tenant`.
+    pub fn reserve(&self, tenant: Option<&str>, req: &CanonicalAIRequest) -> Result<TokenReservation, TokenLimitError> {
+        self.reserve_at(tenant, req, now())
+    }
+
+    fn reserve_at(
+        &self,
+        tenant: Option<&str>,
+        req: &CanonicalAIRequest,
+        now: u64,
+    ) -> Result<TokenReservation, TokenLimitError> {
+        let key = tenant.unwrap_or(ANONYMOUS).to_string();
+        let quota = self.state.cfg.tenants.get(&key).unwrap_or(&self.state.cfg.default);
+        let prompt = estimate_prompt_tokens(req);
+        let completion = req.constraints.max_tokens.unwrap_or(self.state.cfg.default_max_tokens);
+        let amount = u64::from(prompt) + u64::from(completion);
+        let windows = [Window::Minute, Window::Day];
+        if let Some(limit) = windows.iter().filter_map(|w| w.limit(quota)).find(|limit| amount > *limit) {
+            return Err(TokenLimitError::TooLarge { needed: amount, limit });
+        }
+
+        let mut counters = self.state.counters.lock().unwrap();
+        let c = counters.entry(key.clone()).or_default();
+        for w in windows {
+            if let Some(limit) = w.limit(quota) {
+                if *c.used(w, now) + amount > limit {
+                    return Err(TokenLimitError::Limited(RateLimited {
+                        dimension: Dimension::Tokens,
+                        limit: limit.min(u64::from(u32::MAX)) as u32,
+                        retry_after: Duration::from_secs(w.secs() - now % w.secs()),
+                    }));
+                }
+            }
+        }
+        *c.used(Window::Minute, now) += amount;
+        *c.used(Window::Day, now) += amount;
+
+        Ok(TokenReservation { limiter: Some(self.clone()), key, reserved: amount, prompt_estimate: prompt, at: now })
+    }
+
+    /// Replace `reserved` tokens charged at `at` with `actual`. Windows that have rolled over
+    /// since are charged `actual` afresh.
+    fn adjust(&self, key: &str, reserved: u64, at: u64, actual: u64, now: u64) {
+        let mut counters = self.state.counters.lock().unwrap();
+        let Some(c) = counters.get_mut(key) else {
+            return;
+        };
+        for w in [Window::Minute, Window::Day] {
+            let same_window = c.slot(w).0 == at / w.secs();
+            let used = c.used(w, now);
+            if same_window && at / w.secs() == now / w.secs() {
+                *used = used.saturating_sub(reserved) + actual;
+            } else {
+                *used += actual;
+            }
+        }
+    }
+
+    /// Forget counters from past days.
+    pub(crate) fn prune(&self) {
+        let today = now() / Window::Day.secs();
+        self.state.counters.lock().unwrap().retain(|_, c| c.day.0 >= today);
+    }
+}
+
+/// Tokens held for one in-flight request. Call [`settle`](Self::settle) with the response's
+/// usage; dropping it unsettled returns the tokens.
+#[must_use]
+pub struct TokenReservation {
+    limiter: Option<TokenLimiter>,
+    key: String,
+    reserved: u64,
+    prompt_estimate: u32,
+    at: u64,
+}
+
+impl TokenReservation {
+    /// A reservation that charges nothing, for when token limits are off.
+    pub fn none() -> Self {
+        Self { limiter: None, key: String::new(), reserved: 0, prompt_estimate: 0, at: 0 }
+    }
+
+    pub fn prompt_estimate(&self) -> u32 {
+        self.prompt_estimate
+    }
+
+    /// Charge what the request actually used. Without reported usage the reservation stands.
+    pub fn settle(self, usage: Option<Usage>) {
+        self.settle_at(usage, now());
+    }
+
+    fn settle_at(mut self, usage: Option<Usage>, now: u64) {
+        if let (Some(limiter), Some(usage)) = (self.limiter.take(), usage) {
+            limiter.adjust(&self.key, self.reserved, self.at, u64::from(usage.total_tokens()), now);
+        }
+    }
+}
+
+impl Drop for TokenReservation {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.adjust(&self.key, self.reserved, self.at, 0, now());
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> TokenLimiter {
+        TokenLimiter::new(&serde_yaml::from_str("default: { per_minute: 1000 }\ndefault_max_tokens: 100").unwrap())
+    }
+
+    fn request(chars: usize, max_tokens: Option<u32>) -> CanonicalAIRequest {
+        let mut req = CanonicalAIRequest::chat_text(None, "x".repeat(chars));
+        req.constraints.max_tokens = max_tokens;
+        req
+    }
+
+    #[test]
+    fn estimates_text_and_overhead() {
+        assert_eq!(estimate_prompt_tokens(&request(400, None)), 100 + MESSAGE_OVERHEAD);
+    }
+
+    #[test]
+    fn reservation_is_reconciled_with_usage() {
+        let l = limiter();
+        let t0 = 6_000; // start of a minute
+        let r = l.reserve_at(Some("acme"), &request(384, Some(500)), t0).unwrap();
+        assert_eq!(r.reserved, 96 + u64::from(MESSAGE_OVERHEAD) + 500);
+        assert!(l.reserve_at(Some("acme"), &request(384, Some(500)), t0).is_err());
+
+        r.settle_at(Some(Usage { prompt_tokens: 100, completion_tokens: 50, cached_tokens: 0 }), t0 + 1);
+        let r = l.reserve_at(Some("acme"), &request(384, Some(500)), t0 + 1).unwrap();
+        r.settle_at(None, t0 + 1);
+        // Another tenant has its own budget.
+        let r = l.reserve_at(Some("other"), &request(384, Some(500)), t0).unwrap();
+        r.settle_at(None, t0);
+    }
+
+    #[test]
+    fn failed_requests_give_tokens_back() {
+        let l = limiter();
+        drop(l.reserve(None, &request(0, Some(900))).unwrap());
+        l.reserve(None, &request(0, Some(900))).unwrap().settle(Some(Usage { prompt_tokens: 1, completion_tokens: 1, cached_tokens: 0 }));
+        let Err(TokenLimitError::Limited(limited)) = l.reserve(None, &request(0, Some(996))) else {
+            panic!("expected the minute budget to be exhausted");
+        };
+        assert_eq!(limited.dimension, Dimension::Tokens);
+    }
+
+    #[test]
+    fn request_larger_than_the_budget_is_not_rate_limited() {
+        let l = limiter();
+        let Err(err) = l.reserve(None, &request(400, Some(1000))) else {
+            panic!("expected the request to be refused");
+        };
+        assert_eq!(err, TokenLimitError::TooLarge { needed: 1104, limit: 1000 });
+        // Nothing was charged.
+        l.reserve(None, &request(0, Some(990))).unwrap().settle(None);
+    }
+}
@@ -0,0 +1,114 @@
+//! API key administration: `POST /admin/api-keys` creates a key (the plaintext is returned only
+//! in that response), `GET /admin/api-keys` lists keys and `DELETE /admin/api-keys/{id}` revokes
+//! one. All three require `Authorization: Bearer <core.auth.api_keys.admin_token>`.
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use tracing::{info, warn};
+
+use crate::middleware::api_keys::NewApiKey;
+use crate::middleware::auth::Authenticator;
+
+use super::json;
+
+pub const API_KEYS_PATH: &str = "/admin/api-keys";
+
+pub async fn handle_api_keys(req: Request<Body>, auth: Authenticator) -> Result<Response<Body>, hyper::Error> {
+    let Some(store) = auth.api_keys() else {
+        return Ok(error(StatusCode::NOT_FOUND, "api keys are not enabled"));
+    };
+    if !auth.is_admin(req.headers()) {
+        return Ok(error(StatusCode::UNAUTHORIZED, "admin token required"));
+    }
+
+    let id = req.uri().path().strip_prefix(API_KEYS_PATH).unwrap_or_default().trim_matches('/').to_string();
+    match (req.method().clone(), id.as_str()) {
+        (Method::GET, "") => Ok(json(StatusCode::OK, &serde_json::json!({ "keys": store.list() }))),
+        (Method::POST, "") => {
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            let new: NewApiKey = match serde_json::from_slice(&body) {
+                Ok(v) => v,
+                Err(e) => return Ok(error(StatusCode::BAD_REQUEST, &e.to_string())),
+            };
+            match store.create(new) {
+                Ok((key, plaintext)) => {
+                    info!(key_id=%key.id, name=%key.name, "api key created");
+                    Ok(json(StatusCode::CREATED, &serde_json::json!({ "key": plaintext, "api_key": key })))
+                }
+                Err(e) => {
+                    warn!(error=%e, "creating api key failed");
+                    Ok(error(StatusCode::INTERNAL_SERVER_ERROR, "could not store api key"))
+                }
+            }
+        }
+        (Method::DELETE, id) if !id.is_empty() => match store.revoke(id) {
+            Ok(true) => {
+                info!(key_id=%id, "api key revoked");
+                Ok(Response::builder().status(StatusCode::NO_CONTENT).body(Body::empty()).unwrap())
+            }
+            Ok(false) => Ok(error(StatusCode::NOT_FOUND, "no such api key")),
+            Err(e) => {
+                warn!(error=%e, "revoking api key failed");
+                Ok(error(StatusCode::INTERNAL_SERVER_ERROR, "could not store api key"))
+            }
+        },
+        _ => Ok(error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed")),
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiKeysConfig, AuthConfig, TokenConfig};
+
+    fn authenticator() -> Authenticator {
+        let path = std::env::temp_dir().join(format!("pagi-keys-{}-admin.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let api_keys = ApiKeysConfig {
+            store_path: path.to_string_lossy().into_owned(),
+            admin_token: Some(TokenConfig { token: Some("root".to_string()), token_env: None }),
+        };
+        Authenticator::new(&AuthConfig { api_keys: Some(api_keys), ..Default::default() }).unwrap()
+    }
+
+    async fn send(auth: &Authenticator, method: Method, path: &str, token: Option<&str>, body: &str) -> (StatusCode, serde_json::Value) {
+        let mut req = Request::builder().method(method).uri(path);
+        if let Some(t) = token {
+            req = req.header("authorization", format!("Bearer {t}"));
+        }
+        let resp = handle_api_keys(req.body(Body::from(body.to_string())).unwrap(), auth.clone()).await.unwrap();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn manages_keys_with_the_admin_token() {
+        let auth = authenticator();
+        let (status, _) = send(&auth, Method::POST, API_KEYS_PATH, None, r#"{"name":"ci"}"#).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&auth, Method::GET, API_KEYS_PATH, Some("guess"), "").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, created) = send(&auth, Method::POST, API_KEYS_PATH, Some("root"), r#"{"name":"ci"}"#).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let plaintext = created["key"].as_str().unwrap();
+        let id = created["api_key"]["id"].as_str().unwrap();
+        assert!(auth.api_keys().unwrap().verify(plaintext).is_some());
+
+        // The plaintext is never shown again.
+        let (status, listed) = send(&auth, Method::GET, API_KEYS_PATH, Some("root"), "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listed["keys"][0]["id"], id);
+        assert!(!listed.to_string().contains(plaintext));
+
+        let key_path = format!("{API_KEYS_PATH}/{id}");
+        assert_eq!(send(&auth, Method::DELETE, &key_path, Some("root"), "").await.0, StatusCode::NO_CONTENT);
+        assert!(auth.api_keys().unwrap().verify(plaintext).is_none());
+        let unknown = format!("{API_KEYS_PATH}/nope");
+        assert_eq!(send(&auth, Method::DELETE, &unknown, Some("root"), "").await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use hyper::{Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::canonical::{
+    CanonicalAIRequest, CanonicalAIResponse, ContentPart, FinishReason, Message, MessageRole, Tool, ToolCall,
+};
+use crate::middleware::observability::Metrics;
+use crate::middleware::auth::Authenticator;
+use crate::middleware::rate_limit::RateLimits;
+use crate::middleware::token_limit::TokenReservation;
+use crate::registry::{AdapterRegistryState, ForwardStream};
+
+use super::{
+    forward_error_status, forward_options, json, relay, remote_addr, sse_event, sse_response, with_quota, Admitted, Gate, Rejected,
+};
+
+/// Anthropic Messages API request (`POST /v1/messages`).
+///
+/// Only the fields that have a canonical equivalent are modeled; unknown fields are ignored.
+#[derive(Debug, Deserialize)]
+pub struct MessagesRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub max_tokens: u32,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(default)]
+    pub system: Option<SystemPrompt>,
+    #[serde(default)]
+    pub tools: Vec<AnthropicTool>,
+    #[serde(default)]
+    pub tool_choice: Option<AnthropicToolChoice>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SystemPrompt {
+    Text(String),
+    Blocks(Vec<TextBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TextBlock {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnthropicRole {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicMessage {
+    pub role: AnthropicRole,
+    pub content: AnthropicContent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AnthropicContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: MediaSource,
+    },
+    Document {
+        source: MediaSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        content: Option<ToolResultContent>,
+        #[serde(default)]
+        is_error: bool,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MediaSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+}
+
+/// `{"type":"auto"|"any"|"none"}` or `{"type":"tool","name":...}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicToolChoice {
+    Auto,
+    Any,
+    None,
+    Tool { name: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessagesResponse {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub role: &'static str,
+    pub model: String,
+    pub content: Vec<ResponseBlock>,
+    /// `None` only in a stream's `message_start`.
+    pub stop_reason: Option<String>,
+    pub stop_sequence: Option<String>,
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseBlock {
+    Text { text: String },
+    ToolUse { id: String, name: String, input: serde_json::Value },
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct AnthropicUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// A server-sent event of a streamed message (`stream: true`); the event name is its `type`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    MessageStart { message: MessagesResponse },
+    ContentBlockStart { index: u32, content_block: ResponseBlock },
+    ContentBlockDelta { index: u32, delta: BlockDelta },
+    ContentBlockStop { index: u32 },
+    MessageDelta { delta: MessageDelta, usage: DeltaUsage },
+    MessageStop,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockDelta {
+    TextDelta { text: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageDelta {
+    pub stop_reason: String,
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeltaUsage {
+    pub output_tokens: u64,
+}
+
+impl MessagesRequest {
+    pub fn into_canonical(self) -> CanonicalAIRequest {
+        let mut req = CanonicalAIRequest::new();
+
+        let system = match self.system {
+            Some(SystemPrompt::Text(text)) => vec![text],
+            Some(SystemPrompt::Blocks(blocks)) => blocks.into_iter().map(|b| b.text).collect(),
+            None => vec![],
+        };
+        if !system.is_empty() {
+            req.messages.push(Message {
+                role: MessageRole::System,
+                content: system.into_iter().map(|text| ContentPart::Text { text }).collect(),
+                name: None,
+                tool_call_id: None,
+                tool_calls: vec![],
+            });
+        }
+        for m in self.messages {
+            push_canonical_messages(&mut req.messages, m);
+        }
+
+        req.tools = self
+            .tools
+            .into_iter()
+            .map(|t| Tool { name: t.name, description: t.description, parameters_json_schema: t.input_schema, strict: false })
+            .collect();
+        req.tool_choice = self.tool_choice.map(|c| match c {
+            AnthropicToolChoice::Auto => "auto".to_string(),
+            AnthropicToolChoice::Any => "required".to_string(),
+            AnthropicToolChoice::None => "none".to_string(),
+            AnthropicToolChoice::Tool { name } => name,
+        });
+        req.constraints.max_tokens = Some(self.max_tokens);
+        req.constraints.temperature = self.temperature;
+        req.constraints.top_p = self.top_p;
+        req.constraints.top_k = self.top_k;
+        req.constraints.stop_sequences = self.stop_sequences;
+        req.constraints.stream = self.stream;
+        req.preferred_model = self.model.filter(|m| !m.is_empty());
+        req.metadata = self.metadata;
+        req
+    }
+}
+
+/// Anthropic interleaves `tool_result` blocks inside user turns; canonically each result is
+/// its own `tool` message, so one Anthropic message may expand into several.
+fn push_canonical_messages(out: &mut Vec<Message>, m: AnthropicMessage) {
+    let role = match m.role {
+        AnthropicRole::User => MessageRole::User,
+        AnthropicRole::Assistant => MessageRole::Assistant,
+    };
+    let blocks = match m.content {
+        AnthropicContent::Text(text) => vec![ContentBlock::Text { text }],
+        AnthropicContent::Blocks(blocks) => blocks,
+    };
+
+    let mut content = vec![];
+    let mut tool_calls = vec![];
+    for block in blocks {
+        match block {
+            ContentBlock::ToolResult { tool_use_id, content: result, is_error } => {
+                let mut parts = match result {
+                    None => vec![],
+                    Some(ToolResultContent::Text(text)) => vec![ContentPart::Text { text }],
+                    Some(ToolResultContent::Blocks(blocks)) => blocks.into_iter().filter_map(to_content_part).collect(),
+                };
+                if is_error {
+                    parts.insert(0, ContentPart::Text { text: "[tool error]".to_string() });
+                }
+                out.push(Message {
+                    role: MessageRole::Tool,
+                    content: parts,
+                    name: None,
+                    tool_call_id: Some(tool_use_id),
+                    tool_calls: vec![],
+                });
+            }
+            ContentBlock::ToolUse { id, name, input } => {
+                tool_calls.push(ToolCall { id, name, arguments: input.to_string() });
+            }
+            other => content.extend(to_content_part(other)),
+        }
+    }
+
+    if !content.is_empty() || !tool_calls.is_empty() {
+        out.push(Message { role, content, name: None, tool_call_id: None, tool_calls });
+    }
+}
+
+fn to_content_part(block: ContentBlock) -> Option<ContentPart> {
+    match block {
+        ContentBlock::Text { text } => Some(ContentPart::Text { text }),
+        ContentBlock::Image { source } => Some(ContentPart::Image { url: source_url(source).0 }),
+        ContentBlock::Document { source } => {
+            let (url, mime_type) = source_url(source);
+            Some(ContentPart::File { url, mime_type: mime_type.unwrap_or_else(|| "application/pdf".to_string()) })
+        }
+        ContentBlock::ToolUse { .. } | ContentBlock::ToolResult { .. } => None,
+    }
+}
+
+/// Base64 sources become `data:` URLs so they survive the canonical URL-only content parts.
+fn source_url(source: MediaSource) -> (String, Option<String>) {
+    match source {
+        MediaSource::Base64 { media_type, data } => (format!("data:{media_type};base64,{data}"), Some(media_type)),
+        MediaSource::Url { url } => (url, None),
+    }
+}
+
+/// Render a canonical response as a Messages API response.
+///
+/// The Messages API has a single completion per response, so only the first choice is used.
+pub fn render_response(resp: &CanonicalAIResponse, requested_model: Option<&str>) -> MessagesResponse {
+    let choice = resp.choices.first();
+    let mut content = vec![];
+    if let Some(c) = choice {
+        let text = c.message.text();
+        if !text.is_empty() {
+            content.push(ResponseBlock::Text { text });
+        }
+        for t in &c.message.tool_calls {
+            let input = serde_json::from_str(&t.arguments).unwrap_or(serde_json::Value::Object(Default::default()));
+            content.push(ResponseBlock::ToolUse { id: t.id.clone(), name: t.name.clone(), input });
+        }
+    }
+    let stop_sequence = choice.and_then(|c| c.stop_sequence.clone());
+    let has_tool_use = content.iter().any(|b| matches!(b, ResponseBlock::ToolUse { .. }));
+    let stop_reason = match choice.and_then(|c| c.finish_reason) {
+        _ if stop_sequence.is_some() => "stop_sequence",
+        Some(FinishReason::Length) => "max_tokens",
+        Some(FinishReason::ToolCalls) => "tool_use",
+        Some(FinishReason::ContentFilter) => "refusal",
+        _ if has_tool_use => "tool_use",
+        _ => "end_turn",
+    };
+
+    MessagesResponse {
+        id: format!("msg_{}", resp.request_id),
+        kind: "message",
+        role: "assistant",
+        model: resp.model.as_deref().or(requested_model).unwrap_or("pagi").to_string(),
+        content,
+        stop_reason: Some(stop_reason.to_string()),
+        stop_sequence,
+        usage: resp
+            .usage
+            .map(|u| AnthropicUsage { input_tokens: u.prompt_tokens as u64, output_tokens: u.completion_tokens as u64 })
+            .unwrap_or_default(),
+    }
+}
+
+/// Relay adapter chunks as Messages stream events: `message_start` and a text
+/// `content_block_start` with the first chunk, a `content_block_delta` per piece of text, then
+/// `content_block_stop`, `message_delta` (stop reason and output tokens) and `message_stop`. An
+/// adapter failure mid-stream is sent as an `error` event instead.
+fn stream_response(stream: ForwardStream, reservation: TokenReservation, requested_model: Option<String>) -> Response<Body> {
+    let model = requested_model.unwrap_or_else(|| "pagi".to_string());
+    let mut started = false;
+    sse_response(relay(stream, reservation, move |item| match item {
+        Ok(c) => {
+            let mut events = vec![];
+            if !std::mem::replace(&mut started, true) {
+                events.push(StreamEvent::MessageStart {
+                    message: MessagesResponse {
+                        id: format!("msg_{}", c.request_id),
+                        kind: "message",
+                        role: "assistant",
+                        model: model.clone(),
+                        content: vec![],
+                        stop_reason: None,
+                        stop_sequence: None,
+                        usage: AnthropicUsage::default(),
+                    },
+                });
+                events.push(StreamEvent::ContentBlockStart {
+                    index: 0,
+                    content_block: ResponseBlock::Text { text: String::new() },
+                });
+            }
+            if !c.delta.is_empty() {
+                events.push(StreamEvent::ContentBlockDelta { index: 0, delta: BlockDelta::TextDelta { text: c.delta } });
+            }
+            if c.done {
+                events.push(StreamEvent::ContentBlockStop { index: 0 });
+                events.push(StreamEvent::MessageDelta {
+                    delta: MessageDelta { stop_reason: chunk_stop_reason(&c.finish_reason).to_string(), stop_sequence: None },
+                    usage: DeltaUsage { output_tokens: c.usage.map_or(0, |u| u.completion_tokens as u64) },
+                });
+                events.push(StreamEvent::MessageStop);
+            }
+            events.iter().map(stream_event).collect()
+        }
+        Err(e) => sse_event(Some("error"), &error_body("api_error", &e.to_string()).to_string()),
+    }))
+}
+
+fn stream_event(event: &StreamEvent) -> String {
+    let data = serde_json::to_value(event).unwrap();
+    sse_event(data["type"].as_str(), &data.to_string())
+}
+
+/// A streamed chunk's finish reason (see [`FinishReason::as_str`]) as a `stop_reason`.
+fn chunk_stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "length" => "max_tokens",
+        "tool_calls" => "tool_use",
+        "content_filter" => "refusal",
+        _ => "end_turn",
+    }
+}
+
+pub async fn handle_messages(
+    req: Request<Body>,
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+) -> Result<Response<Body>, hyper::Error> {
+    let started = Instant::now();
+    let gate = Gate::new(&auth, &limits, &metrics, "anthropic");
+    let caller = match gate.caller(remote_addr(&req), req.headers()).await {
+        Ok(c) => c,
+        Err(r) => return Ok(rejected(r)),
+    };
+
+    let mut opts = forward_options(req.headers());
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let parsed: MessagesRequest = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(error=%e, "invalid messages request");
+            metrics.inc_requests("anthropic", "400");
+            return Ok(error(StatusCode::BAD_REQUEST, "invalid_request_error", &e.to_string()));
+        }
+    };
+
+    let mut canonical = parsed.into_canonical();
+    let Admitted { reservation, quota } = match gate.admit(&caller, &mut canonical, &mut opts) {
+        Ok(a) => a,
+        Err(r) => return Ok(rejected(r)),
+    };
+    if canonical.messages.is_empty() {
+        metrics.inc_requests("anthropic", "400");
+        return Ok(error(StatusCode::BAD_REQUEST, "invalid_request_error", "messages required"));
+    }
+
+    info!(request_id=%canonical.request_id, "canonicalized anthropic request");
+
+    let requested_model = canonical.preferred_model.clone();
+    if canonical.constraints.stream {
+        let stream = match registry.forward_stream_with(canonical, opts).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error=%e, "forward failed");
+                let (code, label, msg) = forward_error_status(&e);
+                metrics.inc_requests("anthropic", label);
+                return Ok(error(code, "api_error", msg));
+            }
+        };
+        metrics.inc_requests("anthropic", "200");
+        // Time to first byte; the stream itself may stay open much longer.
+        metrics.observe_latency("anthropic", started.elapsed().as_secs_f64());
+        return Ok(with_quota(stream_response(stream, reservation, requested_model), quota));
+    }
+
+    let resp = match registry.forward_with(canonical, opts).await {
+        Ok(r) => r,
+        Err(e) => {
+            warn!(error=%e, "forward failed");
+            let (code, label, msg) = forward_error_status(&e);
+            metrics.inc_requests("anthropic", label);
+            return Ok(error(code, "api_error", msg));
+        }
+    };
+
+    metrics.inc_requests("anthropic", "200");
+    metrics.observe_latency("anthropic", started.elapsed().as_secs_f64());
+
+    reservation.settle(resp.usage);
+    if let Some(usage) = resp.usage {
+        metrics.observe_usage(&resp.adapter_id, usage);
+    }
+
+    let out = render_response(&resp, requested_model.as_deref());
+    Ok(with_quota(json(StatusCode::OK, &out), quota))
+}
+
+fn rejected(r: Rejected) -> Response<Body> {
+    let kind = match &r {
+        Rejected::RateLimited(_) => "rate_limit_error",
+        Rejected::Auth(_) if r.status() == StatusCode::FORBIDDEN => "permission_error",
+        Rejected::Auth(_) => "authentication_error",
+        Rejected::TooLarge(_) => "invalid_request_error",
+    };
+    let mut resp = error(r.status(), kind, &r.to_string());
+    r.set_headers(resp.headers_mut());
+    resp
+}
+
+/// Anthropic-style error envelope so SDKs surface a readable message.
+fn error(status: StatusCode, kind: &str, message: &str) -> Response<Body> {
+    json(status, &error_body(kind, message))
+}
+
+fn error_body(kind: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({ "type": "error", "error": { "type": kind, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, RateLimitConfig, RequestReplayConfig};
+
+    #[test]
+    fn maps_messages_request() {
+        let j = r#"{
+          "model": "claude-sonnet-4",
+          "max_tokens": 128,
+          "system": "be terse",
+          "messages": [
+            {"role": "user", "content": "weather?"},
+            {"role": "assistant", "content": [
+              {"type":"text","text":"checking"},
+              {"type":"tool_use","id":"tu1","name":"get_weather","input":{"city":"Austin"}}
+            ]},
+            {"role": "user", "content": [
+              {"type":"tool_result","tool_use_id":"tu1","content":"sunny"},
+              {"type":"text","text":"thanks"}
+            ]}
+          ],
+          "tools": [{"name":"get_weather","input_schema":{"type":"object"}}],
+          "tool_choice": {"type":"any"},
+          "stop_sequences": ["END"]
+        }"#;
+        let parsed: MessagesRequest = serde_json::from_str(j).unwrap();
+        let req = parsed.into_canonical();
+        let roles: Vec<_> = req.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(
+            roles,
+            vec![MessageRole::System, MessageRole::User, MessageRole::Assistant, MessageRole::Tool, MessageRole::User]
+        );
+        assert_eq!(req.messages[2].tool_calls[0].arguments, r#"{"city":"Austin"}"#);
+        assert_eq!(req.messages[3].tool_call_id.as_deref(), Some("tu1"));
+        assert_eq!(req.tool_choice.as_deref(), Some("required"));
+        assert_eq!(req.constraints.max_tokens, Some(128));
+        assert_eq!(req.constraints.stop_sequences, vec!["END".to_string()]);
+    }
+
+    #[test]
+    fn renders_message_shape() {
+        let mut resp = CanonicalAIResponse {
+            request_id: "r1".to_string(),
+            adapter_id: "python".to_string(),
+            model: None,
+            choices: vec![],
+            usage: None,
+            json: r#"{"echo_text":"hi"}"#.to_string(),
+        };
+        resp.fill_from_legacy_json();
+        let out = render_response(&resp, Some("claude"));
+        let v = serde_json::to_value(&out).unwrap();
+        assert_eq!(v["type"], "message");
+        assert_eq!(v["content"][0]["type"], "text");
+        assert_eq!(v["content"][0]["text"], "hi");
+        assert_eq!(v["model"], "claude");
+        assert_eq!(v["stop_reason"], "end_turn");
+    }
+
+    #[tokio::test]
+    async fn streams_message_events() {
+        let metrics = Metrics::new();
+        let registry = AdapterRegistryState::with_echo(RequestReplayConfig::default(), metrics.clone()).await;
+        let body = r#"{"model":"claude","max_tokens":64,"messages":[{"role":"user","content":"hello there"}],"stream":true}"#;
+        let req = Request::builder().method("POST").uri("/v1/messages").body(Body::from(body)).unwrap();
+        let resp = handle_messages(
+            req,
+            registry,
+            metrics,
+            Authenticator::new(&AuthConfig::default()).unwrap(),
+            RateLimits::new(&RateLimitConfig::default()).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+        let raw = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let events: Vec<(&str, serde_json::Value)> = std::str::from_utf8(&raw)
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|event| {
+                let (name, data) = event.strip_prefix("event: ").and_then(|e| e.split_once("\ndata: ")).expect(event);
+                let data: serde_json::Value = serde_json::from_str(data).unwrap();
+                assert_eq!(data["type"], name);
+                (name, data)
+            })
+            .collect();
+        let names: Vec<&str> = events.iter().map(|(n, _)| *n).filter(|n| *n != "content_block_delta").collect();
+        assert_eq!(
+            names,
+            ["message_start", "content_block_start", "content_block_stop", "message_delta", "message_stop"]
+        );
+        let start = &events[0].1["message"];
+        assert_eq!(start["model"], "claude");
+        assert!(start["id"].as_str().unwrap().starts_with("msg_"));
+        assert!(start["stop_reason"].is_null());
+        let text: String = events
+            .iter()
+            .filter(|(n, _)| *n == "content_block_delta")
+            .map(|(_, d)| d["delta"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(text, "hello there");
+        let (_, delta) = events.iter().find(|(n, _)| *n == "message_delta").unwrap();
+        assert_eq!(delta["delta"]["stop_reason"], "end_turn");
+    }
+}
@@ -0,0 +1,89 @@
+//! Refuses introspection when it is disabled and counts every limit a query breaks in
+//! `pagi_request_violations_total{protocol="graphql"}`.
+//!
+//! Depth and complexity are enforced by the schema builder itself; this extension only
+//! recognizes its errors.
+
+use std::sync::Arc;
+
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextValidation};
+use async_graphql::parser::types::{ExecutableDocument, Selection, SelectionSet};
+use async_graphql::{ServerError, ServerResult, ValidationResult, Variables};
+
+use crate::middleware::observability::Metrics;
+
+use super::PROTOCOL;
+
+// async-graphql's messages for `limit_complexity` / `limit_depth`.
+const TOO_COMPLEX: &str = "Query is too complex.";
+const TOO_DEEP: &str = "Query is nested too deep.";
+
+/// Fragments nested deeper than this are not searched for introspection fields.
+const MAX_FRAGMENT_NESTING: usize = 8;
+
+pub(crate) struct QueryLimits {
+    pub(crate) metrics: Metrics,
+    pub(crate) introspection: bool,
+}
+
+impl ExtensionFactory for QueryLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(LimitsExtension { metrics: self.metrics.clone(), introspection: self.introspection })
+    }
+}
+
+struct LimitsExtension {
+    metrics: Metrics,
+    introspection: bool,
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for LimitsExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let doc = next.run(ctx, query, variables).await?;
+        if !self.introspection && selects_introspection(&doc) {
+            self.metrics.inc_violations(PROTOCOL, "introspection");
+            return Err(ServerError::new("introspection is disabled", None));
+        }
+        Ok(doc)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await;
+        for e in result.as_ref().err().into_iter().flatten() {
+            match e.message.as_str() {
+                TOO_COMPLEX => self.metrics.inc_violations(PROTOCOL, "complexity"),
+                TOO_DEEP => self.metrics.inc_violations(PROTOCOL, "depth"),
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+/// Whether an operation selects `__schema` or `__type` at its root, directly or through
+/// fragments.
+fn selects_introspection(doc: &ExecutableDocument) -> bool {
+    fn at_root(set: &SelectionSet, doc: &ExecutableDocument, nesting: usize) -> bool {
+        nesting < MAX_FRAGMENT_NESTING
+            && set.items.iter().any(|s| match &s.node {
+                Selection::Field(f) => matches!(f.node.name.node.as_str(), "__schema" | "__type"),
+                Selection::InlineFragment(i) => at_root(&i.node.selection_set.node, doc, nesting + 1),
+                Selection::FragmentSpread(s) => doc
+                    .fragments
+                    .get(&s.node.fragment_name.node)
+                    .is_some_and(|f| at_root(&f.node.selection_set.node, doc, nesting + 1)),
+            })
+    }
+    doc.operations.iter().any(|(_, op)| at_root(&op.node.selection_set.node, doc, 0))
+}
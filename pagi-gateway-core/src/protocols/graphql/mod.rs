@@ -0,0 +1,696 @@
+//! GraphQL ingress: `POST /graphql`, plus subscriptions over WebSocket on `GET /graphql`.
+//!
+//! Subscriptions speak both `graphql-transport-ws` (the `graphql-ws` library) and the legacy
+//! `graphql-ws` protocol, picked from `sec-websocket-protocol`. Credentials come from the
+//! handshake headers (or `?access_token=`), else from the `connection_init` payload's
+//! `authorization` / `x-api-key`, since browser WebSocket clients cannot set headers.
+//!
+//! The read-side queries (`adapters`, `adapterStats`, `requests`, `config`) are for operators:
+//! they need `Authorization: Bearer <core.graphql.operator_token>`, or a JWT carrying
+//! `core.graphql.operator_scope`. Without either configured they are refused.
+//!
+//! `core.graphql` bounds what a query may cost: body size, depth, complexity and introspection,
+//! with violations counted in `pagi_request_violations_total`. Automatic persisted queries
+//! (Apollo's `extensions.persistedQuery`) are cached in memory.
+
+mod limits;
+pub mod types;
+
+use std::sync::Arc;
+
+use async_graphql::extensions::apollo_persisted_queries::{ApolloPersistedQueries, LruCacheStorage};
+use async_graphql::http::{WebSocket as GqlWebSocket, WebSocketProtocols, WsMessage};
+use async_graphql::{Context, Data, ErrorExtensions, Guard, Json, Object, Request as GqlRequest, Schema, Subscription, Variables};
+use futures_util::{future, SinkExt, Stream, StreamExt};
+use subtle::ConstantTimeEq;
+use hyper::header::{HeaderValue, AUTHORIZATION, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_PROTOCOL, UPGRADE};
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, HeaderMap, Method, Request, Response, StatusCode};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+use tracing::warn;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::{Config, GraphqlConfig};
+use crate::middleware::auth::{bearer, AuthError, Authenticator, Principal};
+use crate::middleware::observability::Metrics;
+use crate::middleware::rate_limit::RateLimits;
+use crate::middleware::token_limit::TokenReservation;
+use crate::registry::{AdapterRegistryState, ForwardOptions, ReplayFilter};
+
+use super::{forward_error_status, relay, remote_addr, Admitted, Caller, Gate, Rejected};
+use super::ws::{credentials, upgrade_key};
+use limits::QueryLimits;
+use types::{AIChunk, AIRequestInput, AIResponse, Adapter, AdapterStats, ReplayedRequest};
+
+pub type SchemaType = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+const PROTOCOL: &str = "graphql";
+
+/// The loaded config with defaults filled in and secrets redacted.
+struct EffectiveConfig(serde_json::Value);
+
+/// Marks a caller allowed to run the operator queries.
+struct Operator;
+
+impl Guard for Operator {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        match ctx.data_opt::<Operator>() {
+            Some(_) => Ok(()),
+            None => Err(status_error(StatusCode::FORBIDDEN, "operator access required")),
+        }
+    }
+}
+
+/// The credentials that make a caller an [`Operator`], from `core.graphql`.
+#[derive(Clone, Default)]
+struct OperatorAuth {
+    token: Option<Arc<[u8]>>,
+    scope: Option<String>,
+}
+
+impl OperatorAuth {
+    fn new(cfg: &GraphqlConfig) -> Self {
+        let token = cfg.operator_token.as_ref().and_then(|t| {
+            let token = t.resolve();
+            if token.is_none() {
+                warn!("operator_token is empty; graphql operator token disabled");
+            }
+            token.map(|t| Arc::from(t.into_bytes()))
+        });
+        Self { token, scope: cfg.operator_scope.clone() }
+    }
+
+    fn has_token(&self, headers: &HeaderMap) -> bool {
+        match (&self.token, bearer(headers)) {
+            (Some(expected), Some(presented)) => expected.ct_eq(presented.as_bytes()).into(),
+            _ => false,
+        }
+    }
+
+    /// Whether a JWT's `scope` (space separated) or `scp` claim grants the operator scope.
+    fn has_scope(&self, p: &Principal) -> bool {
+        let Some(want) = &self.scope else {
+            return false;
+        };
+        ["scope", "scp"].into_iter().filter_map(|k| p.claims.get(k)).any(|v| match v {
+            serde_json::Value::String(s) => s.split_whitespace().any(|s| s == want),
+            serde_json::Value::Array(a) => a.iter().any(|s| s.as_str() == Some(want)),
+            _ => false,
+        })
+    }
+}
+
+pub fn build_schema(
+    config: &Config,
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+) -> SchemaType {
+    let gql = &config.core.graphql;
+    let mut builder = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .limit_depth(gql.max_depth)
+        .limit_complexity(gql.max_complexity)
+        .extension(QueryLimits { metrics: metrics.clone(), introspection: gql.introspection })
+        .data(EffectiveConfig(serde_json::to_value(config).expect("serialize config")))
+        .data(OperatorAuth::new(gql))
+        .data(gql.clone())
+        .data(registry)
+        .data(metrics)
+        .data(auth)
+        .data(limits);
+    if !gql.introspection {
+        builder = builder.disable_introspection();
+    }
+    if gql.persisted_queries > 0 {
+        builder = builder.extension(ApolloPersistedQueries::new(LruCacheStorage::new(gql.persisted_queries)));
+    }
+    builder.finish()
+}
+
+#[derive(serde::Deserialize)]
+struct HttpGraphQLRequest {
+    /// May be left out when `extensions.persistedQuery` names a cached query.
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    variables: serde_json::Value,
+    #[serde(default, rename = "operationName")]
+    operation_name: Option<String>,
+    #[serde(default)]
+    extensions: async_graphql::Extensions,
+}
+
+pub async fn handle(
+    req: Request<Body>,
+    schema: SchemaType,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+) -> Result<Response<Body>, hyper::Error> {
+    let max_body = schema.data::<GraphqlConfig>().map_or(usize::MAX, |c| c.max_body_bytes);
+    match *req.method() {
+        Method::GET if upgrade_key(&req).is_some() => {
+            if let Err(r) = Gate::new(&auth, &limits, &metrics, PROTOCOL).peer(remote_addr(&req), req.headers()) {
+                let mut resp = graphql_error(r.status(), &r.to_string());
+                r.set_headers(resp.headers_mut());
+                return Ok(resp);
+            }
+            Ok(upgrade(req, schema, metrics, auth, max_body))
+        }
+        Method::GET => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(
+                "GraphQL endpoint. Send POST /graphql with {query, variables, operationName}, or subscribe over graphql-ws.",
+            ))
+            .unwrap()),
+        Method::POST => {
+            if let Err(r) = Gate::new(&auth, &limits, &metrics, PROTOCOL).peer(remote_addr(&req), req.headers()) {
+                let mut resp = graphql_error(r.status(), &r.to_string());
+                r.set_headers(resp.headers_mut());
+                return Ok(resp);
+            }
+            let operators = schema.data::<OperatorAuth>().cloned().unwrap_or_default();
+            let data = match identify(&auth, &operators, req.headers()).await {
+                Ok(d) => d,
+                Err(e) => return Ok(graphql_error(e.status(), &e.to_string())),
+            };
+            let Some(body) = read_body(req, max_body).await? else {
+                metrics.inc_violations(PROTOCOL, "body_size");
+                return Ok(graphql_error(StatusCode::PAYLOAD_TOO_LARGE, "request body too large"));
+            };
+            let parsed: HttpGraphQLRequest = match serde_json::from_slice(&body) {
+                Ok(v) => v,
+                Err(_) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from("invalid graphql http request"))
+                        .unwrap());
+                }
+            };
+
+            let mut gql = GqlRequest::new(parsed.query);
+            if let Some(op) = parsed.operation_name {
+                gql = gql.operation_name(op);
+            }
+            if !parsed.variables.is_null() {
+                if let Ok(vars) = serde_json::from_value::<Variables>(parsed.variables) {
+                    gql = gql.variables(vars);
+                }
+            }
+
+            gql.extensions = parsed.extensions;
+            gql.data = data;
+
+            let resp = schema.execute(gql).await;
+            let out = serde_json::to_vec(&resp).expect("serialize graphql response");
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(Body::from(out))
+                .unwrap())
+        }
+        _ => Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Body::from("method not allowed"))
+            .unwrap()),
+    }
+}
+
+/// Switch to a GraphQL-over-WebSocket connection.
+fn upgrade(req: Request<Body>, schema: SchemaType, metrics: Metrics, auth: Authenticator, max_message: usize) -> Response<Body> {
+    let offered = req.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let Some(protocol) = offered.split(',').find_map(|p| p.trim().parse::<WebSocketProtocols>().ok()) else {
+        return graphql_error(StatusCode::BAD_REQUEST, "unsupported sec-websocket-protocol");
+    };
+    let key = upgrade_key(&req).unwrap_or_default();
+    let headers = credentials(&req);
+    let operators = schema.data::<OperatorAuth>().cloned().unwrap_or_default();
+
+    tokio::spawn(async move {
+        let upgraded = match hyper::upgrade::on(req).await {
+            Ok(u) => u,
+            Err(e) => return warn!(error=%e, "graphql websocket upgrade failed"),
+        };
+        let cfg = WebSocketConfig {
+            max_message_size: Some(max_message),
+            max_frame_size: Some(max_message),
+            ..Default::default()
+        };
+        let (mut sink, stream) = WebSocketStream::from_raw_socket(upgraded, Role::Server, Some(cfg)).await.split();
+        let input = stream
+            .inspect(move |m| {
+                if let Err(WsError::Capacity(_)) = m {
+                    metrics.inc_violations(PROTOCOL, "body_size");
+                }
+            })
+            .take_while(|m| future::ready(matches!(m, Ok(m) if !m.is_close())))
+            .filter_map(|m| {
+                future::ready(match m {
+                    Ok(Message::Text(t)) => Some(t.into_bytes()),
+                    Ok(Message::Binary(b)) => Some(b),
+                    _ => None,
+                })
+            });
+        let mut output = GqlWebSocket::new(schema, input, protocol)
+            .on_connection_init(move |payload| connection_data(auth, operators, headers, payload));
+        while let Some(msg) = output.next().await {
+            let msg = match msg {
+                WsMessage::Text(t) => Message::Text(t),
+                WsMessage::Close(code, reason) => {
+                    Message::Close(Some(CloseFrame { code: CloseCode::from(code), reason: reason.into() }))
+                }
+            };
+            if sink.send(msg).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_ACCEPT, derive_accept_key(key.as_bytes()))
+        .header(SEC_WEBSOCKET_PROTOCOL, protocol.sec_websocket_protocol())
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Authenticate a subscription connection; an error closes it.
+async fn connection_data(
+    auth: Authenticator,
+    operators: OperatorAuth,
+    mut headers: hyper::HeaderMap,
+    payload: serde_json::Value,
+) -> async_graphql::Result<Data> {
+    if !headers.contains_key(AUTHORIZATION) && !headers.contains_key("x-api-key") {
+        for name in [AUTHORIZATION.as_str(), "x-api-key"] {
+            if let Some(v) = payload.get(name).and_then(|v| v.as_str()).and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, v);
+            }
+        }
+    }
+    Ok(identify(&auth, &operators, &headers).await.map_err(|e| e.to_string())?)
+}
+
+/// Request data for a caller. The operator token makes an operator without a principal; anyone
+/// else is authenticated as usual, and is an operator only if their JWT has the operator scope.
+async fn identify(auth: &Authenticator, operators: &OperatorAuth, headers: &HeaderMap) -> Result<Data, AuthError> {
+    let mut data = Data::default();
+    if operators.has_token(headers) {
+        data.insert(Operator);
+        return Ok(data);
+    }
+    if let Some(p) = auth.authenticate(headers).await? {
+        if operators.has_scope(&p) {
+            data.insert(Operator);
+        }
+        data.insert(p);
+    }
+    Ok(data)
+}
+
+/// The body of `req`, or `None` if it is longer than `max` bytes.
+async fn read_body(req: Request<Body>, max: usize) -> Result<Option<Bytes>, hyper::Error> {
+    let declared = req.headers().get(hyper::header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse::<usize>().ok());
+    if declared.is_some_and(|n| n > max) {
+        return Ok(None);
+    }
+    let mut body = req.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > max {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Some(buf.into()))
+}
+
+/// A request-level failure in GraphQL's response shape.
+fn graphql_error(status: StatusCode, message: &str) -> Response<Body> {
+    let out = serde_json::json!({ "errors": [{ "message": message }] });
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(out.to_string()))
+        .unwrap()
+}
+
+/// A field error carrying the HTTP status other protocols would answer with.
+fn status_error(status: StatusCode, message: impl Into<String>) -> async_graphql::Error {
+    async_graphql::Error::new(message).extend_with(|_, e| e.set("status", status.as_u16()))
+}
+
+/// Authorize, rate limit and reserve tokens for a request made through the schema.
+fn admit(ctx: &Context<'_>, mut req: CanonicalAIRequest) -> async_graphql::Result<(CanonicalAIRequest, ForwardOptions, TokenReservation)> {
+    let metrics = ctx.data::<Metrics>()?;
+    if req.messages.is_empty() {
+        metrics.inc_requests(PROTOCOL, "400");
+        return Err(status_error(StatusCode::BAD_REQUEST, "messages required"));
+    }
+    let gate = Gate::new(ctx.data::<Authenticator>()?, ctx.data::<RateLimits>()?, metrics, PROTOCOL);
+    let caller = Caller { principal: ctx.data_opt::<Principal>().cloned(), ..Default::default() };
+    let mut opts = ForwardOptions::default();
+    let Admitted { reservation, .. } = gate.admit(&caller, &mut req, &mut opts).map_err(|r| {
+        let e = status_error(r.status(), r.to_string());
+        match r {
+            Rejected::RateLimited(l) => e.extend_with(|_, x| x.set("retryAfter", l.retry_after.as_secs_f64().ceil() as u64)),
+            _ => e,
+        }
+    })?;
+    Ok((req, opts, reservation))
+}
+
+fn forward_failed(metrics: &Metrics, e: anyhow::Error) -> async_graphql::Error {
+    warn!(error=%e, "forward failed");
+    let (status, label, msg) = forward_error_status(&e);
+    metrics.inc_requests(PROTOCOL, label);
+    status_error(status, msg)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn ping(&self) -> &str {
+        "pong"
+    }
+
+    /// Registered adapters with their capabilities, health and version.
+    #[graphql(guard = "Operator")]
+    async fn adapters(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Adapter>> {
+        Ok(ctx.data::<AdapterRegistryState>()?.list().await.into_iter().map(Into::into).collect())
+    }
+
+    /// Live counters per registered adapter.
+    #[graphql(guard = "Operator")]
+    async fn adapter_stats(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AdapterStats>> {
+        Ok(ctx.data::<AdapterRegistryState>()?.stats().await.into_iter().map(Into::into).collect())
+    }
+
+    /// Recent requests from the replay log, newest first. `since` and `until` are unix seconds.
+    #[graphql(guard = "Operator")]
+    async fn requests(
+        &self,
+        ctx: &Context<'_>,
+        agent_id: Option<String>,
+        session_id: Option<String>,
+        since: Option<u64>,
+        until: Option<u64>,
+        #[graphql(default = 50, validator(maximum = 500))] limit: usize,
+    ) -> async_graphql::Result<Vec<ReplayedRequest>> {
+        let filter = ReplayFilter { agent_id, session_id, since, until };
+        let entries = ctx
+            .data::<AdapterRegistryState>()?
+            .replay_history(&filter, limit)
+            .await
+            .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, format!("reading replay log: {e}")))?;
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
+
+    /// The effective config, defaults included, with secrets redacted.
+    #[graphql(guard = "Operator")]
+    async fn config(&self, ctx: &Context<'_>) -> async_graphql::Result<Json<serde_json::Value>> {
+        Ok(Json(ctx.data::<EffectiveConfig>()?.0.clone()))
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Run a canonical request and return the structured response.
+    async fn call(&self, ctx: &Context<'_>, request: AIRequestInput) -> async_graphql::Result<AIResponse> {
+        let req = request.into_canonical().map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+        let (req, opts, reservation) = admit(ctx, req)?;
+        let metrics = ctx.data::<Metrics>()?;
+        let resp = ctx.data::<AdapterRegistryState>()?.forward_with(req, opts).await.map_err(|e| forward_failed(metrics, e))?;
+        metrics.inc_requests(PROTOCOL, "200");
+        reservation.settle(resp.usage);
+        Ok(resp.into())
+    }
+
+    #[graphql(deprecation = "use `call`")]
+    async fn ai_call(&self, ctx: &Context<'_>, agent_id: String, text: String) -> async_graphql::Result<String> {
+        let (req, opts, reservation) = admit(ctx, CanonicalAIRequest::chat_text(Some(agent_id), text))?;
+        let metrics = ctx.data::<Metrics>()?;
+        let resp = ctx.data::<AdapterRegistryState>()?.forward_with(req, opts).await.map_err(|e| forward_failed(metrics, e))?;
+        metrics.inc_requests(PROTOCOL, "200");
+        reservation.settle(resp.usage);
+        Ok(resp.json)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Stream a canonical request's response as it is generated.
+    async fn call_stream(
+        &self,
+        ctx: &Context<'_>,
+        request: AIRequestInput,
+    ) -> async_graphql::Result<impl Stream<Item = async_graphql::Result<AIChunk>>> {
+        let req = request.into_canonical().map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+        let (req, opts, reservation) = admit(ctx, req)?;
+        let metrics = ctx.data::<Metrics>()?;
+        let stream =
+            ctx.data::<AdapterRegistryState>()?.forward_stream_with(req, opts).await.map_err(|e| forward_failed(metrics, e))?;
+        metrics.inc_requests(PROTOCOL, "200");
+        let rx = relay(stream, reservation, |item| {
+            item.map(AIChunk::from).map_err(|e| status_error(StatusCode::BAD_GATEWAY, e.to_string()))
+        });
+        Ok(ReceiverStream::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RateLimitConfig, RequestReplayConfig};
+    use serde_json::json;
+
+    async fn schema() -> SchemaType {
+        schema_with("graphql: {}").await
+    }
+
+    /// A schema over the echo adapter; `core` adds fields to the `core` config section.
+    async fn schema_with(core: &str) -> SchemaType {
+        let config: Config =
+            serde_yaml::from_str(&format!("version: v1\ncore: {{ bind_http: '127.0.0.1:0', bind_grpc: '127.0.0.1:0', {core} }}"))
+                .unwrap();
+        let replay = RequestReplayConfig {
+            enabled: true,
+            path: std::env::temp_dir().join(format!("pagi-gql-replay-{}", uuid::Uuid::new_v4())).to_string_lossy().into_owned(),
+        };
+        let registry = AdapterRegistryState::with_echo(replay, Metrics::new()).await;
+        build_schema(
+            &config,
+            registry,
+            Metrics::new(),
+            Authenticator::new(&config.core.auth).unwrap(),
+            RateLimits::new(&RateLimitConfig::default()).unwrap(),
+        )
+    }
+
+    const REQUEST: &str = r#"{ sessionId: "s1", messages: [{ role: USER, content: [{ text: "hello there" }] }],
+        constraints: { maxTokens: 16 }, metadata: [{ key: "k", value: "v" }] }"#;
+
+    #[tokio::test]
+    async fn call_returns_typed_response() {
+        let schema = schema().await;
+        let query = format!(
+            "mutation {{ call(request: {REQUEST}) {{ adapterId text choices {{ message {{ role content {{ ... on TextPart {{ text }} }} }} }} }} }}"
+        );
+        let resp = schema.execute(query).await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        let data = resp.data.into_json().unwrap();
+        assert_eq!(data["call"]["adapterId"], "echo");
+        assert_eq!(data["call"]["choices"][0]["message"]["role"], "ASSISTANT");
+
+        let resp = schema.execute(r#"mutation { call(request: { requestId: "nope", messages: [] }) { text } }"#).await;
+        assert_eq!(resp.errors[0].extensions.as_ref().unwrap().get("status"), Some(&async_graphql::Value::from(400)));
+    }
+
+    #[tokio::test]
+    async fn operator_queries_read_registry_and_replay_log() {
+        let schema = schema().await;
+        let resp = schema.execute("{ adapters { id } }").await;
+        assert_eq!(resp.errors[0].message, "operator access required");
+
+        let call = format!(r#"mutation {{ call(request: {{ agentId: "bot", {} }}) {{ text }} }}"#, &REQUEST[2..REQUEST.len() - 2]);
+        assert!(schema.execute(call).await.errors.is_empty());
+        let query = r#"{
+            adapters { id origin health capabilities { streaming } }
+            adapterStats { adapterId attempts failures }
+            requests(agentId: "bot") { sessionId receivedAt metadata { key value } messages { text } }
+            config
+        }"#;
+        let resp = schema.execute(GqlRequest::new(query).data(Operator)).await;
+        assert!(resp.errors.is_empty(), "{:?}", resp.errors);
+        let data = resp.data.into_json().unwrap();
+        assert_eq!(data["adapters"], json!([{ "id": "echo", "origin": "STATIC", "health": "HEALTHY", "capabilities": { "streaming": true } }]));
+        assert_eq!(data["adapterStats"], json!([{ "adapterId": "echo", "attempts": 1, "failures": 0 }]));
+        let req = &data["requests"][0];
+        assert_eq!(req["sessionId"], "s1");
+        assert_eq!(req["messages"][0]["text"], "hello there");
+        assert_eq!(req["metadata"], json!([{ "key": "k", "value": "v" }]));
+        assert!(req["receivedAt"].as_u64().is_some());
+        assert_eq!(data["config"]["core"]["request_replay"]["enabled"], false);
+    }
+
+    #[tokio::test]
+    async fn operator_queries_need_an_operator_credential() {
+        let query = json!({ "query": "{ adapters { id } }" });
+        let denied = |body: serde_json::Value| body["errors"][0]["message"] == "operator access required";
+
+        // No auth and no operator credential configured: nobody is an operator.
+        assert!(denied(post(&schema().await, query.clone()).await.1));
+
+        let schema = schema_with("graphql: { operator_token: { token: op-secret } }").await;
+        assert!(denied(post_as(&schema, Some("guess"), query.clone()).await.1));
+        let (_, body) = post_as(&schema, Some("op-secret"), query.clone()).await;
+        assert_eq!(body["data"]["adapters"], json!([{ "id": "echo" }]));
+
+        let jwks = crate::middleware::jwt::tests::write_jwks("gql-operator", &[("k1", "secret")]);
+        let schema = schema_with(&format!("graphql: {{ operator_scope: 'pagi:operator' }}, auth: {{ jwt: {{ jwks_path: '{jwks}' }} }}")).await;
+        let jwt = |scope: &str| {
+            let claims = json!({ "sub": "u1", "scope": scope, "exp": crate::middleware::jwt::tests::now() + 60 });
+            crate::middleware::jwt::tests::token("k1", "secret", claims)
+        };
+        assert!(denied(post_as(&schema, Some(&jwt("openid")), query.clone()).await.1));
+        let (_, body) = post_as(&schema, Some(&jwt("openid pagi:operator")), query).await;
+        assert_eq!(body["data"]["adapters"], json!([{ "id": "echo" }]));
+    }
+
+    #[tokio::test]
+    async fn subscription_streams_chunks() {
+        let schema = schema().await;
+        let query = format!("subscription {{ callStream(request: {REQUEST}) {{ delta done adapterId }} }}");
+        let chunks: Vec<_> = schema.execute_stream(query).collect().await;
+        let mut text = String::new();
+        for c in &chunks {
+            assert!(c.errors.is_empty(), "{:?}", c.errors);
+            text.push_str(c.data.clone().into_json().unwrap()["callStream"]["delta"].as_str().unwrap());
+        }
+        assert_eq!(text.trim(), "hello there");
+        let last = chunks.last().unwrap().data.clone().into_json().unwrap();
+        assert_eq!(last["callStream"], json!({ "delta": last["callStream"]["delta"], "done": true, "adapterId": "echo" }));
+    }
+
+    #[tokio::test]
+    async fn subscribes_over_graphql_transport_ws() {
+        use hyper::service::{make_service_fn, service_fn};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let schema = schema().await;
+        let make_svc = make_service_fn(move |_| {
+            let schema = schema.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req| {
+                    let auth = schema.data::<Authenticator>().unwrap().clone();
+                    let limits = schema.data::<RateLimits>().unwrap().clone();
+                    let metrics = schema.data::<Metrics>().unwrap().clone();
+                    handle(req, schema.clone(), metrics, auth, limits)
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let mut req = format!("ws://{addr}/graphql").into_client_request().unwrap();
+        req.headers_mut().insert("sec-websocket-protocol", "graphql-transport-ws".parse().unwrap());
+        let (mut ws, _) = tokio_tungstenite::connect_async(req).await.unwrap();
+        ws.send(Message::Text(json!({ "type": "connection_init" }).to_string())).await.unwrap();
+        let next = |m: Message| serde_json::from_str::<serde_json::Value>(m.to_text().unwrap()).unwrap();
+        assert_eq!(next(ws.next().await.unwrap().unwrap())["type"], "connection_ack");
+
+        let query = format!("subscription {{ callStream(request: {REQUEST}) {{ delta done }} }}");
+        ws.send(Message::Text(json!({ "type": "subscribe", "id": "1", "payload": { "query": query } }).to_string()))
+            .await
+            .unwrap();
+        let mut text = String::new();
+        loop {
+            let m = next(ws.next().await.unwrap().unwrap());
+            match m["type"].as_str().unwrap() {
+                "next" => text.push_str(m["payload"]["data"]["callStream"]["delta"].as_str().unwrap()),
+                "complete" => break,
+                other => panic!("unexpected {other}: {m}"),
+            }
+        }
+        assert_eq!(text.trim(), "hello there");
+    }
+
+    async fn post(schema: &SchemaType, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        post_as(schema, None, body).await
+    }
+
+    async fn post_as(schema: &SchemaType, bearer: Option<&str>, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let mut req = Request::post("/graphql");
+        if let Some(token) = bearer {
+            req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        let (metrics, auth, limits) = (
+            schema.data::<Metrics>().unwrap().clone(),
+            schema.data::<Authenticator>().unwrap().clone(),
+            schema.data::<RateLimits>().unwrap().clone(),
+        );
+        let resp = handle(req, schema.clone(), metrics, auth, limits).await.unwrap();
+        let status = resp.status();
+        (status, serde_json::from_slice(&hyper::body::to_bytes(resp.into_body()).await.unwrap()).unwrap())
+    }
+
+    async fn violations(schema: &SchemaType, reason: &str) -> String {
+        let body = hyper::body::to_bytes(schema.data::<Metrics>().unwrap().render().into_body()).await.unwrap();
+        let needle = format!("pagi_request_violations_total{{protocol=\"graphql\",reason=\"{reason}\"}} ");
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        text.lines().find_map(|l| l.strip_prefix(&needle)).unwrap_or("0").to_string()
+    }
+
+    #[tokio::test]
+    async fn limits_are_enforced_and_counted() {
+        let schema = schema_with("graphql: { max_depth: 2, introspection: false, max_body_bytes: 200 }").await;
+        let op = GqlRequest::new("{ adapters { capabilities { streaming } } }").data(Operator);
+        assert_eq!(schema.execute(op).await.errors[0].message, "Query is nested too deep.");
+        let resp = schema.execute("{ __schema { queryType { name } } }").await;
+        assert_eq!(resp.errors[0].message, "introspection is disabled");
+        assert_eq!(schema.execute("{ __typename ping }").await.errors.len(), 0);
+
+        let (status, _) = post(&schema, json!({ "query": format!("{{ ping {} }}", " ".repeat(200)) })).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        assert_eq!(violations(&schema, "depth").await, "1");
+        assert_eq!(violations(&schema, "introspection").await, "1");
+        assert_eq!(violations(&schema, "body_size").await, "1");
+    }
+
+    #[tokio::test]
+    async fn persisted_queries_are_cached_by_hash() {
+        let schema = schema().await;
+        let query = "{ ping }";
+        let hash = ring::digest::digest(&ring::digest::SHA256, query.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let ext = json!({ "persistedQuery": { "version": 1, "sha256Hash": hash } });
+
+        let (_, body) = post(&schema, json!({ "extensions": ext })).await;
+        assert_eq!(body["errors"][0]["message"], "PersistedQueryNotFound");
+        let (_, body) = post(&schema, json!({ "query": query, "extensions": ext })).await;
+        assert_eq!(body["data"]["ping"], "pong");
+        let (_, body) = post(&schema, json!({ "extensions": ext })).await;
+        assert_eq!(body["data"]["ping"], "pong");
+    }
+}
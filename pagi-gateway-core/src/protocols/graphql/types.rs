@@ -0,0 +1,482 @@
+//! GraphQL input and output types over the canonical model.
+//!
+//! Inputs mirror [`CanonicalAIRequest`]; where GraphQL has no equivalent the shape is adjusted:
+//! content parts are `@oneOf` inputs and a union on output, metadata is a list of key/value
+//! entries, and free-form JSON (tool schemas, `responseFormat`) uses the `JSON` scalar.
+//!
+//! The operator types at the end describe registry and replay-log state.
+
+use async_graphql::{Enum, InputObject, Json, OneofObject, SimpleObject, Union, ID};
+use uuid::Uuid;
+
+use crate::canonical::{self, CanonicalAIRequest};
+use crate::proto::{self, CanonicalAiChunk};
+use crate::registry::{self, AdapterStatsSnapshot, ReplayEntry};
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "canonical::MessageRole")]
+pub enum MessageRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "canonical::FinishReason")]
+pub enum FinishReason {
+    Stop,
+    Length,
+    ToolCalls,
+    ContentFilter,
+    Error,
+}
+
+/// Exactly one of the fields is set.
+#[derive(OneofObject, Debug)]
+pub enum ContentPartInput {
+    Text(String),
+    ImageUrl(String),
+    AudioUrl(String),
+    File(FileInput),
+}
+
+#[derive(InputObject, Debug)]
+pub struct FileInput {
+    pub url: String,
+    pub mime_type: String,
+}
+
+#[derive(InputObject, Debug)]
+pub struct ToolCallInput {
+    pub id: String,
+    pub name: String,
+    /// JSON-encoded arguments.
+    #[graphql(default)]
+    pub arguments: String,
+}
+
+#[derive(InputObject, Debug)]
+pub struct MessageInput {
+    pub role: MessageRole,
+    #[graphql(default)]
+    pub content: Vec<ContentPartInput>,
+    pub name: Option<String>,
+    pub tool_call_id: Option<String>,
+    #[graphql(default)]
+    pub tool_calls: Vec<ToolCallInput>,
+}
+
+#[derive(InputObject, Debug)]
+pub struct ToolInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub parameters_json_schema: Option<Json<serde_json::Value>>,
+    #[graphql(default)]
+    pub strict: bool,
+}
+
+#[derive(InputObject, Debug, Default)]
+pub struct ConstraintsInput {
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    #[graphql(default)]
+    pub stop_sequences: Vec<String>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub reasoning_effort: Option<String>,
+}
+
+#[derive(InputObject, Debug)]
+pub struct MetadataEntryInput {
+    pub key: String,
+    pub value: String,
+}
+
+/// A canonical AI request. `requestId` defaults to a fresh UUID.
+#[derive(InputObject, Debug)]
+pub struct AIRequestInput {
+    pub request_id: Option<ID>,
+    pub agent_id: Option<String>,
+    pub session_id: Option<String>,
+    pub messages: Vec<MessageInput>,
+    #[graphql(default)]
+    pub tools: Vec<ToolInput>,
+    /// "auto" | "required" | "none" | <tool name>
+    pub tool_choice: Option<String>,
+    pub constraints: Option<ConstraintsInput>,
+    pub preferred_model: Option<String>,
+    #[graphql(default)]
+    pub metadata: Vec<MetadataEntryInput>,
+    pub response_format: Option<Json<serde_json::Value>>,
+}
+
+impl AIRequestInput {
+    pub fn into_canonical(self) -> Result<CanonicalAIRequest, String> {
+        let mut req = CanonicalAIRequest::new();
+        if let Some(id) = self.request_id {
+            req.request_id = Uuid::parse_str(&id).map_err(|_| "requestId must be a UUID".to_string())?;
+        }
+        req.agent_id = self.agent_id;
+        req.session_id = self.session_id;
+        req.messages = self
+            .messages
+            .into_iter()
+            .map(|m| canonical::Message {
+                role: m.role.into(),
+                content: m
+                    .content
+                    .into_iter()
+                    .map(|p| match p {
+                        ContentPartInput::Text(text) => canonical::ContentPart::Text { text },
+                        ContentPartInput::ImageUrl(url) => canonical::ContentPart::Image { url },
+                        ContentPartInput::AudioUrl(url) => canonical::ContentPart::Audio { url },
+                        ContentPartInput::File(f) => canonical::ContentPart::File { url: f.url, mime_type: f.mime_type },
+                    })
+                    .collect(),
+                name: m.name,
+                tool_call_id: m.tool_call_id,
+                tool_calls: m
+                    .tool_calls
+                    .into_iter()
+                    .map(|c| canonical::ToolCall { id: c.id, name: c.name, arguments: c.arguments })
+                    .collect(),
+            })
+            .collect();
+        req.tools = self
+            .tools
+            .into_iter()
+            .map(|t| canonical::Tool {
+                name: t.name,
+                description: t.description,
+                parameters_json_schema: t.parameters_json_schema.map(|s| s.0),
+                strict: t.strict,
+            })
+            .collect();
+        req.tool_choice = self.tool_choice;
+        let c = self.constraints.unwrap_or_default();
+        req.constraints = canonical::GenerationConstraints {
+            max_tokens: c.max_tokens,
+            temperature: c.temperature,
+            top_p: c.top_p,
+            top_k: c.top_k,
+            stop_sequences: c.stop_sequences,
+            presence_penalty: c.presence_penalty,
+            frequency_penalty: c.frequency_penalty,
+            reasoning_effort: c.reasoning_effort,
+            stream: false,
+        };
+        req.preferred_model = self.preferred_model;
+        req.metadata = self.metadata.into_iter().map(|e| (e.key, e.value)).collect();
+        req.response_format = self.response_format.map(|f| f.0);
+        Ok(req)
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct TextPart {
+    pub text: String,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ImagePart {
+    pub url: String,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct AudioPart {
+    pub url: String,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct FilePart {
+    pub url: String,
+    pub mime_type: String,
+}
+
+#[derive(Union, Debug)]
+pub enum ContentPart {
+    Text(TextPart),
+    Image(ImagePart),
+    Audio(AudioPart),
+    File(FilePart),
+}
+
+impl From<canonical::ContentPart> for ContentPart {
+    fn from(p: canonical::ContentPart) -> Self {
+        match p {
+            canonical::ContentPart::Text { text } => ContentPart::Text(TextPart { text }),
+            canonical::ContentPart::Image { url } => ContentPart::Image(ImagePart { url }),
+            canonical::ContentPart::Audio { url } => ContentPart::Audio(AudioPart { url }),
+            canonical::ContentPart::File { url, mime_type } => ContentPart::File(FilePart { url, mime_type }),
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// JSON-encoded arguments.
+    pub arguments: String,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct Message {
+    pub role: MessageRole,
+    pub content: Vec<ContentPart>,
+    /// Concatenated text parts.
+    pub text: String,
+    pub name: Option<String>,
+    pub tool_call_id: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl From<canonical::Message> for Message {
+    fn from(m: canonical::Message) -> Self {
+        Self {
+            text: m.text(),
+            role: m.role.into(),
+            content: m.content.into_iter().map(Into::into).collect(),
+            name: m.name,
+            tool_call_id: m.tool_call_id,
+            tool_calls: m.tool_calls.into_iter().map(|c| ToolCall { id: c.id, name: c.name, arguments: c.arguments }).collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct Choice {
+    pub index: u32,
+    pub message: Message,
+    pub finish_reason: Option<FinishReason>,
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub cached_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl From<canonical::Usage> for Usage {
+    fn from(u: canonical::Usage) -> Self {
+        Self {
+            total_tokens: u.total_tokens(),
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            cached_tokens: u.cached_tokens,
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct AIResponse {
+    pub request_id: String,
+    pub adapter_id: String,
+    pub model: Option<String>,
+    pub choices: Vec<Choice>,
+    pub usage: Option<Usage>,
+    /// Text of the first choice.
+    pub text: String,
+    /// Opaque adapter payload.
+    pub json: String,
+}
+
+impl From<canonical::CanonicalAIResponse> for AIResponse {
+    fn from(r: canonical::CanonicalAIResponse) -> Self {
+        Self {
+            text: r.text(),
+            request_id: r.request_id,
+            adapter_id: r.adapter_id,
+            model: r.model,
+            choices: r
+                .choices
+                .into_iter()
+                .map(|c| Choice {
+                    index: c.index,
+                    message: c.message.into(),
+                    finish_reason: c.finish_reason.map(Into::into),
+                    stop_sequence: c.stop_sequence,
+                })
+                .collect(),
+            usage: r.usage.map(Into::into),
+            json: r.json,
+        }
+    }
+}
+
+/// One piece of a streamed response; the last has `done = true`.
+#[derive(SimpleObject, Debug)]
+pub struct AIChunk {
+    pub request_id: String,
+    pub adapter_id: String,
+    pub delta: String,
+    pub done: bool,
+    /// Set on the final chunk.
+    pub finish_reason: Option<String>,
+    pub json: Option<String>,
+}
+
+impl From<CanonicalAiChunk> for AIChunk {
+    fn from(c: CanonicalAiChunk) -> Self {
+        Self {
+            request_id: c.request_id,
+            adapter_id: c.adapter_id,
+            delta: c.delta,
+            done: c.done,
+            finish_reason: Some(c.finish_reason).filter(|r| !r.is_empty()),
+            json: Some(c.json).filter(|j| !j.is_empty()),
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdapterHealth {
+    Healthy,
+    /// Missed its lease; skipped by routing until it heartbeats again.
+    Unhealthy,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdapterOrigin {
+    /// Declared in `adapters` config.
+    Static,
+    /// Self-registered over gRPC.
+    Dynamic,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "registry::breaker::BreakerState")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct AdapterCapabilities {
+    pub streaming: bool,
+    pub token_count: bool,
+    pub model_route: bool,
+    pub embed_cache: bool,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct Adapter {
+    pub id: String,
+    pub endpoint: String,
+    pub version: String,
+    pub capabilities: AdapterCapabilities,
+    pub health: AdapterHealth,
+    pub origin: AdapterOrigin,
+    pub draining: bool,
+    pub in_flight: u32,
+    pub breaker: BreakerState,
+}
+
+impl From<proto::AdapterInfo> for Adapter {
+    fn from(a: proto::AdapterInfo) -> Self {
+        let caps = a.capabilities.clone().unwrap_or_default();
+        Self {
+            health: match a.health() {
+                proto::AdapterHealth::Unhealthy => AdapterHealth::Unhealthy,
+                _ => AdapterHealth::Healthy,
+            },
+            origin: match a.origin() {
+                proto::AdapterOrigin::Static => AdapterOrigin::Static,
+                _ => AdapterOrigin::Dynamic,
+            },
+            breaker: match a.breaker() {
+                proto::BreakerState::Closed => BreakerState::Closed,
+                proto::BreakerState::Open => BreakerState::Open,
+                proto::BreakerState::HalfOpen => BreakerState::HalfOpen,
+            },
+            id: a.adapter_id,
+            endpoint: a.endpoint,
+            version: a.version,
+            capabilities: AdapterCapabilities {
+                streaming: caps.streaming,
+                token_count: caps.token_count,
+                model_route: caps.model_route,
+                embed_cache: caps.embed_cache,
+            },
+            draining: a.draining,
+            in_flight: a.in_flight,
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct AdapterStats {
+    pub adapter_id: String,
+    pub in_flight: u32,
+    /// Calls waiting for a concurrency slot.
+    pub queued: u32,
+    /// Calls since the adapter registered at its current endpoint, and how many failed.
+    pub attempts: u64,
+    pub failures: u64,
+    pub breaker: BreakerState,
+    /// Latency of recent successful calls; null until enough have been seen.
+    pub latency_p50_ms: Option<f64>,
+    pub latency_p95_ms: Option<f64>,
+}
+
+impl From<AdapterStatsSnapshot> for AdapterStats {
+    fn from(s: AdapterStatsSnapshot) -> Self {
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        Self {
+            adapter_id: s.adapter_id,
+            in_flight: s.in_flight as u32,
+            queued: s.queued as u32,
+            attempts: s.attempts,
+            failures: s.failures,
+            breaker: s.breaker.into(),
+            latency_p50_ms: s.latency_p50.map(ms),
+            latency_p95_ms: s.latency_p95.map(ms),
+        }
+    }
+}
+
+/// A request read back from the replay log.
+#[derive(SimpleObject, Debug)]
+pub struct ReplayedRequest {
+    /// Unix seconds; null for entries logged before timestamps were recorded.
+    pub received_at: Option<u64>,
+    pub request_id: String,
+    pub agent_id: Option<String>,
+    pub session_id: Option<String>,
+    pub preferred_model: Option<String>,
+    pub messages: Vec<Message>,
+    pub metadata: Vec<MetadataEntry>,
+    /// The full canonical request.
+    pub request: Json<CanonicalAIRequest>,
+}
+
+impl From<ReplayEntry> for ReplayedRequest {
+    fn from(e: ReplayEntry) -> Self {
+        let r = e.request;
+        let mut metadata: Vec<_> = r.metadata.iter().map(|(k, v)| MetadataEntry { key: k.clone(), value: v.clone() }).collect();
+        metadata.sort_by(|a, b| a.key.cmp(&b.key));
+        Self {
+            received_at: e.received_at,
+            request_id: r.request_id.to_string(),
+            agent_id: r.agent_id.clone(),
+            session_id: r.session_id.clone(),
+            preferred_model: r.preferred_model.clone(),
+            messages: r.messages.iter().cloned().map(Into::into).collect(),
+            metadata,
+            request: Json(r),
+        }
+    }
+}
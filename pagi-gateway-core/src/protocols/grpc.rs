@@ -1,4 +1,263 @@
-// gRPC ingress handlers would live here.
-//
-// MVP: the Rust core exposes gRPC only for adapter registration via [`crate::registry`].
+//! Public gRPC ingress: `pagi.v1.GatewayService`.
+//!
+//! Served next to `AdapterRegistry` on `core.bind_grpc` for backends that would rather call the
+//! gateway natively than over REST. Calls go through the same client authentication, rate
+//! limits, token budgets and metrics (protocol `grpc`) as the HTTP protocols. Rejections map
+//! onto gRPC codes: 401 is `UNAUTHENTICATED`, 403 `PERMISSION_DENIED`, 429 `RESOURCE_EXHAUSTED`
+//! (with `retry-after` metadata), 503 `UNAVAILABLE` and 504 `DEADLINE_EXCEEDED`.
 
+use std::time::{Duration, Instant};
+
+use hyper::{HeaderMap, StatusCode};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::canonical::CanonicalAIRequest;
+use crate::middleware::auth::Authenticator;
+use crate::middleware::observability::Metrics;
+use crate::middleware::rate_limit::{RateLimitStatus, RateLimits};
+use crate::middleware::token_limit::TokenReservation;
+use crate::proto::gateway_service_server::{GatewayService, GatewayServiceServer};
+use crate::proto::{CanonicalAiChunk, CanonicalAiRequest, CanonicalAiResponse};
+use crate::registry::{from_proto_request, to_proto_response, AdapterRegistryState, ForwardOptions};
+
+use super::{forward_error_status, forward_options, relay, Admitted, Gate, Rejected};
+
+const PROTOCOL: &str = "grpc";
+
+pub struct GatewaySvc {
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+}
+
+/// A call that passed authentication and rate limiting and is ready to forward.
+struct Call {
+    canonical: CanonicalAIRequest,
+    opts: ForwardOptions,
+    reservation: TokenReservation,
+    quota: Option<RateLimitStatus>,
+}
+
+impl GatewaySvc {
+    pub fn new(
+        registry: AdapterRegistryState,
+        metrics: Metrics,
+        auth: Authenticator,
+        limits: RateLimits,
+    ) -> GatewayServiceServer<Self> {
+        GatewayServiceServer::new(Self { registry, metrics, auth, limits })
+    }
+
+    async fn admit(&self, request: Request<CanonicalAiRequest>) -> Result<Call, Status> {
+        let gate = Gate::new(&self.auth, &self.limits, &self.metrics, PROTOCOL);
+        let headers = request.metadata().clone().into_headers();
+        let caller = gate.caller(request.remote_addr(), &headers).await.map_err(rejected)?;
+
+        let mut opts = forward_options(&headers);
+        if opts.timeout.is_none() {
+            opts.timeout = grpc_timeout(&headers);
+        }
+        let mut canonical = from_proto_request(request.into_inner())
+            .map_err(|e| self.reject(StatusCode::BAD_REQUEST, "400", e))?;
+        if canonical.messages.is_empty() {
+            return Err(self.reject(StatusCode::BAD_REQUEST, "400", "messages required".to_string()));
+        }
+        let Admitted { reservation, quota } = gate.admit(&caller, &mut canonical, &mut opts).map_err(rejected)?;
+
+        info!(request_id=%canonical.request_id, "canonicalized grpc request");
+        Ok(Call { canonical, opts, reservation, quota })
+    }
+
+    fn reject(&self, status: StatusCode, label: &'static str, message: String) -> Status {
+        self.metrics.inc_requests(PROTOCOL, label);
+        Status::new(code_for(status), message)
+    }
+
+    fn forward_failed(&self, e: anyhow::Error) -> Status {
+        warn!(error=%e, "forward failed");
+        let (status, label, message) = forward_error_status(&e);
+        self.reject(status, label, message.to_string())
+    }
+}
+
+#[tonic::async_trait]
+impl GatewayService for GatewaySvc {
+    async fn call(&self, request: Request<CanonicalAiRequest>) -> Result<Response<CanonicalAiResponse>, Status> {
+        let started = Instant::now();
+        let Call { canonical, opts, reservation, quota } = self.admit(request).await?;
+
+        let resp = self.registry.forward_with(canonical, opts).await.map_err(|e| self.forward_failed(e))?;
+        self.metrics.inc_requests(PROTOCOL, "200");
+        self.metrics.observe_latency(PROTOCOL, started.elapsed().as_secs_f64());
+
+        reservation.settle(resp.usage);
+        if let Some(usage) = resp.usage {
+            self.metrics.observe_usage(&resp.adapter_id, usage);
+        }
+        Ok(with_quota(Response::new(to_proto_response(resp)), quota))
+    }
+
+    type CallStreamStream = ReceiverStream<Result<CanonicalAiChunk, Status>>;
+
+    /// Relays adapter chunks as they arrive; adapter failures end the stream with their status.
+    async fn call_stream(
+        &self,
+        request: Request<CanonicalAiRequest>,
+    ) -> Result<Response<Self::CallStreamStream>, Status> {
+        let started = Instant::now();
+        let Call { canonical, opts, reservation, quota } = self.admit(request).await?;
+
+        let stream = self.registry.forward_stream_with(canonical, opts).await.map_err(|e| self.forward_failed(e))?;
+        self.metrics.inc_requests(PROTOCOL, "200");
+        // Time to first byte; the stream itself may stay open much longer.
+        self.metrics.observe_latency(PROTOCOL, started.elapsed().as_secs_f64());
+
+        // tonic streams carry `Status`, however large it is.
+        #[allow(clippy::result_large_err)]
+        let rx = relay(stream, reservation, |item| {
+            item.map_err(|e| e.downcast::<Status>().unwrap_or_else(|e| Status::unavailable(e.to_string())))
+        });
+        Ok(with_quota(Response::new(ReceiverStream::new(rx)), quota))
+    }
+}
+
+/// Add `ratelimit-*` metadata for the caller's tightest quota.
+fn with_quota<T>(mut resp: Response<T>, quota: Option<RateLimitStatus>) -> Response<T> {
+    if let Some(q) = quota {
+        let mut headers = HeaderMap::new();
+        q.set_headers(&mut headers);
+        *resp.metadata_mut() = MetadataMap::from_headers(headers);
+    }
+    resp
+}
+
+/// A [`Gate`] rejection as a status; rate limits carry `retry-after` metadata.
+fn rejected(r: Rejected) -> Status {
+    let mut headers = HeaderMap::new();
+    r.set_headers(&mut headers);
+    Status::with_metadata(code_for(r.status()), r.to_string(), MetadataMap::from_headers(headers))
+}
+
+fn code_for(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    }
+}
+
+/// The client's gRPC deadline (`grpc-timeout: <n><unit>`), used when it sends no
+/// `x-pagi-timeout-ms`.
+fn grpc_timeout(headers: &HeaderMap) -> Option<Duration> {
+    let v = headers.get("grpc-timeout")?.to_str().ok()?;
+    let (n, unit) = v.split_at(v.len().checked_sub(1)?);
+    let n: u64 = n.parse().ok()?;
+    let d = match unit {
+        "H" => Duration::from_secs(n.saturating_mul(3600)),
+        "M" => Duration::from_secs(n.saturating_mul(60)),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+    Some(d).filter(|d| !d.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, JwtConfig, RateLimitConfig, RequestReplayConfig};
+    use crate::middleware::jwt::tests::write_jwks;
+    use crate::proto::content_part::Part;
+    use crate::proto::{ContentPart, GenerationConstraints, Message, MessageRole, TextPart};
+
+    async fn svc(auth: AuthConfig) -> GatewaySvc {
+        let metrics = Metrics::new();
+        GatewaySvc {
+            registry: AdapterRegistryState::with_echo(RequestReplayConfig::default(), metrics.clone()).await,
+            metrics,
+            auth: Authenticator::new(&auth).unwrap(),
+            limits: RateLimits::new(&RateLimitConfig::default()).unwrap(),
+        }
+    }
+
+    fn hello() -> CanonicalAiRequest {
+        CanonicalAiRequest {
+            messages: vec![Message {
+                role: MessageRole::User as i32,
+                content: vec![ContentPart { part: Some(Part::Text(TextPart { text: "hello there".to_string() })) }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn call_and_stream_reach_the_adapter() {
+        let svc = svc(AuthConfig::default()).await;
+        let resp = svc.call(Request::new(hello())).await.unwrap().into_inner();
+        assert_eq!(resp.adapter_id, "echo");
+        assert!(!resp.request_id.is_empty());
+        assert!(resp.choices[0].message.as_ref().is_some_and(|m| !m.content.is_empty()));
+
+        let mut chunks = svc.call_stream(Request::new(hello())).await.unwrap().into_inner().into_inner();
+        let mut text = String::new();
+        while let Some(chunk) = chunks.recv().await {
+            let chunk = chunk.unwrap();
+            text.push_str(&chunk.delta);
+            if chunk.done {
+                break;
+            }
+        }
+        assert_eq!(text.trim(), "hello there");
+
+        let err = svc.call(Request::new(CanonicalAiRequest::default())).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn checks_roles_and_keeps_zero_temperature() {
+        let svc = svc(AuthConfig::default()).await;
+        let mut unset = hello();
+        unset.messages[0].role = MessageRole::Unspecified as i32;
+        let err = svc.call(Request::new(unset)).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().contains("messages[0].role"), "{}", err.message());
+
+        let greedy = CanonicalAiRequest {
+            constraints: Some(GenerationConstraints { temperature: Some(0.0), ..Default::default() }),
+            ..hello()
+        };
+        let canonical = from_proto_request(greedy).unwrap();
+        assert_eq!(canonical.constraints.temperature, Some(0.0));
+        assert_eq!(canonical.constraints.top_p, None);
+    }
+
+    #[tokio::test]
+    async fn requires_credentials_when_auth_is_on() {
+        let jwt = JwtConfig { jwks_path: Some(write_jwks("grpc", &[("k1", "secret")])), ..Default::default() };
+        let svc = svc(AuthConfig { jwt: Some(jwt), ..Default::default() }).await;
+        let err = svc.call(Request::new(hello())).await.unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn parses_grpc_timeout() {
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-timeout", "1500m".parse().unwrap());
+        assert_eq!(grpc_timeout(&headers), Some(Duration::from_millis(1500)));
+        headers.insert("grpc-timeout", "2S".parse().unwrap());
+        assert_eq!(grpc_timeout(&headers), Some(Duration::from_secs(2)));
+        headers.insert("grpc-timeout", "soon".parse().unwrap());
+        assert_eq!(grpc_timeout(&headers), None);
+    }
+}
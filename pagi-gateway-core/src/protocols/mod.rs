@@ -1,5 +1,278 @@
+pub mod admin;
+pub mod anthropic;
 pub mod graphql;
 pub mod grpc;
+pub mod openai;
 pub mod rest;
 pub mod ws;
 
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::{Body, HeaderMap, Request, Response, StatusCode};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+
+use crate::canonical::{CanonicalAIRequest, Usage};
+use crate::middleware::auth::{AuthError, Authenticator, Principal};
+use crate::middleware::observability::Metrics;
+use crate::middleware::rate_limit::{RateLimitStatus, RateLimited, RateLimits, RemoteAddr};
+use crate::middleware::token_limit::{estimate_completion_tokens, TokenLimitError, TokenReservation};
+use crate::proto::CanonicalAiChunk;
+use crate::registry::{from_proto_usage, DeadlineExceeded, ForwardOptions, ForwardStream, QueueError};
+
+const STREAM_BUFFER: usize = 32;
+
+pub(crate) fn json<T: serde::Serialize>(status: StatusCode, v: &T) -> Response<Body> {
+    let body = serde_json::to_vec(v).unwrap();
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Why [`Gate`] refused a request.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Rejected {
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    /// The request alone is larger than the caller's token budget.
+    #[error(transparent)]
+    TooLarge(TokenLimitError),
+}
+
+impl From<TokenLimitError> for Rejected {
+    fn from(e: TokenLimitError) -> Self {
+        match e {
+            TokenLimitError::Limited(l) => Rejected::RateLimited(l),
+            e => Rejected::TooLarge(e),
+        }
+    }
+}
+
+impl Rejected {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Rejected::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Rejected::Auth(e) => e.status(),
+            Rejected::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    /// [`status`](Self::status) as a metrics label.
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Rejected::RateLimited(_) => "429",
+            Rejected::Auth(e) => e.status_label(),
+            Rejected::TooLarge(_) => "413",
+        }
+    }
+
+    /// `Retry-After`/`RateLimit-*` headers for a rate-limited request.
+    pub(crate) fn set_headers(&self, headers: &mut HeaderMap) {
+        if let Rejected::RateLimited(l) = self {
+            l.set_headers(headers);
+        }
+    }
+}
+
+/// The caller of a request, once its IP quota and credentials have been checked.
+#[derive(Default)]
+pub(crate) struct Caller {
+    pub principal: Option<Principal>,
+    /// Remaining IP quota.
+    pub quota: Option<RateLimitStatus>,
+}
+
+/// A canonical request cleared for forwarding.
+pub(crate) struct Admitted {
+    /// Dropped unsettled (e.g. when forwarding fails) it gives the tokens back.
+    pub reservation: TokenReservation,
+    /// The tightest quota the request was charged against.
+    pub quota: Option<RateLimitStatus>,
+}
+
+/// The admission checks every ingress runs before forwarding: the client IP's quota and
+/// authentication ([`caller`](Self::caller)), then, once the protocol has built its canonical
+/// request, the caller's identity and scopes, per-caller quotas and token budget
+/// ([`admit`](Self::admit)). Rejections are logged and counted under the ingress's protocol.
+#[derive(Clone, Copy)]
+pub(crate) struct Gate<'a> {
+    auth: &'a Authenticator,
+    limits: &'a RateLimits,
+    metrics: &'a Metrics,
+    protocol: &'static str,
+}
+
+impl<'a> Gate<'a> {
+    pub(crate) fn new(auth: &'a Authenticator, limits: &'a RateLimits, metrics: &'a Metrics, protocol: &'static str) -> Self {
+        Self { auth, limits, metrics, protocol }
+    }
+
+    /// Charge the client IP's quota; `peer` is `None` for Unix sockets, which are not limited.
+    pub(crate) fn peer(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> Result<Option<RateLimitStatus>, Rejected> {
+        self.limits.check_peer(peer, headers).map_err(|l| self.reject(l.into()))
+    }
+
+    /// Check the IP quota, then the request's credentials.
+    pub(crate) async fn caller(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> Result<Caller, Rejected> {
+        let quota = self.peer(peer, headers)?;
+        let principal = self.auth.authenticate(headers).await.map_err(|e| self.reject(e.into()))?;
+        Ok(Caller { principal, quota })
+    }
+
+    /// Stamp the caller's identity onto `req` and hold it to the caller's scopes, quotas and
+    /// token budget.
+    pub(crate) fn admit(
+        &self,
+        caller: &Caller,
+        req: &mut CanonicalAIRequest,
+        opts: &mut ForwardOptions,
+    ) -> Result<Admitted, Rejected> {
+        let principal = caller.principal.as_ref();
+        self.auth.apply(principal, req, opts).map_err(|e| self.reject(e.into()))?;
+        let quota = self.limits.check_caller(principal, req).map_err(|l| self.reject(l.into()))?;
+        let reservation = self.limits.reserve_tokens(principal, req).map_err(|e| self.reject(e.into()))?;
+        Ok(Admitted { reservation, quota: RateLimitStatus::tighter(caller.quota, quota) })
+    }
+
+    fn reject(&self, rejected: Rejected) -> Rejected {
+        match &rejected {
+            Rejected::RateLimited(l) => debug!(dimension = l.dimension.label(), protocol = self.protocol, "rate limited"),
+            e => warn!(error=%e, protocol = self.protocol, "request rejected"),
+        }
+        self.metrics.inc_requests(self.protocol, rejected.label());
+        rejected
+    }
+}
+
+/// The peer address hyper's server stored on `req`.
+pub(crate) fn remote_addr<B>(req: &Request<B>) -> Option<SocketAddr> {
+    req.extensions().get::<RemoteAddr>().map(|r| r.0)
+}
+
+/// Add `RateLimit-*` headers for the caller's tightest quota to a successful response.
+pub(crate) fn with_quota(mut resp: Response<Body>, quota: Option<RateLimitStatus>) -> Response<Body> {
+    if let Some(q) = quota {
+        q.set_headers(resp.headers_mut());
+    }
+    resp
+}
+
+/// Forwarding options from request headers: `x-pagi-timeout-ms` sets the total deadline.
+pub(crate) fn forward_options(headers: &HeaderMap) -> ForwardOptions {
+    let timeout = headers
+        .get("x-pagi-timeout-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis);
+    ForwardOptions { timeout, ..Default::default() }
+}
+
+/// HTTP status for a failed forward: 504 if the deadline ran out, otherwise 503 (also when
+/// every adapter was at its concurrency limit and the queue gave up).
+pub(crate) fn forward_error_status(e: &anyhow::Error) -> (StatusCode, &'static str, &'static str) {
+    if e.downcast_ref::<DeadlineExceeded>().is_some() {
+        (StatusCode::GATEWAY_TIMEOUT, "504", "deadline exceeded")
+    } else if e.downcast_ref::<QueueError>().is_some() {
+        (StatusCode::SERVICE_UNAVAILABLE, "503", "adapters busy")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "503", "no adapter available")
+    }
+}
+
+/// Relay adapter chunks as they arrive, turning each chunk (with `request_id` and `adapter_id`
+/// filled in) or adapter failure into a protocol message with `frame`.
+///
+/// The relay ends with the adapter stream or when the returned receiver is dropped, which
+/// cancels the adapter stream. The token reservation is then settled with the usage the adapter
+/// reported, else with an estimate from the text relayed.
+pub(crate) fn relay<T: Send + 'static>(
+    mut stream: ForwardStream,
+    reservation: TokenReservation,
+    mut frame: impl FnMut(anyhow::Result<CanonicalAiChunk>) -> T + Send + 'static,
+) -> mpsc::Receiver<T> {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let prompt = reservation.prompt_estimate();
+        let mut streamed = 0;
+        let mut reported = None;
+        loop {
+            let item = tokio::select! {
+                item = stream.chunks.recv() => item,
+                _ = tx.closed() => break,
+            };
+            let Some(item) = item else { break };
+            let item = match item {
+                Ok(mut chunk) => {
+                    streamed += chunk.delta.len();
+                    reported = chunk.usage.clone().map(from_proto_usage).or(reported);
+                    if chunk.request_id.is_empty() {
+                        chunk.request_id = stream.request_id.clone();
+                    }
+                    chunk.adapter_id = stream.adapter_id.clone();
+                    Ok(chunk)
+                }
+                Err(e) => {
+                    warn!(error=%e, "adapter stream failed");
+                    Err(e)
+                }
+            };
+            if tx.send(frame(item)).await.is_err() {
+                break;
+            }
+        }
+        reservation.settle(Some(reported.unwrap_or(Usage {
+            prompt_tokens: prompt,
+            completion_tokens: estimate_completion_tokens(streamed),
+            cached_tokens: 0,
+        })));
+    });
+    rx
+}
+
+/// A `text/event-stream` response sending each of `events` (see [`sse_event`]) as it arrives.
+pub(crate) fn sse_response(events: mpsc::Receiver<String>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/event-stream")
+        .header("cache-control", "no-cache")
+        .body(Body::wrap_stream(ReceiverStream::new(events).map(Ok::<_, Infallible>)))
+        .unwrap()
+}
+
+/// One server-sent event, with an `event:` line if `event` is set.
+pub(crate) fn sse_event(event: Option<&str>, data: &str) -> String {
+    match event {
+        Some(e) => format!("event: {e}\ndata: {data}\n\n"),
+        None => format!("data: {data}\n\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timeout_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forward_options(&headers).timeout, None);
+        headers.insert("x-pagi-timeout-ms", "1500".parse().unwrap());
+        assert_eq!(forward_options(&headers).timeout, Some(Duration::from_millis(1500)));
+        headers.insert("x-pagi-timeout-ms", "soon".parse().unwrap());
+        assert_eq!(forward_options(&headers).timeout, None);
+    }
+
+    #[test]
+    fn formats_sse_events() {
+        assert_eq!(sse_event(None, "{}"), "data: {}\n\n");
+        assert_eq!(sse_event(Some("error"), "{}"), "event: error\ndata: {}\n\n");
+    }
+}
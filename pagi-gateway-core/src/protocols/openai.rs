@@ -0,0 +1,654 @@
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::{Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::canonical::{
+    CanonicalAIRequest, CanonicalAIResponse, ContentPart, FinishReason, Message, MessageRole, Tool, ToolCall, Usage,
+};
+use crate::middleware::observability::Metrics;
+use crate::middleware::auth::Authenticator;
+use crate::middleware::rate_limit::RateLimits;
+use crate::middleware::token_limit::TokenReservation;
+use crate::registry::{from_proto_usage, AdapterRegistryState, ForwardStream};
+
+use super::{
+    forward_error_status, forward_options, json, relay, remote_addr, sse_event, sse_response, with_quota, Admitted, Gate, Rejected,
+};
+
+/// OpenAI Chat Completions request (`POST /v1/chat/completions`).
+///
+/// Only the fields that have a canonical equivalent are modeled; unknown fields are ignored.
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub tools: Vec<ChatTool>,
+    #[serde(default)]
+    pub tool_choice: Option<ChatToolChoice>,
+    #[serde(default)]
+    pub response_format: Option<ChatResponseFormat>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<StopInput>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    #[serde(default)]
+    pub content: Option<ChatContent>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<ChatToolCall>,
+}
+
+/// OpenAI roles; `developer` is the newer alias for `system`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    System,
+    Developer,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ChatImageUrl },
+    InputAudio { input_audio: ChatInputAudio },
+    File { file: ChatFile },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatImageUrl {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatInputAudio {
+    /// Base64-encoded audio.
+    pub data: String,
+    pub format: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatFile {
+    #[serde(default)]
+    pub file_id: Option<String>,
+    #[serde(default)]
+    pub file_data: Option<String>,
+    #[serde(default)]
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChatToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: ChatFunctionCall,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChatFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatTool {
+    pub function: ChatFunctionDef,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatFunctionDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+/// `"auto" | "none" | "required"` or `{"type":"function","function":{"name":...}}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatToolChoice {
+    Mode(String),
+    Function { function: ChatToolChoiceFunction },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatToolChoiceFunction {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: ChatJsonSchema },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatJsonSchema {
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum StopInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatChoice {
+    pub index: u32,
+    pub message: ChatResponseMessage,
+    pub finish_reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatResponseMessage {
+    pub role: &'static str,
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ChatToolCall>,
+}
+
+/// One `chat.completion.chunk` event of a streamed completion (`stream: true`).
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatChunkChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatChunkChoice {
+    pub index: u32,
+    pub delta: ChatDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl ChatCompletionRequest {
+    pub fn into_canonical(self) -> CanonicalAIRequest {
+        let mut req = CanonicalAIRequest::new();
+        req.messages = self.messages.into_iter().map(to_canonical_message).collect();
+        req.tools = self
+            .tools
+            .into_iter()
+            .map(|t| Tool {
+                name: t.function.name,
+                description: t.function.description,
+                parameters_json_schema: t.function.parameters,
+                strict: t.function.strict.unwrap_or(false),
+            })
+            .collect();
+        req.tool_choice = self.tool_choice.map(|c| match c {
+            ChatToolChoice::Mode(m) => m,
+            ChatToolChoice::Function { function } => function.name,
+        });
+        req.response_format = self.response_format.and_then(|f| match f {
+            ChatResponseFormat::Text => None,
+            ChatResponseFormat::JsonObject => Some(serde_json::json!({ "type": "object" })),
+            ChatResponseFormat::JsonSchema { json_schema } => json_schema.schema,
+        });
+        req.constraints.max_tokens = self.max_completion_tokens.or(self.max_tokens);
+        req.constraints.temperature = self.temperature;
+        req.constraints.top_p = self.top_p;
+        req.constraints.stop_sequences = match self.stop {
+            Some(StopInput::One(s)) => vec![s],
+            Some(StopInput::Many(v)) => v,
+            None => vec![],
+        };
+        req.constraints.presence_penalty = self.presence_penalty;
+        req.constraints.frequency_penalty = self.frequency_penalty;
+        req.constraints.reasoning_effort = self.reasoning_effort;
+        req.constraints.stream = self.stream;
+        req.preferred_model = self.model.filter(|m| !m.is_empty());
+        req.metadata = self.metadata;
+        if let Some(user) = self.user {
+            req.metadata.entry("user_id".to_string()).or_insert(user);
+        }
+        req
+    }
+}
+
+fn to_canonical_message(m: ChatMessage) -> Message {
+    let role = match m.role {
+        ChatRole::System | ChatRole::Developer => MessageRole::System,
+        ChatRole::User => MessageRole::User,
+        ChatRole::Assistant => MessageRole::Assistant,
+        ChatRole::Tool => MessageRole::Tool,
+    };
+    let content = match m.content {
+        None => vec![],
+        Some(ChatContent::Text(text)) => vec![ContentPart::Text { text }],
+        Some(ChatContent::Parts(parts)) => parts
+            .into_iter()
+            .filter_map(|p| match p {
+                ChatContentPart::Text { text } => Some(ContentPart::Text { text }),
+                ChatContentPart::ImageUrl { image_url } => Some(ContentPart::Image { url: image_url.url }),
+                ChatContentPart::InputAudio { input_audio } => Some(ContentPart::Audio {
+                    url: format!("data:audio/{};base64,{}", input_audio.format, input_audio.data),
+                }),
+                ChatContentPart::File { file } => {
+                    let url = file.file_data.or(file.file_id)?;
+                    let mime_type = file
+                        .filename
+                        .as_deref()
+                        .and_then(mime_from_filename)
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+                    Some(ContentPart::File { url, mime_type })
+                }
+            })
+            .collect(),
+    };
+    Message {
+        role,
+        content,
+        name: m.name,
+        tool_call_id: m.tool_call_id,
+        tool_calls: m
+            .tool_calls
+            .into_iter()
+            .map(|c| ToolCall { id: c.id, name: c.function.name, arguments: c.function.arguments })
+            .collect(),
+    }
+}
+
+fn mime_from_filename(name: &str) -> Option<&'static str> {
+    match name.rsplit('.').next()?.to_ascii_lowercase().as_str() {
+        "pdf" => Some("application/pdf"),
+        "txt" => Some("text/plain"),
+        "json" => Some("application/json"),
+        _ => None,
+    }
+}
+
+/// Render a canonical response as a Chat Completions response.
+pub fn render_response(resp: &CanonicalAIResponse, requested_model: Option<&str>) -> ChatCompletionResponse {
+    let choices = resp
+        .choices
+        .iter()
+        .map(|c| {
+            let text = c.message.text();
+            let tool_calls: Vec<ChatToolCall> = c
+                .message
+                .tool_calls
+                .iter()
+                .map(|t| ChatToolCall {
+                    id: t.id.clone(),
+                    kind: function_type(),
+                    function: ChatFunctionCall { name: t.name.clone(), arguments: t.arguments.clone() },
+                })
+                .collect();
+            let finish_reason = match c.finish_reason {
+                Some(FinishReason::Length) => "length",
+                Some(FinishReason::ToolCalls) => "tool_calls",
+                Some(FinishReason::ContentFilter) => "content_filter",
+                Some(FinishReason::Stop) | Some(FinishReason::Error) | None => {
+                    if tool_calls.is_empty() {
+                        "stop"
+                    } else {
+                        "tool_calls"
+                    }
+                }
+            };
+            ChatChoice {
+                index: c.index,
+                message: ChatResponseMessage {
+                    role: "assistant",
+                    content: if text.is_empty() && !tool_calls.is_empty() { None } else { Some(text) },
+                    tool_calls,
+                },
+                finish_reason: finish_reason.to_string(),
+            }
+        })
+        .collect();
+
+    ChatCompletionResponse {
+        id: format!("chatcmpl-{}", resp.request_id),
+        object: "chat.completion",
+        created: unix_now(),
+        model: resp.model.as_deref().or(requested_model).unwrap_or("pagi").to_string(),
+        choices,
+        usage: resp.usage.map(chat_usage),
+    }
+}
+
+/// Relay adapter chunks as `chat.completion.chunk` server-sent events. The first carries the
+/// assistant role, the last the finish reason and any usage the adapter reported, and the stream
+/// ends with `data: [DONE]`. An adapter failure mid-stream is sent as an error envelope instead.
+fn stream_response(stream: ForwardStream, reservation: TokenReservation, requested_model: Option<String>) -> Response<Body> {
+    let created = unix_now();
+    let model = requested_model.unwrap_or_else(|| "pagi".to_string());
+    let mut first = true;
+    sse_response(relay(stream, reservation, move |item| match item {
+        Ok(c) => {
+            let chunk = ChatCompletionChunk {
+                id: format!("chatcmpl-{}", c.request_id),
+                object: "chat.completion.chunk",
+                created,
+                model: model.clone(),
+                choices: vec![ChatChunkChoice {
+                    index: 0,
+                    delta: ChatDelta {
+                        role: std::mem::take(&mut first).then_some("assistant"),
+                        content: Some(c.delta).filter(|d| !d.is_empty()),
+                    },
+                    finish_reason: c.done.then(|| chunk_finish_reason(&c.finish_reason).to_string()),
+                }],
+                usage: c.usage.map(|u| chat_usage(from_proto_usage(u))),
+            };
+            let mut event = sse_event(None, &serde_json::to_string(&chunk).unwrap());
+            if c.done {
+                event.push_str(&sse_event(None, "[DONE]"));
+            }
+            event
+        }
+        Err(e) => sse_event(None, &error_body("api_error", &e.to_string()).to_string()),
+    }))
+}
+
+/// A streamed chunk's finish reason (see [`FinishReason::as_str`]) in OpenAI's terms.
+fn chunk_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "length" => "length",
+        "tool_calls" => "tool_calls",
+        "content_filter" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn chat_usage(u: Usage) -> ChatUsage {
+    ChatUsage {
+        prompt_tokens: u.prompt_tokens as u64,
+        completion_tokens: u.completion_tokens as u64,
+        total_tokens: u.total_tokens() as u64,
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+pub async fn handle_chat_completions(
+    req: Request<Body>,
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+) -> Result<Response<Body>, hyper::Error> {
+    let started = Instant::now();
+    let gate = Gate::new(&auth, &limits, &metrics, "openai");
+    let caller = match gate.caller(remote_addr(&req), req.headers()).await {
+        Ok(c) => c,
+        Err(r) => return Ok(rejected(r)),
+    };
+
+    let mut opts = forward_options(req.headers());
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let parsed: ChatCompletionRequest = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(error=%e, "invalid chat completions request");
+            metrics.inc_requests("openai", "400");
+            return Ok(error(StatusCode::BAD_REQUEST, "invalid_request_error", &e.to_string()));
+        }
+    };
+
+    let mut canonical = parsed.into_canonical();
+    let Admitted { reservation, quota } = match gate.admit(&caller, &mut canonical, &mut opts) {
+        Ok(a) => a,
+        Err(r) => return Ok(rejected(r)),
+    };
+    if canonical.messages.is_empty() {
+        metrics.inc_requests("openai", "400");
+        return Ok(error(StatusCode::BAD_REQUEST, "invalid_request_error", "messages required"));
+    }
+
+    info!(request_id=%canonical.request_id, "canonicalized openai request");
+
+    let requested_model = canonical.preferred_model.clone();
+    if canonical.constraints.stream {
+        let stream = match registry.forward_stream_with(canonical, opts).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error=%e, "forward failed");
+                let (code, label, msg) = forward_error_status(&e);
+                metrics.inc_requests("openai", label);
+                return Ok(error(code, "api_error", msg));
+            }
+        };
+        metrics.inc_requests("openai", "200");
+        // Time to first byte; the stream itself may stay open much longer.
+        metrics.observe_latency("openai", started.elapsed().as_secs_f64());
+        return Ok(with_quota(stream_response(stream, reservation, requested_model), quota));
+    }
+
+    let resp = match registry.forward_with(canonical, opts).await {
+        Ok(r) => r,
+        Err(e) => {
+            warn!(error=%e, "forward failed");
+            let (code, label, msg) = forward_error_status(&e);
+            metrics.inc_requests("openai", label);
+            return Ok(error(code, "api_error", msg));
+        }
+    };
+
+    metrics.inc_requests("openai", "200");
+    metrics.observe_latency("openai", started.elapsed().as_secs_f64());
+
+    reservation.settle(resp.usage);
+    if let Some(usage) = resp.usage {
+        metrics.observe_usage(&resp.adapter_id, usage);
+    }
+
+    let out = render_response(&resp, requested_model.as_deref());
+    Ok(with_quota(json(StatusCode::OK, &out), quota))
+}
+
+fn rejected(r: Rejected) -> Response<Body> {
+    let kind = match &r {
+        Rejected::RateLimited(_) => "rate_limit_error",
+        Rejected::Auth(_) if r.status() == StatusCode::FORBIDDEN => "permission_error",
+        Rejected::Auth(_) => "authentication_error",
+        Rejected::TooLarge(_) => "invalid_request_error",
+    };
+    let mut resp = error(r.status(), kind, &r.to_string());
+    r.set_headers(resp.headers_mut());
+    resp
+}
+
+/// OpenAI-style error envelope so SDKs surface a readable message.
+fn error(status: StatusCode, kind: &str, message: &str) -> Response<Body> {
+    json(status, &error_body(kind, message))
+}
+
+fn error_body(kind: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({ "error": { "message": message, "type": kind, "code": null } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, RateLimitConfig, RequestReplayConfig};
+
+    #[test]
+    fn maps_chat_completion_request() {
+        let j = r#"{
+          "model": "gpt-4o-mini",
+          "messages": [
+            {"role": "developer", "content": "be terse"},
+            {"role": "user", "content": [{"type":"text","text":"hi"},{"type":"image_url","image_url":{"url":"https://e/x.png"}}]},
+            {"role": "assistant", "content": null, "tool_calls": [{"id":"c1","type":"function","function":{"name":"f","arguments":"{}"}}]},
+            {"role": "tool", "tool_call_id": "c1", "content": "42"}
+          ],
+          "tools": [{"type":"function","function":{"name":"f","parameters":{"type":"object"}}}],
+          "tool_choice": {"type":"function","function":{"name":"f"}},
+          "response_format": {"type":"json_schema","json_schema":{"name":"x","schema":{"type":"object"}}},
+          "max_tokens": 64,
+          "stop": "END",
+          "user": "u1"
+        }"#;
+        let parsed: ChatCompletionRequest = serde_json::from_str(j).unwrap();
+        let req = parsed.into_canonical();
+        assert_eq!(req.messages.len(), 4);
+        assert_eq!(req.messages[0].role, MessageRole::System);
+        assert_eq!(req.messages[1].content.len(), 2);
+        assert_eq!(req.messages[2].tool_calls[0].name, "f");
+        assert_eq!(req.messages[3].tool_call_id.as_deref(), Some("c1"));
+        assert_eq!(req.tools[0].name, "f");
+        assert_eq!(req.tool_choice.as_deref(), Some("f"));
+        assert_eq!(req.response_format, Some(serde_json::json!({"type":"object"})));
+        assert_eq!(req.constraints.max_tokens, Some(64));
+        assert_eq!(req.constraints.stop_sequences, vec!["END".to_string()]);
+        assert_eq!(req.preferred_model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(req.metadata.get("user_id").map(String::as_str), Some("u1"));
+    }
+
+    #[test]
+    fn renders_tool_calls_and_usage() {
+        let resp = CanonicalAIResponse {
+            request_id: "r1".to_string(),
+            adapter_id: "a".to_string(),
+            model: Some("gpt-x".to_string()),
+            choices: vec![crate::canonical::Choice {
+                index: 0,
+                message: Message {
+                    role: MessageRole::Assistant,
+                    content: vec![],
+                    name: None,
+                    tool_call_id: None,
+                    tool_calls: vec![ToolCall { id: "c1".to_string(), name: "f".to_string(), arguments: "{}".to_string() }],
+                },
+                finish_reason: Some(FinishReason::ToolCalls),
+                stop_sequence: None,
+            }],
+            usage: Some(crate::canonical::Usage { prompt_tokens: 5, completion_tokens: 2, cached_tokens: 0 }),
+            json: String::new(),
+        };
+        let out = render_response(&resp, Some("m"));
+        assert_eq!(out.id, "chatcmpl-r1");
+        assert_eq!(out.model, "gpt-x");
+        assert_eq!(out.choices[0].message.content, None);
+        assert_eq!(out.choices[0].message.tool_calls[0].function.name, "f");
+        assert_eq!(out.choices[0].finish_reason, "tool_calls");
+        assert_eq!(out.usage.unwrap().total_tokens, 7);
+    }
+
+    #[tokio::test]
+    async fn streams_chat_completion_chunks() {
+        let metrics = Metrics::new();
+        let registry = AdapterRegistryState::with_echo(RequestReplayConfig::default(), metrics.clone()).await;
+        let body = r#"{"model":"m","messages":[{"role":"user","content":"hello there"}],"stream":true}"#;
+        let req = Request::builder().method("POST").uri("/v1/chat/completions").body(Body::from(body)).unwrap();
+        let resp = handle_chat_completions(
+            req,
+            registry,
+            metrics,
+            Authenticator::new(&AuthConfig::default()).unwrap(),
+            RateLimits::new(&RateLimitConfig::default()).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+        let raw = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let mut events: Vec<&str> = std::str::from_utf8(&raw)
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|event| event.strip_prefix("data: ").expect(event))
+            .collect();
+        assert_eq!(events.pop(), Some("[DONE]"));
+        let chunks: Vec<serde_json::Value> = events.iter().map(|e| serde_json::from_str(e).unwrap()).collect();
+        assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk" && c["model"] == "m" && c["id"] == chunks[0]["id"]));
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert!(chunks[1..].iter().all(|c| c["choices"][0]["delta"].get("role").is_none()));
+        let text: String = chunks.iter().filter_map(|c| c["choices"][0]["delta"]["content"].as_str()).collect();
+        assert_eq!(text, "hello there");
+        let (last, rest) = chunks.split_last().unwrap();
+        assert_eq!(last["choices"][0]["finish_reason"], "stop");
+        assert!(rest.iter().all(|c| c["choices"][0]["finish_reason"].is_null()));
+    }
+}
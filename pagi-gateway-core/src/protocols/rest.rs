@@ -6,10 +6,16 @@ use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::canonical::{CanonicalAIRequest, ContentPart, GenerationConstraints, Message, MessageRole, Tool};
-use crate::middleware::{auth, rate_limit::IpRateLimiter};
+use crate::canonical::{CanonicalAIRequest, ContentPart, GenerationConstraints, Message, MessageRole, Tool, ToolCall};
+use crate::middleware::auth::Authenticator;
+use crate::middleware::rate_limit::RateLimits;
+use crate::middleware::token_limit::TokenReservation;
 use crate::middleware::observability::Metrics;
-use crate::registry::AdapterRegistryState;
+use crate::registry::{AdapterRegistryState, ForwardStream};
+
+use super::{
+    forward_error_status, forward_options, json, relay, remote_addr, sse_event, sse_response, with_quota, Admitted, Gate, Rejected,
+};
 
 /// Accept both the legacy MVP shape and the newer canonical-ish shape.
 #[derive(Debug, Deserialize)]
@@ -17,7 +23,7 @@ use crate::registry::AdapterRegistryState;
 pub enum RestIngressRequest {
     V0(LegacyV0Request),
     V1(LegacyV1Request),
-    V2(CanonicalIngressRequest),
+    V2(Box<CanonicalIngressRequest>),
 }
 
 /// Canonical-ish request without requiring client to provide request_id.
@@ -45,6 +51,46 @@ pub struct CanonicalIngressRequest {
     pub response_format: Option<serde_json::Value>,
 }
 
+impl CanonicalIngressRequest {
+    /// The canonical request, expanding content-part shorthands.
+    pub fn into_canonical(self) -> CanonicalAIRequest {
+        let mut req = CanonicalAIRequest::new();
+        if let Some(id) = self.request_id {
+            req.request_id = id;
+        }
+        req.agent_id = self.agent_id;
+        req.session_id = self.session_id;
+        req.messages = self
+            .messages
+            .into_iter()
+            .map(|m| Message {
+                role: m.role,
+                content: m
+                    .content
+                    .into_iter()
+                    .map(|p| match p {
+                        RestContentPartInput::Canonical(p) => p,
+                        RestContentPartInput::Text { text } => ContentPart::Text { text },
+                        RestContentPartInput::Image { image } => ContentPart::Image { url: image.url },
+                        RestContentPartInput::Audio { audio } => ContentPart::Audio { url: audio.url },
+                        RestContentPartInput::File { file } => ContentPart::File { url: file.url, mime_type: file.mime_type },
+                    })
+                    .collect(),
+                name: m.name,
+                tool_call_id: m.tool_call_id,
+                tool_calls: m.tool_calls,
+            })
+            .collect();
+        req.tools = self.tools;
+        req.tool_choice = self.tool_choice;
+        req.constraints = self.constraints.unwrap_or_default();
+        req.preferred_model = self.preferred_model;
+        req.metadata = self.metadata;
+        req.response_format = self.response_format;
+        req
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RestMessageInput {
     pub role: MessageRole,
@@ -54,6 +100,8 @@ pub struct RestMessageInput {
     pub name: Option<String>,
     #[serde(default)]
     pub tool_call_id: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -101,37 +149,34 @@ pub enum LegacyPayload {
     Json { json: serde_json::Value },
 }
 
+/// One SSE `data:` payload when `constraints.stream` is set.
 #[derive(Debug, Serialize)]
-pub struct RestCallResponse {
+pub struct RestStreamChunk {
     pub request_id: String,
     pub adapter_id: String,
-    pub json: String,
+    pub delta: String,
+    pub done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<String>,
 }
 
 pub async fn handle_call(
     req: Request<Body>,
     registry: AdapterRegistryState,
     metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
 ) -> Result<Response<Body>, hyper::Error> {
     let started = Instant::now();
-    let limiter = IpRateLimiter::new_per_second(50);
-
-    if !auth::authorize(&req) {
-        metrics.inc_requests("rest", "401");
-        return Ok(status(StatusCode::UNAUTHORIZED, "unauthorized"));
-    }
-
-    let ip = req
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("127.0.0.1")
-        .to_string();
-    if !limiter.check(ip) {
-        metrics.inc_requests("rest", "429");
-        return Ok(status(StatusCode::TOO_MANY_REQUESTS, "rate limited"));
-    }
+    let gate = Gate::new(&auth, &limits, &metrics, "rest");
+    let caller = match gate.caller(remote_addr(&req), req.headers()).await {
+        Ok(c) => c,
+        Err(r) => return Ok(rejected(r)),
+    };
 
+    let mut opts = forward_options(req.headers());
     let body = hyper::body::to_bytes(req.into_body()).await?;
     let parsed: RestIngressRequest = match serde_json::from_slice(&body) {
         Ok(v) => v,
@@ -143,41 +188,7 @@ pub async fn handle_call(
     };
 
     let canonical = match parsed {
-        RestIngressRequest::V2(v) => {
-            let mut req = CanonicalAIRequest::new();
-            if let Some(id) = v.request_id {
-                req.request_id = id;
-            }
-            req.agent_id = v.agent_id;
-            req.session_id = v.session_id;
-            req.messages = v
-                .messages
-                .into_iter()
-                .map(|m| Message {
-                    role: m.role,
-                    content: m
-                        .content
-                        .into_iter()
-                        .map(|p| match p {
-                            RestContentPartInput::Canonical(p) => p,
-                            RestContentPartInput::Text { text } => ContentPart::Text { text },
-                            RestContentPartInput::Image { image } => ContentPart::Image { url: image.url },
-                            RestContentPartInput::Audio { audio } => ContentPart::Audio { url: audio.url },
-                            RestContentPartInput::File { file } => ContentPart::File { url: file.url, mime_type: file.mime_type },
-                        })
-                        .collect(),
-                    name: m.name,
-                    tool_call_id: m.tool_call_id,
-                })
-                .collect();
-            req.tools = v.tools;
-            req.tool_choice = v.tool_choice;
-            req.constraints = v.constraints.unwrap_or_default();
-            req.preferred_model = v.preferred_model;
-            req.metadata = v.metadata;
-            req.response_format = v.response_format;
-            req
-        }
+        RestIngressRequest::V2(v) => v.into_canonical(),
         RestIngressRequest::V1(v) => {
             // Legacy request maps to a single user message.
             let mut req = CanonicalAIRequest::chat_text(Some(v.agent_id), match v.payload {
@@ -190,14 +201,13 @@ pub async fn handle_call(
         RestIngressRequest::V0(v) => CanonicalAIRequest::chat_text(Some(v.agent_id), v.payload),
     };
 
-    // Convenience: allow clients to specify a preferred provider without needing to know
-    // the internal routing key name.
     let mut canonical = canonical;
-    if !canonical.metadata.contains_key("adapter_id") {
-        if let Some(p) = canonical.metadata.get("preferred_provider").cloned() {
-            canonical.metadata.insert("adapter_id".to_string(), p);
-        }
-    }
+    pin_preferred_provider(&mut canonical);
+
+    let Admitted { reservation, quota } = match gate.admit(&caller, &mut canonical, &mut opts) {
+        Ok(a) => a,
+        Err(r) => return Ok(rejected(r)),
+    };
 
     // If client sent an empty messages list, treat as invalid.
     if canonical.messages.is_empty() {
@@ -207,25 +217,90 @@ pub async fn handle_call(
 
     info!(request_id=%canonical.request_id, "canonicalized rest request");
 
-    let resp = match registry.forward(canonical.clone()).await {
+    if canonical.constraints.stream {
+        let stream = match registry.forward_stream_with(canonical, opts.clone()).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error=%e, "forward failed");
+                let (code, label, msg) = forward_error_status(&e);
+                metrics.inc_requests("rest", label);
+                return Ok(status(code, msg));
+            }
+        };
+        metrics.inc_requests("rest", "200");
+        // Time to first byte; the stream itself may stay open much longer.
+        metrics.observe_latency("rest", started.elapsed().as_secs_f64());
+        return Ok(with_quota(stream_response(stream, reservation), quota));
+    }
+
+    let resp = match registry.forward_with(canonical.clone(), opts).await {
         Ok(r) => r,
         Err(e) => {
             warn!(error=%e, "forward failed");
-            metrics.inc_requests("rest", "503");
-            return Ok(status(StatusCode::SERVICE_UNAVAILABLE, "no adapter available"));
+            let (code, label, msg) = forward_error_status(&e);
+            metrics.inc_requests("rest", label);
+            return Ok(status(code, msg));
         }
     };
 
     metrics.inc_requests("rest", "200");
     metrics.observe_latency("rest", started.elapsed().as_secs_f64());
 
-    let out = RestCallResponse { request_id: resp.request_id, adapter_id: resp.adapter_id, json: resp.json };
-    Ok(json(StatusCode::OK, &out))
+    reservation.settle(resp.usage);
+    if let Some(usage) = resp.usage {
+        metrics.observe_usage(&resp.adapter_id, usage);
+    }
+
+    // The canonical response keeps `request_id`/`adapter_id`/`json` at the top level, so
+    // clients of the original `{request_id, adapter_id, json}` shape keep working.
+    Ok(with_quota(json(StatusCode::OK, &resp), quota))
+}
+
+/// Convenience: allow clients to specify a preferred provider without needing to know the
+/// internal routing key name.
+pub(crate) fn pin_preferred_provider(req: &mut CanonicalAIRequest) {
+    if !req.metadata.contains_key("adapter_id") {
+        if let Some(p) = req.metadata.get("preferred_provider").cloned() {
+            req.metadata.insert("adapter_id".to_string(), p);
+        }
+    }
+}
+
+/// Relay adapter chunks to the client as `text/event-stream`.
+///
+/// Each chunk is a `data:` event carrying a [`RestStreamChunk`]; adapter failures mid-stream
+/// are reported as a final `event: error`.
+fn stream_response(stream: ForwardStream, reservation: TokenReservation) -> Response<Body> {
+    sse_response(relay(stream, reservation, |item| match item {
+        Ok(c) => {
+            let chunk = RestStreamChunk {
+                request_id: c.request_id,
+                adapter_id: c.adapter_id,
+                delta: c.delta,
+                done: c.done,
+                finish_reason: Some(c.finish_reason).filter(|r| !r.is_empty()),
+                json: Some(c.json).filter(|j| !j.is_empty()),
+            };
+            sse_event(None, &serde_json::to_string(&chunk).unwrap())
+        }
+        Err(e) => sse_event(Some("error"), &serde_json::json!({ "error": e.to_string() }).to_string()),
+    }))
+}
+
+fn rejected(r: Rejected) -> Response<Body> {
+    let mut resp = status(r.status(), &r.to_string());
+    r.set_headers(resp.headers_mut());
+    resp
+}
+
+fn status(status: StatusCode, msg: &str) -> Response<Body> {
+    Response::builder().status(status).body(Body::from(msg.to_string())).unwrap()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{AuthConfig, RateLimitConfig, RequestReplayConfig};
 
     #[test]
     fn parses_legacy_v0() {
@@ -249,17 +324,36 @@ mod tests {
         assert_eq!(parsed.messages.len(), 1);
         assert_eq!(parsed.messages[0].content.len(), 2);
     }
-}
 
-fn json<T: serde::Serialize>(status: StatusCode, v: &T) -> Response<Body> {
-    let body = serde_json::to_vec(v).unwrap();
-    Response::builder()
-        .status(status)
-        .header("content-type", "application/json")
-        .body(Body::from(body))
-        .unwrap()
-}
+    #[tokio::test]
+    async fn streams_server_sent_events_from_the_adapter() {
+        let metrics = Metrics::new();
+        let registry = AdapterRegistryState::with_echo(RequestReplayConfig::default(), metrics.clone()).await;
+        let body = r#"{"messages":[{"role":"user","content":[{"text":"hello there"}]}],"constraints":{"stream":true}}"#;
+        let req = Request::builder().method("POST").uri("/v1/ai:call").body(Body::from(body)).unwrap();
+        let resp = handle_call(
+            req,
+            registry,
+            metrics,
+            Authenticator::new(&AuthConfig::default()).unwrap(),
+            RateLimits::new(&RateLimitConfig::default()).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
 
-fn status(status: StatusCode, msg: &str) -> Response<Body> {
-    Response::builder().status(status).body(Body::from(msg.to_string())).unwrap()
+        let raw = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let chunks: Vec<serde_json::Value> = std::str::from_utf8(&raw)
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|event| serde_json::from_str(event.strip_prefix("data: ").expect(event)).unwrap())
+            .collect();
+        let text: String = chunks.iter().map(|c| c["delta"].as_str().unwrap()).collect();
+        assert_eq!(text, "hello there");
+        assert!(chunks.iter().all(|c| c["adapter_id"] == "echo" && c["request_id"] == chunks[0]["request_id"]));
+        let last = chunks.last().unwrap();
+        assert_eq!(last["done"], true);
+        assert_eq!(last["finish_reason"], "stop");
+    }
 }
@@ -1,4 +1,549 @@
-// WebSocket ingress placeholder.
-//
-// The long-term direction is to allow streaming (token streaming, events) over WS.
+//! WebSocket ingress: `GET /v1/ws`.
+//!
+//! One socket carries a whole conversation. The client authenticates once, at the handshake
+//! (`authorization`/`x-api-key` headers, or `?access_token=` for browsers that cannot set
+//! headers), and every call on the socket runs as that principal. Frames are JSON text:
+//!
+//! - client: `{"type":"request","id":"c1","request":{...}}` starts a streamed call (the request
+//!   has the REST canonical shape; `id` defaults to its `request_id`),
+//!   `{"type":"cancel","id":"c1"}` stops one, and `{"type":"ping"}` is answered with `pong`.
+//! - server: `session` once after the upgrade, then per call `start`, any number of `delta`s
+//!   and a final `done`, `cancelled` or `error` (with an HTTP-style `status`).
+//!
+//! The socket's `session_id` (`?session_id=`, else generated) is stamped on every request, and a
+//! request naming another session is refused. Each request frame is rate limited like an HTTP
+//! request, and calls still running when the socket closes are cancelled.
 
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures_util::{SinkExt, StreamExt};
+use hyper::header::{HeaderValue, AUTHORIZATION, CONNECTION, SEC_WEBSOCKET_ACCEPT, UPGRADE};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, HeaderMap, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::middleware::auth::Authenticator;
+use crate::middleware::observability::Metrics;
+use crate::middleware::rate_limit::RateLimits;
+use crate::registry::AdapterRegistryState;
+
+use super::rest::{pin_preferred_provider, CanonicalIngressRequest};
+use super::{forward_error_status, forward_options, relay, remote_addr, Admitted, Caller, Gate, Rejected};
+
+pub const PATH: &str = "/v1/ws";
+
+const PROTOCOL: &str = "ws";
+
+/// Calls one socket may have running at once.
+const MAX_CALLS: usize = 16;
+
+const OUTBOX: usize = 64;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    Request {
+        #[serde(default)]
+        id: Option<String>,
+        request: Box<CanonicalIngressRequest>,
+    },
+    Cancel {
+        id: String,
+    },
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame {
+    Session {
+        session_id: String,
+    },
+    Start {
+        id: String,
+        request_id: String,
+        adapter_id: String,
+    },
+    Delta {
+        id: String,
+        delta: String,
+    },
+    Done {
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        finish_reason: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        json: Option<String>,
+    },
+    Cancelled {
+        id: String,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        status: u16,
+        error: String,
+        /// Seconds, on 429.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+    Pong,
+}
+
+impl ServerFrame {
+    fn error(id: Option<String>, status: StatusCode, error: impl Into<String>) -> Self {
+        ServerFrame::Error { id, status: status.as_u16(), error: error.into(), retry_after: None }
+    }
+
+    fn rejected(id: Option<String>, r: &Rejected) -> Self {
+        let retry_after = match r {
+            Rejected::RateLimited(l) => Some(l.retry_after.as_secs_f64().ceil() as u64),
+            _ => None,
+        };
+        ServerFrame::Error { id, status: r.status().as_u16(), error: r.to_string(), retry_after }
+    }
+}
+
+pub async fn handle(
+    req: Request<Body>,
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+) -> Result<Response<Body>, hyper::Error> {
+    let caller = match Gate::new(&auth, &limits, &metrics, PROTOCOL).caller(remote_addr(&req), &credentials(&req)).await {
+        Ok(c) => c,
+        Err(r) => {
+            let mut resp = status(r.status(), &r.to_string());
+            r.set_headers(resp.headers_mut());
+            return Ok(resp);
+        }
+    };
+    let Some(key) = upgrade_key(&req) else {
+        metrics.inc_requests(PROTOCOL, "400");
+        return Ok(status(StatusCode::BAD_REQUEST, "websocket upgrade required"));
+    };
+
+    let session = Session {
+        registry,
+        metrics,
+        auth,
+        limits,
+        caller,
+        session_id: query_param(&req, "session_id").map(str::to_string).unwrap_or_else(|| Uuid::new_v4().to_string()),
+        peer: remote_addr(&req),
+        headers: req.headers().clone(),
+        calls: Arc::default(),
+    };
+    tokio::spawn(async move {
+        match hyper::upgrade::on(req).await {
+            Ok(upgraded) => session.run(WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await).await,
+            Err(e) => warn!(error=%e, "websocket upgrade failed"),
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_ACCEPT, derive_accept_key(key.as_bytes()))
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Running calls by id. A cancelled call keeps its entry (with the sender taken) until it has
+/// wound down, so its id cannot be reused before then.
+type Calls = Arc<Mutex<HashMap<String, Option<oneshot::Sender<()>>>>>;
+
+struct Session {
+    registry: AdapterRegistryState,
+    metrics: Metrics,
+    auth: Authenticator,
+    limits: RateLimits,
+    caller: Caller,
+    session_id: String,
+    peer: Option<SocketAddr>,
+    /// Handshake headers, for `x-forwarded-for` and `x-pagi-timeout-ms`.
+    headers: HeaderMap,
+    calls: Calls,
+}
+
+impl Session {
+    async fn run(self, ws: WebSocketStream<Upgraded>) {
+        let (mut sink, mut stream) = ws.split();
+        let (out, mut outbox) = mpsc::channel::<ServerFrame>(OUTBOX);
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = outbox.recv().await {
+                if sink.send(Message::Text(serde_json::to_string(&frame).unwrap())).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        info!(session_id=%self.session_id, "websocket session opened");
+        let _ = out.send(ServerFrame::Session { session_id: self.session_id.clone() }).await;
+        while let Some(msg) = stream.next().await {
+            let frame = match msg {
+                Ok(Message::Text(text)) => serde_json::from_str::<ClientFrame>(&text),
+                Ok(Message::Binary(bytes)) => serde_json::from_slice::<ClientFrame>(&bytes),
+                Ok(Message::Close(_)) | Err(_) => break,
+                // Pings are answered by tungstenite.
+                Ok(_) => continue,
+            };
+            let reply = match frame {
+                Ok(frame) => self.on_frame(frame, &out),
+                Err(e) => Some(ServerFrame::error(None, StatusCode::BAD_REQUEST, format!("invalid frame: {e}"))),
+            };
+            if let Some(reply) = reply {
+                let _ = out.send(reply).await;
+            }
+        }
+
+        // Dropping the senders cancels whatever is still running.
+        self.calls.lock().unwrap().clear();
+        drop(out);
+        let _ = writer.await;
+        info!(session_id=%self.session_id, "websocket session closed");
+    }
+
+    /// Handle one client frame, returning an immediate reply if there is one.
+    fn on_frame(&self, frame: ClientFrame, out: &mpsc::Sender<ServerFrame>) -> Option<ServerFrame> {
+        match frame {
+            ClientFrame::Ping => Some(ServerFrame::Pong),
+            ClientFrame::Cancel { id } => match self.calls.lock().unwrap().get_mut(&id) {
+                Some(cancel) => {
+                    if let Some(tx) = cancel.take() {
+                        let _ = tx.send(());
+                    }
+                    None
+                }
+                None => Some(ServerFrame::error(Some(id), StatusCode::NOT_FOUND, "no such call")),
+            },
+            ClientFrame::Request { id, request } => self.start(id, *request, out).err(),
+        }
+    }
+
+    /// Admit a request frame and spawn its call.
+    fn start(
+        &self,
+        id: Option<String>,
+        request: CanonicalIngressRequest,
+        out: &mpsc::Sender<ServerFrame>,
+    ) -> Result<(), ServerFrame> {
+        let started = Instant::now();
+        let mut canonical = request.into_canonical();
+        let id = id.unwrap_or_else(|| canonical.request_id.to_string());
+        let reject = |status: StatusCode, label: &'static str, msg: String| {
+            self.metrics.inc_requests(PROTOCOL, label);
+            ServerFrame::error(Some(id.clone()), status, msg)
+        };
+        let gate = Gate::new(&self.auth, &self.limits, &self.metrics, PROTOCOL);
+        let rejected = |r: Rejected| ServerFrame::rejected(Some(id.clone()), &r);
+
+        gate.peer(self.peer, &self.headers).map_err(rejected)?;
+        match &canonical.session_id {
+            Some(s) if *s != self.session_id => {
+                return Err(reject(StatusCode::BAD_REQUEST, "400", "session_id does not match this socket".to_string()));
+            }
+            _ => canonical.session_id = Some(self.session_id.clone()),
+        }
+        if canonical.messages.is_empty() {
+            return Err(reject(StatusCode::BAD_REQUEST, "400", "messages required".to_string()));
+        }
+        pin_preferred_provider(&mut canonical);
+
+        let mut opts = forward_options(&self.headers);
+        // No response headers to carry the remaining quota on; an exhausted one still rejects.
+        let Admitted { reservation, .. } = gate.admit(&self.caller, &mut canonical, &mut opts).map_err(rejected)?;
+
+        let mut cancelled = {
+            let mut calls = self.calls.lock().unwrap();
+            if calls.contains_key(&id) {
+                return Err(reject(StatusCode::CONFLICT, "409", "call id already in use".to_string()));
+            }
+            if calls.len() >= MAX_CALLS {
+                return Err(reject(StatusCode::TOO_MANY_REQUESTS, "429", "too many calls on this socket".to_string()));
+            }
+            let (tx, rx) = oneshot::channel();
+            calls.insert(id.clone(), Some(tx));
+            rx
+        };
+        info!(request_id=%canonical.request_id, call_id=%id, "canonicalized ws request");
+
+        let registry = self.registry.clone();
+        let metrics = self.metrics.clone();
+        let calls = self.calls.clone();
+        let out = out.clone();
+        tokio::spawn(async move {
+            let forwarded = tokio::select! {
+                r = registry.forward_stream_with(canonical, opts) => Some(r),
+                _ = &mut cancelled => None,
+            };
+            let last = match forwarded {
+                None => ServerFrame::Cancelled { id: id.clone() },
+                Some(Err(e)) => {
+                    warn!(error=%e, "forward failed");
+                    let (code, label, msg) = forward_error_status(&e);
+                    metrics.inc_requests(PROTOCOL, label);
+                    ServerFrame::error(Some(id.clone()), code, msg)
+                }
+                Some(Ok(stream)) => {
+                    metrics.inc_requests(PROTOCOL, "200");
+                    // Time to first byte; the stream itself may stay open much longer.
+                    metrics.observe_latency(PROTOCOL, started.elapsed().as_secs_f64());
+                    let start = ServerFrame::Start {
+                        id: id.clone(),
+                        request_id: stream.request_id.clone(),
+                        adapter_id: stream.adapter_id.clone(),
+                    };
+                    let _ = out.send(start).await;
+                    // Dropped when the call ends, which stops the relay and the adapter stream.
+                    let mut chunks = relay(stream, reservation, |item| item);
+                    loop {
+                        let item = tokio::select! {
+                            item = chunks.recv() => item,
+                            _ = &mut cancelled => break ServerFrame::Cancelled { id: id.clone() },
+                        };
+                        match item {
+                            Some(Ok(c)) => {
+                                if !c.delta.is_empty() {
+                                    let _ = out.send(ServerFrame::Delta { id: id.clone(), delta: c.delta }).await;
+                                }
+                                if c.done {
+                                    break ServerFrame::Done {
+                                        id: id.clone(),
+                                        finish_reason: Some(c.finish_reason).filter(|r| !r.is_empty()),
+                                        json: Some(c.json).filter(|j| !j.is_empty()),
+                                    };
+                                }
+                            }
+                            Some(Err(e)) => break ServerFrame::error(Some(id.clone()), StatusCode::BAD_GATEWAY, e.to_string()),
+                            None => break ServerFrame::Done { id: id.clone(), finish_reason: None, json: None },
+                        }
+                    }
+                }
+            };
+            calls.lock().unwrap().remove(&id);
+            let _ = out.send(last).await;
+        });
+        Ok(())
+    }
+}
+
+/// The `sec-websocket-key` of a valid version-13 upgrade request.
+pub(crate) fn upgrade_key(req: &Request<Body>) -> Option<String> {
+    let headers = req.headers();
+    let has = |name, value: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case(value))
+    };
+    if req.method() != hyper::Method::GET || !has(CONNECTION, "upgrade") || !has(UPGRADE, "websocket") {
+        return None;
+    }
+    if headers.get("sec-websocket-version").and_then(|v| v.to_str().ok()) != Some("13") {
+        return None;
+    }
+    headers.get("sec-websocket-key").and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+/// Handshake headers, with `?access_token=` standing in for a missing `authorization` header.
+pub(crate) fn credentials(req: &Request<Body>) -> HeaderMap {
+    let mut headers = req.headers().clone();
+    if !headers.contains_key(AUTHORIZATION) && !headers.contains_key("x-api-key") {
+        if let Some(v) = query_param(req, "access_token").and_then(|t| HeaderValue::from_str(&format!("Bearer {t}")).ok()) {
+            headers.insert(AUTHORIZATION, v);
+        }
+    }
+    headers
+}
+
+fn query_param<'a>(req: &'a Request<Body>, name: &str) -> Option<&'a str> {
+    req.uri()
+        .query()?
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+        .filter(|v| !v.is_empty())
+}
+
+fn status(status: StatusCode, msg: &str) -> Response<Body> {
+    Response::builder().status(status).body(Body::from(msg.to_string())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, JwtConfig, RateLimitConfig, RequestReplayConfig};
+    use crate::middleware::jwt::tests::write_jwks;
+    use crate::proto::adapter_service_server::AdapterService;
+    use crate::proto::{AdapterCapabilities, AdapterInfo, CanonicalAiChunk, CanonicalAiRequest, CanonicalAiResponse};
+    use hyper::service::{make_service_fn, service_fn};
+    use serde_json::{json, Value};
+    use std::pin::Pin;
+    use std::time::Duration;
+    use tonic::Status;
+
+    /// Streams one word, then goes quiet until the call is cancelled.
+    struct StallingAdapter;
+
+    #[tonic::async_trait]
+    impl AdapterService for StallingAdapter {
+        async fn process(
+            &self,
+            _request: tonic::Request<CanonicalAiRequest>,
+        ) -> Result<tonic::Response<CanonicalAiResponse>, Status> {
+            Err(Status::unimplemented("stalling"))
+        }
+
+        type ProcessStreamStream = Pin<Box<dyn futures_util::Stream<Item = Result<CanonicalAiChunk, Status>> + Send>>;
+
+        async fn process_stream(
+            &self,
+            _request: tonic::Request<CanonicalAiRequest>,
+        ) -> Result<tonic::Response<Self::ProcessStreamStream>, Status> {
+            let first = CanonicalAiChunk { delta: "thinking ".to_string(), ..Default::default() };
+            Ok(tonic::Response::new(Box::pin(futures_util::stream::iter([Ok(first)]).chain(futures_util::stream::pending()))))
+        }
+    }
+
+    /// Serve the socket over the echo adapter; the registry is returned for adding more.
+    async fn serve(auth: AuthConfig) -> (SocketAddr, AdapterRegistryState) {
+        let metrics = Metrics::new();
+        let registry = AdapterRegistryState::with_echo(RequestReplayConfig::default(), metrics.clone()).await;
+        let auth = Authenticator::new(&auth).unwrap();
+        let limits = RateLimits::new(&RateLimitConfig::default()).unwrap();
+
+        let state = registry.clone();
+        let make_svc = make_service_fn(move |_| {
+            let (registry, metrics, auth, limits) = (state.clone(), metrics.clone(), auth.clone(), limits.clone());
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req| {
+                    handle(req, registry.clone(), metrics.clone(), auth.clone(), limits.clone())
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, registry)
+    }
+
+    async fn next_frame<S>(ws: &mut S) -> Value
+    where
+        S: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        loop {
+            if let Message::Text(t) = ws.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&t).unwrap();
+            }
+        }
+    }
+
+    fn request(id: &str, text: &str) -> Message {
+        let frame = json!({ "type": "request", "id": id, "request": { "messages": [{ "role": "user", "content": [{ "text": text }] }] } });
+        Message::Text(frame.to_string())
+    }
+
+    #[tokio::test]
+    async fn streams_calls_within_one_session() {
+        let (addr, _) = serve(AuthConfig::default()).await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}{PATH}?session_id=s1")).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await, json!({ "type": "session", "session_id": "s1" }));
+
+        for _ in 0..2 {
+            ws.send(request("c1", "hello there")).await.unwrap();
+            assert_eq!(next_frame(&mut ws).await["type"], "start");
+            let mut text = String::new();
+            let done = loop {
+                let f = next_frame(&mut ws).await;
+                match f["type"].as_str().unwrap() {
+                    "delta" => text.push_str(f["delta"].as_str().unwrap()),
+                    _ => break f,
+                }
+            };
+            assert_eq!(done["type"], "done");
+            assert_eq!(done["id"], "c1");
+            assert_eq!(text.trim(), "hello there");
+        }
+
+        ws.send(Message::Text(json!({ "type": "ping" }).to_string())).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await, json!({ "type": "pong" }));
+        ws.send(Message::Text(json!({ "type": "cancel", "id": "nope" }).to_string())).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["status"], 404);
+
+        let other = json!({ "type": "request", "request": { "session_id": "s2", "messages": [{ "role": "user", "content": [{ "text": "x" }] }] } });
+        ws.send(Message::Text(other.to_string())).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["status"], 400);
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_a_running_call_and_frees_its_id() {
+        let (addr, registry) = serve(AuthConfig::default()).await;
+        let info = AdapterInfo {
+            adapter_id: "slow".to_string(),
+            capabilities: Some(AdapterCapabilities { streaming: true, ..Default::default() }),
+            ..Default::default()
+        };
+        registry.register_in_process(info, StallingAdapter).await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}{PATH}")).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["type"], "session");
+
+        let slow = json!({ "type": "request", "id": "c1", "request": {
+            "messages": [{ "role": "user", "content": [{ "text": "think" }] }],
+            "metadata": { "adapter_id": "slow" } } });
+        ws.send(Message::Text(slow.to_string())).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["adapter_id"], "slow");
+        assert_eq!(next_frame(&mut ws).await, json!({ "type": "delta", "id": "c1", "delta": "thinking " }));
+
+        ws.send(Message::Text(json!({ "type": "cancel", "id": "c1" }).to_string())).await.unwrap();
+        let cancelled = tokio::time::timeout(Duration::from_secs(5), next_frame(&mut ws)).await.unwrap();
+        assert_eq!(cancelled, json!({ "type": "cancelled", "id": "c1" }));
+
+        // The call is gone: its id is free again and the adapter slot was given back.
+        ws.send(Message::Text(json!({ "type": "cancel", "id": "c1" }).to_string())).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["status"], 404);
+        ws.send(request("c1", "hello")).await.unwrap();
+        assert_eq!(next_frame(&mut ws).await["type"], "start");
+        let in_flight = || async { registry.list().await.into_iter().find(|a| a.adapter_id == "slow").unwrap().in_flight };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while in_flight().await > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("adapter stream was not cancelled");
+    }
+
+    #[tokio::test]
+    async fn handshake_without_credentials_is_rejected() {
+        let jwt = JwtConfig { jwks_path: Some(write_jwks("ws", &[("k1", "secret")])), ..Default::default() };
+        let (addr, _) = serve(AuthConfig { jwt: Some(jwt), required: true, ..Default::default() }).await;
+        match tokio_tungstenite::connect_async(format!("ws://{addr}{PATH}")).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => assert_eq!(resp.status().as_u16(), 401),
+            other => panic!("expected 401, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn plain_get_is_not_upgraded() {
+        let (addr, _) = serve(AuthConfig::default()).await;
+        let resp = hyper::Client::new().get(format!("http://{addr}{PATH}").parse().unwrap()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,192 @@
+//! Authentication for the mutating `AdapterRegistry` RPCs (Register, Heartbeat, Deregister,
+//! Drain).
+//!
+//! Adapters listed under `core.registry.auth.tokens` must send their pre-shared token as
+//! `authorization: Bearer <token>` gRPC metadata. When the registry listens with mTLS, TCP
+//! clients must present a certificate, and with `require_san_match` it must also carry the
+//! adapter id as a DNS or URI subject alternative name. The TLS layer itself only asks for client
+//! certificates, because the same port serves `GatewayService` to clients that have none; the
+//! requirement is enforced here. The Unix socket listener has no TLS and skips the certificate
+//! checks but is still subject to tokens.
+
+use std::collections::HashMap;
+
+use subtle::ConstantTimeEq;
+use tonic::{Request, Status};
+use tracing::warn;
+use x509_parser::extensions::GeneralName;
+
+use crate::config::RegistrationAuthConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AuthFailure {
+    #[error("adapter token required")]
+    MissingToken,
+    #[error("adapter token invalid")]
+    BadToken,
+    #[error("adapter id not allowed to register")]
+    Unlisted,
+    #[error("client certificate does not name this adapter")]
+    SanMismatch,
+    #[error("client certificate required")]
+    MissingCertificate,
+}
+
+impl AuthFailure {
+    /// Label for `pagi_registration_rejected_total`.
+    pub fn reason(self) -> &'static str {
+        match self {
+            AuthFailure::MissingToken => "missing_token",
+            AuthFailure::BadToken => "bad_token",
+            AuthFailure::Unlisted => "unlisted",
+            AuthFailure::SanMismatch => "san_mismatch",
+            AuthFailure::MissingCertificate => "missing_certificate",
+        }
+    }
+}
+
+impl From<AuthFailure> for Status {
+    fn from(failure: AuthFailure) -> Self {
+        Status::unauthenticated(failure.to_string())
+    }
+}
+
+pub(crate) struct RegistrationAuth {
+    /// `None` when the configured `token_env` is unset; such adapters can never authenticate.
+    tokens: HashMap<String, Option<Vec<u8>>>,
+    allow_unlisted: bool,
+    require_cert: bool,
+    require_san_match: bool,
+}
+
+impl RegistrationAuth {
+    pub(crate) fn new(cfg: &RegistrationAuthConfig) -> Self {
+        let tokens = cfg
+            .tokens
+            .iter()
+            .map(|(id, t)| {
+                let token = t.resolve();
+                if token.is_none() {
+                    warn!(adapter_id=%id, "no token configured for adapter; its registrations will be rejected");
+                }
+                (id.clone(), token.map(String::into_bytes))
+            })
+            .collect();
+        Self {
+            tokens,
+            allow_unlisted: cfg.allow_unlisted,
+            require_cert: cfg.tls.is_some(),
+            require_san_match: cfg.tls.as_ref().is_some_and(|t| t.require_san_match),
+        }
+    }
+
+    /// With mTLS on, a TCP caller must have presented a client certificate.
+    pub(crate) fn check_peer<T>(&self, req: &Request<T>) -> Result<(), AuthFailure> {
+        if self.require_cert && req.remote_addr().is_some() && req.peer_certs().is_none() {
+            return Err(AuthFailure::MissingCertificate);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check<T>(&self, adapter_id: &str, req: &Request<T>) -> Result<(), AuthFailure> {
+        self.check_peer(req)?;
+        match self.tokens.get(adapter_id) {
+            Some(expected) => {
+                let presented = bearer(req).ok_or(AuthFailure::MissingToken)?;
+                let ok = expected.as_ref().is_some_and(|e| bool::from(e.as_slice().ct_eq(presented.as_bytes())));
+                if !ok {
+                    return Err(AuthFailure::BadToken);
+                }
+            }
+            None if !self.allow_unlisted => return Err(AuthFailure::Unlisted),
+            None => {}
+        }
+
+        if self.require_san_match {
+            if let Some(certs) = req.peer_certs() {
+                let leaf = certs.first().ok_or(AuthFailure::SanMismatch)?;
+                if !san_names(leaf.as_ref()).iter().any(|n| n == adapter_id) {
+                    return Err(AuthFailure::SanMismatch);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn bearer<T>(req: &Request<T>) -> Option<&str> {
+    req.metadata().get("authorization")?.to_str().ok()?.strip_prefix("Bearer ").map(str::trim)
+}
+
+/// DNS and URI subject alternative names of a DER certificate.
+fn san_names(der: &[u8]) -> Vec<String> {
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(der) else {
+        return Vec::new();
+    };
+    let Ok(Some(san)) = cert.subject_alternative_name() else {
+        return Vec::new();
+    };
+    san.value
+        .general_names
+        .iter()
+        .filter_map(|n| match n {
+            GeneralName::DNSName(s) | GeneralName::URI(s) => Some(s.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TokenConfig;
+
+    fn auth(allow_unlisted: bool) -> RegistrationAuth {
+        let mut cfg = RegistrationAuthConfig { allow_unlisted, ..Default::default() };
+        cfg.tokens.insert("openrouter".to_string(), TokenConfig { token: Some("s3cret".to_string()), token_env: None });
+        RegistrationAuth::new(&cfg)
+    }
+
+    fn req(token: Option<&str>) -> Request<()> {
+        let mut r = Request::new(());
+        if let Some(t) = token {
+            r.metadata_mut().insert("authorization", format!("Bearer {t}").parse().unwrap());
+        }
+        r
+    }
+
+    #[test]
+    fn listed_adapters_need_their_token() {
+        let a = auth(true);
+        assert_eq!(a.check("openrouter", &req(None)), Err(AuthFailure::MissingToken));
+        assert_eq!(a.check("openrouter", &req(Some("guess"))), Err(AuthFailure::BadToken));
+        assert_eq!(a.check("openrouter", &req(Some("s3cret"))), Ok(()));
+        assert_eq!(a.check("python", &req(None)), Ok(()));
+    }
+
+    #[test]
+    fn unlisted_adapters_can_be_refused() {
+        assert_eq!(auth(false).check("python", &req(None)), Err(AuthFailure::Unlisted));
+    }
+
+    #[test]
+    fn mtls_requires_a_certificate_over_tcp() {
+        let tls = crate::config::RegistryTlsConfig {
+            cert_path: String::new(),
+            key_path: String::new(),
+            client_ca_path: String::new(),
+            require_san_match: false,
+        };
+        let a = RegistrationAuth::new(&RegistrationAuthConfig { tls: Some(tls), ..Default::default() });
+        // Unix socket callers have no peer address and no TLS.
+        assert_eq!(a.check("python", &req(None)), Ok(()));
+
+        let mut tcp = req(None);
+        tcp.extensions_mut().insert(tonic::transport::server::TcpConnectInfo {
+            local_addr: None,
+            remote_addr: Some("127.0.0.1:5000".parse().unwrap()),
+        });
+        assert_eq!(a.check("python", &tcp), Err(AuthFailure::MissingCertificate));
+        assert_eq!(a.check_peer(&tcp), Err(AuthFailure::MissingCertificate));
+    }
+}
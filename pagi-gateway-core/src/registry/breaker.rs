@@ -0,0 +1,221 @@
+//! Per-adapter circuit breakers.
+//!
+//! A closed breaker trips open after `consecutive_failures` failures in a row, or once the
+//! failure rate over the last `window` calls reaches `error_rate`. Open adapters are skipped by
+//! routing until `cooldown_ms` has passed; then a single probe request is let through
+//! (half-open), which either closes the breaker or re-opens it for another cooldown.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use tonic::{Code, Status};
+
+use crate::config::BreakerConfig;
+use crate::proto::BreakerState as ProtoBreakerState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn to_proto(self) -> ProtoBreakerState {
+        match self {
+            BreakerState::Closed => ProtoBreakerState::Closed,
+            BreakerState::Open => ProtoBreakerState::Open,
+            BreakerState::HalfOpen => ProtoBreakerState::HalfOpen,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    state: BreakerState,
+    opened_at: Instant,
+    consecutive_failures: u32,
+    /// Recent outcomes, `true` for failure; at most `window` long.
+    outcomes: VecDeque<bool>,
+    probing: bool,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            opened_at: Instant::now(),
+            consecutive_failures: 0,
+            outcomes: VecDeque::new(),
+            probing: false,
+        }
+    }
+}
+
+impl BreakerConfig {
+    pub fn enabled(&self) -> bool {
+        self.consecutive_failures > 0 || self.error_rate > 0.0
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.cooldown_ms)
+    }
+}
+
+impl Breaker {
+    pub(crate) fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Whether routing should consider this adapter at `now`, without claiming the probe slot.
+    pub(crate) fn available(&self, cfg: &BreakerConfig, now: Instant) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => now.saturating_duration_since(self.opened_at) >= cfg.cooldown(),
+            BreakerState::HalfOpen => !self.probing,
+        }
+    }
+
+    /// Claim permission for one call. Past the cooldown an open breaker goes half-open and this
+    /// call becomes its only probe. Returns the new state if it changed.
+    pub(crate) fn try_acquire(&mut self, cfg: &BreakerConfig, now: Instant) -> Result<Option<BreakerState>, ()> {
+        if !self.available(cfg, now) {
+            return Err(());
+        }
+        match self.state {
+            BreakerState::Closed => Ok(None),
+            BreakerState::Open => {
+                self.state = BreakerState::HalfOpen;
+                self.probing = true;
+                Ok(Some(BreakerState::HalfOpen))
+            }
+            BreakerState::HalfOpen => {
+                self.probing = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Give back a probe slot claimed by [`try_acquire`](Self::try_acquire) for a call that
+    /// ended without an outcome (never admitted, hedged out or cancelled).
+    pub(crate) fn release_probe(&mut self) {
+        if self.state == BreakerState::HalfOpen {
+            self.probing = false;
+        }
+    }
+
+    /// Record a call outcome. Returns the new state if it changed.
+    pub(crate) fn record(&mut self, cfg: &BreakerConfig, failed: bool, now: Instant) -> Option<BreakerState> {
+        if self.state == BreakerState::HalfOpen {
+            self.probing = false;
+            return Some(if failed { self.open(now) } else { self.close() });
+        }
+        if self.state == BreakerState::Open {
+            // A call that started before the breaker tripped; it does not change anything.
+            return None;
+        }
+
+        self.outcomes.push_back(failed);
+        while self.outcomes.len() > cfg.window.max(1) as usize {
+            self.outcomes.pop_front();
+        }
+        self.consecutive_failures = if failed { self.consecutive_failures + 1 } else { 0 };
+
+        let by_streak = cfg.consecutive_failures > 0 && self.consecutive_failures >= cfg.consecutive_failures;
+        let by_rate = cfg.error_rate > 0.0
+            && self.outcomes.len() >= cfg.window as usize
+            && self.failure_rate() >= cfg.error_rate;
+        (failed && (by_streak || by_rate)).then(|| self.open(now))
+    }
+
+    fn failure_rate(&self) -> f64 {
+        self.outcomes.iter().filter(|f| **f).count() as f64 / self.outcomes.len().max(1) as f64
+    }
+
+    fn open(&mut self, now: Instant) -> BreakerState {
+        self.state = BreakerState::Open;
+        self.opened_at = now;
+        BreakerState::Open
+    }
+
+    fn close(&mut self) -> BreakerState {
+        *self = Self::default();
+        BreakerState::Closed
+    }
+}
+
+/// Whether a forward error says something about the adapter's health. Transport errors and
+/// server-side gRPC codes count; caller errors such as `INVALID_ARGUMENT` do not.
+pub(crate) fn is_adapter_failure(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<Status>() {
+        Some(status) => matches!(
+            status.code(),
+            Code::Unavailable
+                | Code::DeadlineExceeded
+                | Code::Internal
+                | Code::Unknown
+                | Code::ResourceExhausted
+                | Code::Aborted
+        ),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> BreakerConfig {
+        BreakerConfig { consecutive_failures: 3, error_rate: 0.5, window: 10, cooldown_ms: 1000 }
+    }
+
+    #[test]
+    fn trips_on_streak_and_recovers_through_probe() {
+        let cfg = cfg();
+        let mut b = Breaker::default();
+        let t0 = Instant::now();
+        assert_eq!(b.record(&cfg, true, t0), None);
+        assert_eq!(b.record(&cfg, true, t0), None);
+        assert_eq!(b.record(&cfg, true, t0), Some(BreakerState::Open));
+        assert!(b.try_acquire(&cfg, t0 + Duration::from_millis(500)).is_err());
+
+        let later = t0 + Duration::from_secs(1);
+        assert_eq!(b.try_acquire(&cfg, later), Ok(Some(BreakerState::HalfOpen)));
+        // Only one probe at a time.
+        assert!(!b.available(&cfg, later));
+        assert_eq!(b.record(&cfg, false, later), Some(BreakerState::Closed));
+        assert!(b.available(&cfg, later));
+    }
+
+    #[test]
+    fn trips_on_error_rate_once_window_is_full() {
+        let cfg = BreakerConfig { consecutive_failures: 0, ..cfg() };
+        let mut b = Breaker::default();
+        let t0 = Instant::now();
+        for i in 0..9 {
+            assert_eq!(b.record(&cfg, i % 2 == 0, t0), None);
+        }
+        assert_eq!(b.record(&cfg, true, t0), Some(BreakerState::Open));
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let cfg = cfg();
+        let mut b = Breaker::default();
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            b.record(&cfg, true, t0);
+        }
+        let later = t0 + Duration::from_secs(1);
+        b.try_acquire(&cfg, later).unwrap();
+        assert_eq!(b.record(&cfg, true, later), Some(BreakerState::Open));
+        assert!(!b.available(&cfg, later));
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert!(is_adapter_failure(&Status::unavailable("down").into()));
+        assert!(!is_adapter_failure(&Status::invalid_argument("bad").into()));
+        assert!(is_adapter_failure(&anyhow::anyhow!("connection refused")));
+    }
+}
@@ -0,0 +1,92 @@
+//! Adapters hosted inside the core process, selected with `kind: in_process` and an `endpoint`
+//! naming one of [`NAMES`].
+
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+
+use crate::proto::adapter_service_server::AdapterService;
+use crate::proto::content_part::Part;
+use crate::proto::{
+    CanonicalAiChunk, CanonicalAiRequest, CanonicalAiResponse, Choice, ContentPart, FinishReason, Message, MessageRole,
+    TextPart,
+};
+
+use super::transport::in_process_channel;
+
+pub const NAMES: &[&str] = &["echo"];
+
+pub(crate) fn lookup(name: &str) -> Option<Channel> {
+    match name {
+        "echo" => Some(in_process_channel(EchoAdapter)),
+        _ => None,
+    }
+}
+
+/// Replies with the text of the last user message. Handy for smoke tests and local development
+/// without any sidecar running; supports streaming (one chunk per word).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EchoAdapter;
+
+fn last_user_text(req: &CanonicalAiRequest) -> String {
+    req.messages
+        .iter()
+        .rev()
+        .find(|m| m.role == MessageRole::User as i32)
+        .map(|m| {
+            m.content
+                .iter()
+                .filter_map(|p| match &p.part {
+                    Some(Part::Text(t)) => Some(t.text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("")
+        })
+        .unwrap_or_default()
+}
+
+#[tonic::async_trait]
+impl AdapterService for EchoAdapter {
+    async fn process(&self, request: Request<CanonicalAiRequest>) -> Result<Response<CanonicalAiResponse>, Status> {
+        let req = request.into_inner();
+        let text = last_user_text(&req);
+        Ok(Response::new(CanonicalAiResponse {
+            request_id: req.request_id,
+            adapter_id: "echo".to_string(),
+            model: "echo".to_string(),
+            choices: vec![Choice {
+                index: 0,
+                message: Some(Message {
+                    role: MessageRole::Assistant as i32,
+                    content: vec![ContentPart { part: Some(Part::Text(TextPart { text })) }],
+                    ..Default::default()
+                }),
+                finish_reason: FinishReason::Stop as i32,
+                stop_sequence: String::new(),
+            }],
+            ..Default::default()
+        }))
+    }
+
+    type ProcessStreamStream = tokio_stream::Iter<std::vec::IntoIter<Result<CanonicalAiChunk, Status>>>;
+
+    async fn process_stream(
+        &self,
+        request: Request<CanonicalAiRequest>,
+    ) -> Result<Response<Self::ProcessStreamStream>, Status> {
+        let req = request.into_inner();
+        let text = last_user_text(&req);
+        let chunk = |delta: String, done: bool| CanonicalAiChunk {
+            request_id: req.request_id.clone(),
+            adapter_id: "echo".to_string(),
+            delta,
+            done,
+            finish_reason: if done { "stop".to_string() } else { String::new() },
+            json: String::new(),
+            usage: None,
+        };
+        let mut chunks: Vec<_> = text.split_inclusive(' ').map(|w| chunk(w.to_string(), false)).collect();
+        chunks.push(chunk(String::new(), true));
+        Ok(Response::new(tokio_stream::iter(chunks.into_iter().map(Ok).collect::<Vec<_>>())))
+    }
+}
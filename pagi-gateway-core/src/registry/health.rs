@@ -0,0 +1,82 @@
+//! Adapter leases.
+//!
+//! Adapters renew their lease via `AdapterRegistry.Heartbeat`. A periodic sweep marks adapters
+//! that missed `lease_ttl_secs` as unhealthy (routing skips them) and evicts them once they have
+//! been silent for `evict_after_secs`.
+
+use std::time::{Duration, Instant};
+
+use crate::config::HealthConfig;
+use crate::proto::AdapterHealth as ProtoAdapterHealth;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    Unhealthy,
+}
+
+impl Health {
+    pub fn to_proto(self) -> ProtoAdapterHealth {
+        match self {
+            Health::Healthy => ProtoAdapterHealth::Healthy,
+            Health::Unhealthy => ProtoAdapterHealth::Unhealthy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Healthy,
+    Unhealthy,
+    Evict,
+}
+
+impl HealthConfig {
+    pub fn enabled(&self) -> bool {
+        self.lease_ttl_secs > 0
+    }
+
+    pub fn lease_ttl(&self) -> Duration {
+        Duration::from_secs(self.lease_ttl_secs)
+    }
+
+    /// How often the sweeper runs: twice per lease so a missed lease is noticed promptly.
+    pub(crate) fn sweep_interval(&self) -> Duration {
+        (self.lease_ttl() / 2).max(Duration::from_millis(100))
+    }
+
+    pub(crate) fn verdict(&self, last_seen: Instant, now: Instant) -> Verdict {
+        if !self.enabled() {
+            return Verdict::Healthy;
+        }
+        let silent = now.saturating_duration_since(last_seen);
+        if silent >= Duration::from_secs(self.evict_after_secs.max(self.lease_ttl_secs)) {
+            Verdict::Evict
+        } else if silent >= self.lease_ttl() {
+            Verdict::Unhealthy
+        } else {
+            Verdict::Healthy
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdict_follows_lease() {
+        let cfg = HealthConfig { lease_ttl_secs: 10, evict_after_secs: 30 };
+        let t0 = Instant::now();
+        assert_eq!(cfg.verdict(t0, t0 + Duration::from_secs(5)), Verdict::Healthy);
+        assert_eq!(cfg.verdict(t0, t0 + Duration::from_secs(10)), Verdict::Unhealthy);
+        assert_eq!(cfg.verdict(t0, t0 + Duration::from_secs(30)), Verdict::Evict);
+    }
+
+    #[test]
+    fn disabled_is_always_healthy() {
+        let cfg = HealthConfig::default();
+        let t0 = Instant::now();
+        assert_eq!(cfg.verdict(t0, t0 + Duration::from_secs(3600)), Verdict::Healthy);
+    }
+}
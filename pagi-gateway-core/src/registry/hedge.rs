@@ -0,0 +1,86 @@
+//! Hedged requests.
+//!
+//! With hedging on, a unary forward that has not answered within the primary adapter's
+//! `percentile` latency is sent again to the next candidate; the first success wins and the
+//! other call is cancelled. Latencies come from each adapter's recent successful calls.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::HedgingConfig;
+
+/// Successful call latencies kept per adapter.
+const WINDOW: usize = 256;
+
+/// Below this many samples the configured `delay_ms` is used instead of a percentile.
+const MIN_SAMPLES: usize = 16;
+
+#[derive(Debug, Default)]
+pub(crate) struct LatencyWindow {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyWindow {
+    pub(crate) fn record(&mut self, latency: Duration) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// Latency at quantile `q` (0..=1), if enough samples have been seen.
+    pub(crate) fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let idx = ((sorted.len() - 1) as f64 * q.clamp(0.0, 1.0)).round() as usize;
+        Some(sorted[idx])
+    }
+}
+
+impl HedgingConfig {
+    /// Whether to hedge `req`. Only operators turn hedging on (`enabled`), since it can double
+    /// upstream spend; a request can opt out with `metadata.hedge: "false"`.
+    pub(crate) fn applies_to(&self, req: &CanonicalAIRequest) -> bool {
+        self.enabled && req.metadata.get("hedge").map(String::as_str) != Some("false")
+    }
+
+    /// How long to wait on the primary before hedging, given its latency history.
+    pub(crate) fn delay(&self, window: &LatencyWindow) -> Duration {
+        window
+            .quantile(self.percentile)
+            .unwrap_or(Duration::from_millis(self.delay_ms))
+            .max(Duration::from_millis(self.min_delay_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_uses_percentile_once_warm() {
+        let cfg = HedgingConfig { enabled: true, percentile: 0.9, delay_ms: 100, min_delay_ms: 5 };
+        let mut window = LatencyWindow::default();
+        assert_eq!(cfg.delay(&window), Duration::from_millis(100));
+        for ms in 1..=100 {
+            window.record(Duration::from_millis(ms));
+        }
+        assert_eq!(cfg.delay(&window), Duration::from_millis(90));
+    }
+
+    #[test]
+    fn metadata_can_only_opt_out() {
+        let mut req = CanonicalAIRequest::new();
+        req.metadata.insert("hedge".to_string(), "true".to_string());
+        assert!(!HedgingConfig::default().applies_to(&req));
+
+        let cfg = HedgingConfig { enabled: true, ..Default::default() };
+        assert!(cfg.applies_to(&req));
+        req.metadata.insert("hedge".to_string(), "false".to_string());
+        assert!(!cfg.applies_to(&req));
+    }
+}
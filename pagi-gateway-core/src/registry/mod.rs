@@ -0,0 +1,1701 @@
+pub mod auth;
+pub mod breaker;
+pub mod builtin;
+pub mod health;
+pub mod hedge;
+pub mod queue;
+pub mod replay;
+pub mod retry;
+pub mod routing;
+pub mod transport;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{mpsc, RwLock};
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::canonical::{
+    CanonicalAIRequest, CanonicalAIResponse, Choice, ContentPart, FinishReason, Message, MessageRole, ToolCall, Usage,
+};
+use crate::config::{AdapterConfig, BreakerConfig, ProvidersConfig, RegistryConfig, RequestReplayConfig};
+use crate::middleware::observability::Metrics;
+use auth::{AuthFailure, RegistrationAuth};
+use breaker::{is_adapter_failure, Breaker, BreakerState};
+use health::{Health, Verdict};
+use hedge::LatencyWindow;
+use queue::{Admission, Gate, Permit};
+pub use queue::{Priority, QueueError};
+pub use replay::{ReplayEntry, ReplayFilter};
+use retry::RetryBudget;
+use routing::{AdapterSnapshot, Router};
+use transport::TransportKind;
+use crate::proto::{
+    adapter_registry_server::{AdapterRegistry, AdapterRegistryServer},
+    adapter_service_client::AdapterServiceClient,
+    adapter_service_server::AdapterService,
+    AdapterOrigin as ProtoAdapterOrigin,
+    AdapterInfo, CanonicalAiChunk, CanonicalAiRequest, CanonicalAiResponse, ContentPart as ProtoContentPart,
+    FilePart as ProtoFilePart, GenerationConstraints as ProtoGenerationConstraints, ImagePart as ProtoImagePart,
+    Message as ProtoMessage, Tool as ProtoTool, ToolCall as ProtoToolCall, TextPart as ProtoTextPart, AudioPart as ProtoAudioPart,
+    DeregisterAdapterRequest, DeregisterAdapterResponse, DrainAdapterRequest, DrainAdapterResponse,
+    FinishReason as ProtoFinishReason, MessageRole as ProtoMessageRole, HeartbeatRequest, HeartbeatResponse, ListAdaptersRequest, ListAdaptersResponse, RegisterAdapterRequest, RegisterAdapterResponse,
+};
+
+#[derive(Clone)]
+pub struct AdapterRegistryState {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    adapters: RwLock<BTreeMap<String, AdapterEntry>>,
+    replay: RequestReplayConfig,
+    config: RegistryConfig,
+    providers: ProvidersConfig,
+    router: Router,
+    retry_budget: RetryBudget,
+    auth: RegistrationAuth,
+    metrics: Metrics,
+}
+
+/// A registered adapter plus its shared gRPC channel.
+///
+/// The channel is created lazily (first RPC dials) and multiplexes all forwards over one
+/// HTTP/2 connection; it is rebuilt when the adapter re-registers with a different endpoint.
+#[derive(Clone)]
+struct AdapterEntry {
+    info: AdapterInfo,
+    channel: Channel,
+    health: Health,
+    last_seen: Instant,
+    draining: bool,
+    stats: Arc<AdapterStats>,
+    origin: Origin,
+}
+
+/// Live per-adapter state shared by every clone of an entry. It survives re-registration at
+/// the same endpoint; a new endpoint starts fresh.
+#[derive(Debug, Default)]
+struct AdapterStats {
+    in_flight: AtomicUsize,
+    breaker: Mutex<Breaker>,
+    latency: Mutex<LatencyWindow>,
+    gate: Arc<Gate>,
+    attempts: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// Point-in-time view of one adapter's live counters, for operators.
+#[derive(Debug, Clone)]
+pub struct AdapterStatsSnapshot {
+    pub adapter_id: String,
+    pub in_flight: usize,
+    pub queued: usize,
+    /// Calls made since the adapter registered at its current endpoint, and how many failed.
+    pub attempts: u64,
+    pub failures: u64,
+    pub breaker: BreakerState,
+    /// Over recent successful calls; `None` until enough have been seen.
+    pub latency_p50: Option<Duration>,
+    pub latency_p95: Option<Duration>,
+}
+
+/// Where an adapter entry came from. Static entries are owned by the config and are exempt
+/// from leases; dynamic registrations cannot replace or remove them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Static,
+    Dynamic,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("invalid endpoint: {0}")]
+    InvalidEndpoint(#[from] anyhow::Error),
+    #[error("adapter {0:?} is declared in config and cannot be changed at runtime")]
+    Static(String),
+    #[error("adapter {0:?} is not registered")]
+    NotFound(String),
+}
+
+impl AdapterEntry {
+    /// Whether routing may send new requests here.
+    fn routable(&self) -> bool {
+        self.health == Health::Healthy && !self.draining
+    }
+
+    /// Routable, and not held back by an open circuit breaker.
+    fn available(&self, breaker: &BreakerConfig, now: Instant) -> bool {
+        self.routable() && (!breaker.enabled() || self.stats.breaker.lock().unwrap().available(breaker, now))
+    }
+
+    /// Count a call, holding the concurrency slot it was admitted with (if any) alongside.
+    fn begin(&self, permit: Option<Permit>) -> InFlight {
+        self.stats.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight { stats: self.stats.clone(), _permit: permit }
+    }
+}
+
+/// Counts a forward against its adapter, and keeps its concurrency slot, until dropped.
+struct InFlight {
+    stats: Arc<AdapterStats>,
+    _permit: Option<Permit>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.stats.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A breaker's permission for one attempt. When the attempt holds the half-open probe slot,
+/// dropping it without recording an outcome (not admitted, hedged out, or cancelled by the
+/// client) gives the slot back so the adapter can be probed again.
+struct Probe {
+    slot: Option<Arc<AdapterStats>>,
+}
+
+impl Probe {
+    /// The outcome was recorded, which settles the probe.
+    fn settle(mut self) {
+        self.slot = None;
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        if let Some(stats) = self.slot.take() {
+            stats.breaker.lock().unwrap().release_probe();
+        }
+    }
+}
+
+/// An established adapter stream. Chunks arrive in order; the last one has `done = true`.
+#[derive(Debug)]
+pub struct ForwardStream {
+    pub request_id: String,
+    pub adapter_id: String,
+    pub chunks: mpsc::Receiver<anyhow::Result<CanonicalAiChunk>>,
+}
+
+const STREAM_BUFFER: usize = 32;
+
+/// Per-call knobs set by the ingress protocol.
+#[derive(Debug, Clone, Default)]
+pub struct ForwardOptions {
+    /// Client-requested total deadline (`x-pagi-timeout-ms`); clamped to `max_timeout_ms`.
+    pub timeout: Option<Duration>,
+    /// Only route to these adapters (an API key's adapter scope).
+    pub adapters: Option<Vec<String>>,
+}
+
+impl ForwardOptions {
+    fn restrict(&self, candidates: &mut Vec<(String, AdapterEntry)>) {
+        if let Some(allowed) = &self.adapters {
+            candidates.retain(|(id, _)| allowed.contains(id));
+        }
+    }
+}
+
+/// The request deadline passed before any adapter answered.
+#[derive(Debug, thiserror::Error)]
+#[error("deadline of {0:?} exceeded")]
+pub struct DeadlineExceeded(pub Duration);
+
+/// One call to one adapter, as handed to the closure in [`AdapterRegistryState::run`].
+struct Attempt {
+    adapter_id: String,
+    entry: AdapterEntry,
+    /// Started once the adapter had a free slot; the call must hold it until it is done.
+    in_flight: InFlight,
+    /// Per-attempt timeout, to send as the gRPC deadline of a unary call.
+    timeout: Duration,
+    /// What is left of the request deadline.
+    remaining: Duration,
+}
+
+impl AdapterRegistryState {
+    pub fn new(
+        replay: RequestReplayConfig,
+        config: RegistryConfig,
+        providers: ProvidersConfig,
+        metrics: Metrics,
+    ) -> Self {
+        let router = Router::new(&config.routing, &providers);
+        let retry_budget = RetryBudget::new(&config.forwarding);
+        let auth = RegistrationAuth::new(&config.auth);
+        Self {
+            inner: Arc::new(Inner {
+                adapters: RwLock::new(BTreeMap::new()),
+                replay,
+                config,
+                providers,
+                router,
+                retry_budget,
+                auth,
+                metrics,
+            }),
+        }
+    }
+
+    pub async fn forward(&self, req: CanonicalAIRequest) -> anyhow::Result<CanonicalAIResponse> {
+        self.forward_with(req, ForwardOptions::default()).await
+    }
+
+    pub async fn forward_with(&self, req: CanonicalAIRequest, opts: ForwardOptions) -> anyhow::Result<CanonicalAIResponse> {
+        self.maybe_replay(&req).await;
+
+        let mut candidates = self.candidates(&req).await;
+        opts.restrict(&mut candidates);
+        let hedge = self.inner.config.hedging.applies_to(&req);
+        let priority = Priority::of(&req);
+        let proto_req: CanonicalAiRequest = to_proto(req);
+        let proto_req = &proto_req;
+
+        self.run(candidates, &opts, priority, hedge, |attempt| {
+            let mut request = Request::new(self.request_for(&attempt.adapter_id, proto_req));
+            request.set_timeout(attempt.timeout);
+            async move {
+                let _in_flight = attempt.in_flight;
+                let mut client = AdapterServiceClient::new(attempt.entry.channel.clone());
+                let resp: CanonicalAiResponse = client.process(request).await?.into_inner();
+                let mut resp = from_proto_response(resp);
+                resp.adapter_id = attempt.adapter_id;
+                Ok(resp)
+            }
+        })
+        .await
+    }
+
+    pub async fn forward_stream(&self, req: CanonicalAIRequest) -> anyhow::Result<ForwardStream> {
+        self.forward_stream_with(req, ForwardOptions::default()).await
+    }
+
+    /// Streaming variant of [`Self::forward_with`].
+    ///
+    /// Adapters advertising `capabilities.streaming` are tried first via `ProcessStream`; others
+    /// fall back to unary `Process` and yield a single final chunk carrying the whole reply, its
+    /// finish reason and usage. Failover and retries only happen until the stream is established;
+    /// mid-stream errors are delivered through the channel. The whole stream is bounded by the
+    /// request deadline.
+    pub async fn forward_stream_with(
+        &self,
+        req: CanonicalAIRequest,
+        opts: ForwardOptions,
+    ) -> anyhow::Result<ForwardStream> {
+        self.maybe_replay(&req).await;
+
+        let mut candidates = self.candidates(&req).await;
+        opts.restrict(&mut candidates);
+        // Stable sort keeps routing order within each group.
+        candidates.sort_by_key(|(_, a)| !a.info.capabilities.as_ref().map(|c| c.streaming).unwrap_or(false));
+
+        let request_id = req.request_id.to_string();
+        let priority = Priority::of(&req);
+        let proto_req: CanonicalAiRequest = to_proto(req);
+        let proto_req = &proto_req;
+
+        self.run(candidates, &opts, priority, false, |attempt| {
+            let streaming = attempt.entry.info.capabilities.as_ref().map(|c| c.streaming).unwrap_or(false);
+            let mut request = Request::new(self.request_for(&attempt.adapter_id, proto_req));
+            let request_id = request_id.clone();
+            async move {
+                let in_flight = attempt.in_flight;
+                let mut client = AdapterServiceClient::new(attempt.entry.channel.clone());
+                let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+                if streaming {
+                    request.set_timeout(attempt.remaining);
+                    let mut stream = client.process_stream(request).await?.into_inner();
+                    tokio::spawn(async move {
+                        // Held until the stream ends so draining waits for it and the slot stays taken.
+                        let _in_flight = in_flight;
+                        loop {
+                            // Stop as soon as the caller goes away, even while the adapter is quiet.
+                            let message = tokio::select! {
+                                m = stream.message() => m,
+                                _ = tx.closed() => break,
+                            };
+                            match message {
+                                Ok(Some(chunk)) => {
+                                    let done = chunk.done;
+                                    if tx.send(Ok(chunk)).await.is_err() || done {
+                                        break;
+                                    }
+                                }
+                                Ok(None) => break,
+                                Err(e) => {
+                                    let _ = tx.send(Err(e.into())).await;
+                                    break;
+                                }
+                            }
+                        }
+                    });
+                } else {
+                    request.set_timeout(attempt.timeout);
+                    let resp = from_proto_response(client.process(request).await?.into_inner());
+                    let finish_reason = resp.choices.first().and_then(|c| c.finish_reason).unwrap_or(FinishReason::Stop);
+                    let _ = tx
+                        .send(Ok(CanonicalAiChunk {
+                            delta: resp.text(),
+                            done: true,
+                            finish_reason: finish_reason.as_str().to_string(),
+                            usage: resp.usage.map(to_proto_usage),
+                            request_id: resp.request_id,
+                            adapter_id: resp.adapter_id,
+                            json: resp.json,
+                        }))
+                        .await;
+                }
+                Ok(ForwardStream { request_id, adapter_id: attempt.adapter_id, chunks: rx })
+            }
+        })
+        .await
+    }
+
+    /// Try `candidates` in order with `call` until one succeeds.
+    ///
+    /// A pass over the list is failover and is free. If the last failure was retryable, the
+    /// whole list is tried again after a jittered backoff, up to `max_retries` times, each
+    /// retry paid for from the retry budget. Every attempt is bounded by the per-attempt
+    /// timeout and nothing runs past the request deadline. With `hedge`, a slow first-pass
+    /// attempt is raced against the next candidate (see [`hedge`]). Adapters at their
+    /// concurrency limit queue the attempt at `priority` (see [`queue`]).
+    async fn run<T, F, Fut>(
+        &self,
+        candidates: Vec<(String, AdapterEntry)>,
+        opts: &ForwardOptions,
+        priority: Priority,
+        hedge: bool,
+        call: F,
+    ) -> anyhow::Result<T>
+    where
+        F: Fn(Attempt) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let cfg = &self.inner.config.forwarding;
+        let timeout = cfg.deadline(opts.timeout);
+        let deadline = Instant::now() + timeout;
+        self.inner.retry_budget.deposit();
+
+        let mut last_err: Option<anyhow::Error> = None;
+        for round in 0..=cfg.max_retries {
+            if round > 0 {
+                if !last_err.as_ref().is_some_and(retry::is_retryable) {
+                    break;
+                }
+                if !self.inner.retry_budget.try_withdraw(Instant::now()) {
+                    self.inner.metrics.inc_retries("budget_exhausted");
+                    break;
+                }
+                self.inner.metrics.inc_retries("attempted");
+                let pause = cfg.backoff(round).min(deadline.saturating_duration_since(Instant::now()));
+                tokio::time::sleep(pause).await;
+            }
+
+            let mut next = 0;
+            while let Some((adapter_id, entry, probe)) = self.next_acquired(&candidates, &mut next) {
+                if deadline <= Instant::now() {
+                    return Err(DeadlineExceeded(timeout).into());
+                }
+                let mut primary = Box::pin(self.attempt(adapter_id, entry, probe, deadline, priority, &call));
+                let hedge_delay = (hedge && round == 0)
+                    .then(|| self.inner.config.hedging.delay(&entry.stats.latency.lock().unwrap()));
+
+                let result = match hedge_delay {
+                    None => primary.await,
+                    Some(delay) => {
+                        tokio::select! {
+                            r = &mut primary => r,
+                            _ = tokio::time::sleep(delay) => match self.next_acquired(&candidates, &mut next) {
+                                None => primary.await,
+                                Some((hedge_id, hedge_entry, hedge_probe)) => {
+                                    self.inner.metrics.inc_hedges(adapter_id, hedge_id);
+                                    let hedged =
+                                        Box::pin(self.attempt(hedge_id, hedge_entry, hedge_probe, deadline, priority, &call));
+                                    let (result, hedge_won) = race(primary, hedged).await;
+                                    if hedge_won {
+                                        self.inner.metrics.inc_hedge_wins(adapter_id, hedge_id);
+                                    }
+                                    result
+                                }
+                            },
+                        }
+                    }
+                };
+                match result {
+                    Ok(v) => return Ok(v),
+                    Err(e) => last_err = Some(e),
+                }
+            }
+        }
+
+        if deadline <= Instant::now() {
+            return Err(DeadlineExceeded(timeout).into());
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no adapters registered")))
+    }
+
+    /// Advance `next` to the first candidate whose breaker admits a call.
+    fn next_acquired<'a>(
+        &self,
+        candidates: &'a [(String, AdapterEntry)],
+        next: &mut usize,
+    ) -> Option<(&'a str, &'a AdapterEntry, Probe)> {
+        while let Some((adapter_id, entry)) = candidates.get(*next) {
+            *next += 1;
+            if let Some(probe) = self.acquire(adapter_id, entry) {
+                return Some((adapter_id, entry, probe));
+            }
+        }
+        None
+    }
+
+    /// One call to one adapter under the per-attempt timeout, once the adapter has a free
+    /// concurrency slot. Outcome and latency are recorded when it completes. A cancelled
+    /// (hedged-out) attempt, or one that never got a slot, records no outcome; dropping its
+    /// `probe` frees the breaker's half-open slot.
+    async fn attempt<T, F, Fut>(
+        &self,
+        adapter_id: &str,
+        entry: &AdapterEntry,
+        probe: Probe,
+        deadline: Instant,
+        priority: Priority,
+        call: &F,
+    ) -> anyhow::Result<T>
+    where
+        F: Fn(Attempt) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let permit = match self.admit(adapter_id, entry, priority, deadline).await {
+            Ok(permit) => permit,
+            Err(e) => {
+                warn!(%adapter_id, error=%e, "adapter attempt not admitted");
+                return Err(e.into());
+            }
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = self.inner.config.forwarding.attempt_timeout(remaining);
+        let in_flight = entry.begin(Some(permit));
+        let fut = call(Attempt { adapter_id: adapter_id.to_string(), entry: entry.clone(), in_flight, timeout, remaining });
+        self.inner.metrics.inc_adapter_attempts(adapter_id);
+
+        let started = Instant::now();
+        let result = match tokio::time::timeout(timeout, fut).await {
+            Ok(r) => r,
+            Err(_) => Err(Status::deadline_exceeded("adapter attempt timed out").into()),
+        };
+        entry.stats.attempts.fetch_add(1, Ordering::Relaxed);
+        if result.is_ok() {
+            entry.stats.latency.lock().unwrap().record(started.elapsed());
+        } else {
+            entry.stats.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        self.record_outcome(adapter_id, entry, result.as_ref().err());
+        probe.settle();
+        if let Err(e) = &result {
+            warn!(%adapter_id, error=%e, "adapter attempt failed");
+        }
+        result
+    }
+
+    /// Wait for a slot under `adapter_id`'s concurrency limit, for no longer than the queue
+    /// timeout or what is left of the request deadline.
+    async fn admit(
+        &self,
+        adapter_id: &str,
+        entry: &AdapterEntry,
+        priority: Priority,
+        deadline: Instant,
+    ) -> Result<Permit, QueueError> {
+        let cfg = &self.inner.config.concurrency;
+        let metrics = &self.inner.metrics;
+        let gate = &entry.stats.gate;
+        let waiter = match gate.enter(cfg.limit_for(adapter_id), cfg.queue_depth, priority) {
+            Ok(Admission::Ready(permit)) => return Ok(permit),
+            Ok(Admission::Queued(waiter)) => waiter,
+            Err(e) => {
+                metrics.inc_adapter_queue_rejected(adapter_id, e.label());
+                return Err(e);
+            }
+        };
+        metrics.set_adapter_queue_depth(adapter_id, gate.queued());
+        let started = Instant::now();
+        let result = waiter.wait(cfg.queue_timeout().min(deadline.saturating_duration_since(started))).await;
+        metrics.set_adapter_queue_depth(adapter_id, gate.queued());
+        metrics.observe_adapter_queue_wait(adapter_id, priority.as_str(), started.elapsed().as_secs_f64());
+        if let Err(e) = &result {
+            metrics.inc_adapter_queue_rejected(adapter_id, e.label());
+        }
+        result
+    }
+
+    /// Ordered list of adapters to try for `req`. Unhealthy, draining and breaker-open adapters
+    /// are never candidates.
+    ///
+    /// A `metadata.adapter_id` pin selects exactly that adapter. Otherwise the routing policy
+    /// orders every routable adapter that is not a disabled provider.
+    async fn candidates(&self, req: &CanonicalAIRequest) -> Vec<(String, AdapterEntry)> {
+        let adapters = self.inner.adapters.read().await;
+        let now = Instant::now();
+        let available = |a: &AdapterEntry| a.available(&self.inner.config.breaker, now);
+
+        if let Some(id) = req.metadata.get("adapter_id") {
+            return adapters.get(id).filter(|a| available(a)).map(|a| (id.clone(), a.clone())).into_iter().collect();
+        }
+
+        let providers = &self.inner.providers;
+        let enabled = |id: &str| providers.get(id).map(|p| p.enabled).unwrap_or(true);
+        let snapshot: Vec<AdapterSnapshot> = adapters
+            .iter()
+            .filter(|(id, a)| available(a) && enabled(id))
+            .map(|(id, a)| AdapterSnapshot {
+                adapter_id: id.clone(),
+                capabilities: a.info.capabilities.clone().unwrap_or_default(),
+                in_flight: a.stats.in_flight.load(Ordering::SeqCst),
+            })
+            .collect();
+
+        self.inner
+            .router
+            .policy_for(req)
+            .route(req, &snapshot)
+            .into_iter()
+            .filter_map(|id| adapters.get(&id).map(|a| (id, a.clone())))
+            .collect()
+    }
+
+    /// Ask `entry`'s breaker to let one call through; this is where an open breaker past its
+    /// cooldown turns half-open.
+    fn acquire(&self, adapter_id: &str, entry: &AdapterEntry) -> Option<Probe> {
+        let cfg = &self.inner.config.breaker;
+        if !cfg.enabled() {
+            return Some(Probe { slot: None });
+        }
+        let mut breaker = entry.stats.breaker.lock().unwrap();
+        let changed = breaker.try_acquire(cfg, Instant::now()).ok()?;
+        let probing = breaker.state() == BreakerState::HalfOpen;
+        drop(breaker);
+        if let Some(state) = changed {
+            self.breaker_changed(adapter_id, state);
+        }
+        Some(Probe { slot: probing.then(|| entry.stats.clone()) })
+    }
+
+    fn record_outcome(&self, adapter_id: &str, entry: &AdapterEntry, err: Option<&anyhow::Error>) {
+        let cfg = &self.inner.config.breaker;
+        if !cfg.enabled() {
+            return;
+        }
+        let failed = err.is_some_and(is_adapter_failure);
+        let changed = entry.stats.breaker.lock().unwrap().record(cfg, failed, Instant::now());
+        if let Some(state) = changed {
+            self.breaker_changed(adapter_id, state);
+        }
+    }
+
+    fn breaker_changed(&self, adapter_id: &str, state: BreakerState) {
+        match state {
+            BreakerState::Open => warn!(%adapter_id, "circuit breaker open"),
+            BreakerState::HalfOpen => info!(%adapter_id, "circuit breaker half-open, probing"),
+            BreakerState::Closed => info!(%adapter_id, "circuit breaker closed"),
+        }
+        self.inner.metrics.set_adapter_breaker(adapter_id, state);
+    }
+
+    /// The request as sent to `adapter_id`: if that provider maps the preferred model, it is
+    /// rewritten and the original kept in `metadata.requested_model`.
+    fn request_for(&self, adapter_id: &str, req: &CanonicalAiRequest) -> CanonicalAiRequest {
+        let mut req = req.clone();
+        let mapped = self.inner.providers.get(adapter_id).and_then(|p| p.models_mapping.get(&req.preferred_model));
+        if let Some(model) = mapped {
+            let requested = std::mem::replace(&mut req.preferred_model, model.clone());
+            req.metadata.insert("requested_model".to_string(), requested);
+        }
+        req
+    }
+
+    /// Insert or replace a self-registered adapter.
+    ///
+    /// Re-registering with the same endpoint keeps the existing channel (and its connection);
+    /// a new endpoint gets a fresh channel and the old one is dropped. An adapter that is also
+    /// declared in config may register at its configured endpoint, but not elsewhere; that keeps
+    /// the configured entry and only clears a drain left over from the adapter's previous run.
+    pub async fn register(&self, info: AdapterInfo) -> Result<(), RegistryError> {
+        let mut adapters = self.inner.adapters.write().await;
+        if let Some(existing) = adapters.get_mut(&info.adapter_id).filter(|e| e.origin == Origin::Static) {
+            if existing.info.endpoint != info.endpoint {
+                return Err(RegistryError::Static(info.adapter_id));
+            }
+            existing.draining = false;
+            existing.health = Health::Healthy;
+            existing.last_seen = Instant::now();
+            self.inner.metrics.set_adapter_healthy(&info.adapter_id, true);
+            return Ok(());
+        }
+        let reuse = match adapters.get(&info.adapter_id) {
+            Some(existing) if existing.info.endpoint == info.endpoint => {
+                Some((existing.channel.clone(), existing.stats.clone()))
+            }
+            _ => None,
+        };
+        // A flapping adapter that re-registers keeps its breaker; a new endpoint starts fresh.
+        let (channel, stats) = match reuse {
+            Some(v) => v,
+            None => (transport::endpoint_channel(&info.endpoint)?, Arc::default()),
+        };
+        self.insert(&mut adapters, info, channel, stats, Origin::Dynamic);
+        Ok(())
+    }
+
+    /// Pre-seed an adapter declared in `config.adapters`; `kind` selects the transport.
+    pub async fn register_static(&self, cfg: &AdapterConfig) -> anyhow::Result<()> {
+        let channel = match cfg.kind.parse::<TransportKind>()? {
+            TransportKind::Grpc => transport::endpoint_channel(&cfg.endpoint)?,
+            TransportKind::Unix => transport::unix_channel(transport::unix_path(&cfg.endpoint).unwrap_or(&cfg.endpoint))?,
+            TransportKind::InProcess => builtin::lookup(&cfg.endpoint).ok_or_else(|| {
+                anyhow::anyhow!("unknown in-process adapter {:?} (available: {:?})", cfg.endpoint, builtin::NAMES)
+            })?,
+        };
+        let info = AdapterInfo {
+            adapter_id: cfg.id.clone(),
+            endpoint: cfg.endpoint.clone(),
+            capabilities: Some(cfg.capabilities.to_proto()),
+            ..Default::default()
+        };
+        let mut adapters = self.inner.adapters.write().await;
+        self.insert(&mut adapters, info, channel, Arc::default(), Origin::Static);
+        Ok(())
+    }
+
+    /// Host `svc` inside the core as a static adapter (for embedders of this crate).
+    pub async fn register_in_process<S: AdapterService>(&self, info: AdapterInfo, svc: S) {
+        let channel = transport::in_process_channel(svc);
+        let mut adapters = self.inner.adapters.write().await;
+        self.insert(&mut adapters, info, channel, Arc::default(), Origin::Static);
+    }
+
+    fn insert(
+        &self,
+        adapters: &mut BTreeMap<String, AdapterEntry>,
+        info: AdapterInfo,
+        channel: Channel,
+        stats: Arc<AdapterStats>,
+        origin: Origin,
+    ) {
+        self.inner.metrics.set_adapter_healthy(&info.adapter_id, true);
+        self.inner.metrics.set_adapter_breaker(&info.adapter_id, stats.breaker.lock().unwrap().state());
+        // Re-registering (e.g. a fresh deploy under the same id) clears any previous drain.
+        adapters.insert(
+            info.adapter_id.clone(),
+            AdapterEntry {
+                info,
+                channel,
+                health: Health::Healthy,
+                last_seen: Instant::now(),
+                draining: false,
+                stats,
+                origin,
+            },
+        );
+    }
+
+    /// Remove a self-registered adapter. Forwards already in flight keep their channel clone
+    /// and complete.
+    pub async fn deregister(&self, adapter_id: &str) -> Result<(), RegistryError> {
+        let mut adapters = self.inner.adapters.write().await;
+        match adapters.get(adapter_id) {
+            None => return Err(RegistryError::NotFound(adapter_id.to_string())),
+            Some(e) if e.origin == Origin::Static => return Err(RegistryError::Static(adapter_id.to_string())),
+            Some(_) => {}
+        }
+        adapters.remove(adapter_id);
+        self.inner.metrics.remove_adapter(adapter_id);
+        Ok(())
+    }
+
+    /// Stop routing new requests to an adapter. Returns its in-flight count, or `None` if unknown.
+    pub async fn drain(&self, adapter_id: &str) -> Option<usize> {
+        let mut adapters = self.inner.adapters.write().await;
+        let entry = adapters.get_mut(adapter_id)?;
+        if !entry.draining {
+            info!(%adapter_id, "adapter draining");
+            entry.draining = true;
+        }
+        Some(entry.stats.in_flight.load(Ordering::SeqCst))
+    }
+
+    /// Renew an adapter's lease. Returns `false` if the adapter is unknown (e.g. evicted).
+    pub async fn heartbeat(&self, adapter_id: &str) -> bool {
+        let mut adapters = self.inner.adapters.write().await;
+        let Some(entry) = adapters.get_mut(adapter_id) else {
+            return false;
+        };
+        entry.last_seen = Instant::now();
+        if entry.health != Health::Healthy {
+            info!(%adapter_id, "adapter healthy again");
+            entry.health = Health::Healthy;
+            self.inner.metrics.set_adapter_healthy(adapter_id, true);
+        }
+        true
+    }
+
+    pub async fn list(&self) -> Vec<AdapterInfo> {
+        self.inner
+            .adapters
+            .read()
+            .await
+            .values()
+            .map(|a| AdapterInfo {
+                health: a.health.to_proto() as i32,
+                draining: a.draining,
+                in_flight: a.stats.in_flight.load(Ordering::SeqCst) as u32,
+                breaker: a.stats.breaker.lock().unwrap().state().to_proto() as i32,
+                origin: match a.origin {
+                    Origin::Static => ProtoAdapterOrigin::Static,
+                    Origin::Dynamic => ProtoAdapterOrigin::Dynamic,
+                } as i32,
+                ..a.info.clone()
+            })
+            .collect()
+    }
+
+    pub async fn stats(&self) -> Vec<AdapterStatsSnapshot> {
+        self.inner
+            .adapters
+            .read()
+            .await
+            .iter()
+            .map(|(id, a)| {
+                let latency = a.stats.latency.lock().unwrap();
+                AdapterStatsSnapshot {
+                    adapter_id: id.clone(),
+                    in_flight: a.stats.in_flight.load(Ordering::SeqCst),
+                    queued: a.stats.gate.queued(),
+                    attempts: a.stats.attempts.load(Ordering::Relaxed),
+                    failures: a.stats.failures.load(Ordering::Relaxed),
+                    breaker: a.stats.breaker.lock().unwrap().state(),
+                    latency_p50: latency.quantile(0.5),
+                    latency_p95: latency.quantile(0.95),
+                }
+            })
+            .collect()
+    }
+
+    /// Recent requests from the replay log, newest first.
+    pub async fn replay_history(&self, filter: &ReplayFilter, limit: usize) -> std::io::Result<Vec<ReplayEntry>> {
+        replay::read(&self.inner.replay.path, filter, limit).await
+    }
+
+    /// Apply lease verdicts as of `now`: mark stale adapters unhealthy and evict dead ones.
+    pub async fn sweep(&self, now: Instant) {
+        let health = &self.inner.config.health;
+        let mut adapters = self.inner.adapters.write().await;
+        adapters.retain(|id, entry| match health.verdict(entry.last_seen, now) {
+            _ if entry.origin == Origin::Static => true,
+            Verdict::Healthy => true,
+            Verdict::Unhealthy => {
+                if entry.health == Health::Healthy {
+                    warn!(adapter_id=%id, "adapter missed its lease; marking unhealthy");
+                    entry.health = Health::Unhealthy;
+                    self.inner.metrics.set_adapter_healthy(id, false);
+                }
+                true
+            }
+            Verdict::Evict => {
+                warn!(adapter_id=%id, "evicting adapter");
+                self.inner.metrics.remove_adapter(id);
+                self.inner.metrics.inc_adapter_evictions(id);
+                false
+            }
+        });
+    }
+
+    /// Run [`Self::sweep`] periodically. No-op when leases are disabled.
+    pub fn spawn_health_sweeper(&self) {
+        let health = &self.inner.config.health;
+        if !health.enabled() {
+            return;
+        }
+        let state = self.clone();
+        let mut tick = tokio::time::interval(health.sweep_interval());
+        tokio::spawn(async move {
+            loop {
+                tick.tick().await;
+                state.sweep(Instant::now()).await;
+            }
+        });
+    }
+
+    fn lease_ttl_ms(&self) -> u32 {
+        self.inner.config.health.lease_ttl().as_millis().try_into().unwrap_or(u32::MAX)
+    }
+
+    async fn maybe_replay(&self, req: &CanonicalAIRequest) {
+        if !self.inner.replay.enabled {
+            return;
+        }
+        if let Err(e) = replay::append(&self.inner.replay.path, req).await {
+            warn!(error=%e, "writing replay log failed");
+        }
+    }
+}
+
+pub struct AdapterRegistrySvc {
+    state: AdapterRegistryState,
+}
+
+impl AdapterRegistrySvc {
+    pub fn new(state: AdapterRegistryState) -> AdapterRegistryServer<Self> {
+        AdapterRegistryServer::new(Self { state })
+    }
+
+    fn authenticate<T>(&self, rpc: &'static str, adapter_id: &str, request: &Request<T>) -> Result<(), AuthFailure> {
+        self.state.inner.auth.check(adapter_id, request).inspect_err(|failure| {
+            let peer = request.remote_addr().map(|a| a.to_string()).unwrap_or_else(|| "local".to_string());
+            warn!(event = "registration_rejected", rpc, %adapter_id, %peer, reason = failure.reason(), "adapter auth failed");
+            self.state.inner.metrics.inc_registration_rejected(rpc, failure.reason());
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl AdapterRegistry for AdapterRegistrySvc {
+    async fn register(
+        &self,
+        request: Request<RegisterAdapterRequest>,
+    ) -> Result<Response<RegisterAdapterResponse>, Status> {
+        self.authenticate("register", &request.get_ref().adapter_id, &request)?;
+        let r = request.into_inner();
+        if r.adapter_id.is_empty() || r.endpoint.is_empty() {
+            return Err(Status::invalid_argument("adapter_id and endpoint required"));
+        }
+        let caps = r.capabilities.unwrap_or_default();
+        let info = AdapterInfo {
+            adapter_id: r.adapter_id.clone(),
+            endpoint: r.endpoint.clone(),
+            capabilities: Some(caps),
+            version: r.version,
+            ..Default::default()
+        };
+        self.state.register(info).await.map_err(to_status)?;
+        info!(adapter_id=%r.adapter_id, endpoint=%r.endpoint, "adapter registered");
+        Ok(Response::new(RegisterAdapterResponse { ok: true, lease_ttl_ms: self.state.lease_ttl_ms() }))
+    }
+
+    async fn heartbeat(&self, request: Request<HeartbeatRequest>) -> Result<Response<HeartbeatResponse>, Status> {
+        self.authenticate("heartbeat", &request.get_ref().adapter_id, &request)?;
+        let r = request.into_inner();
+        if !self.state.heartbeat(&r.adapter_id).await {
+            return Err(Status::not_found("adapter not registered"));
+        }
+        Ok(Response::new(HeartbeatResponse { ok: true, lease_ttl_ms: self.state.lease_ttl_ms() }))
+    }
+
+    async fn deregister(
+        &self,
+        request: Request<DeregisterAdapterRequest>,
+    ) -> Result<Response<DeregisterAdapterResponse>, Status> {
+        self.authenticate("deregister", &request.get_ref().adapter_id, &request)?;
+        let r = request.into_inner();
+        self.state.deregister(&r.adapter_id).await.map_err(to_status)?;
+        info!(adapter_id=%r.adapter_id, "adapter deregistered");
+        Ok(Response::new(DeregisterAdapterResponse { ok: true }))
+    }
+
+    async fn drain(&self, request: Request<DrainAdapterRequest>) -> Result<Response<DrainAdapterResponse>, Status> {
+        self.authenticate("drain", &request.get_ref().adapter_id, &request)?;
+        let r = request.into_inner();
+        let Some(in_flight) = self.state.drain(&r.adapter_id).await else {
+            return Err(Status::not_found("adapter not registered"));
+        };
+        Ok(Response::new(DrainAdapterResponse { ok: true, in_flight: in_flight as u32 }))
+    }
+
+    async fn list(
+        &self,
+        request: Request<ListAdaptersRequest>,
+    ) -> Result<Response<ListAdaptersResponse>, Status> {
+        self.state.inner.auth.check_peer(&request)?;
+        let adapters = self.state.list().await;
+        Ok(Response::new(ListAdaptersResponse { adapters }))
+    }
+}
+
+/// First success of two in-flight attempts; the slower one is dropped, cancelling its RPC and
+/// giving back any half-open probe slot it held. If one fails the other is awaited. The flag
+/// says whether `hedged` produced a successful result.
+async fn race<T, A, B>(mut primary: Pin<Box<A>>, mut hedged: Pin<Box<B>>) -> (anyhow::Result<T>, bool)
+where
+    A: Future<Output = anyhow::Result<T>>,
+    B: Future<Output = anyhow::Result<T>>,
+{
+    tokio::select! {
+        r = &mut primary => match r {
+            Ok(v) => (Ok(v), false),
+            Err(_) => {
+                let r = hedged.await;
+                let won = r.is_ok();
+                (r, won)
+            }
+        },
+        r = &mut hedged => match r {
+            Ok(v) => (Ok(v), true),
+            Err(_) => (primary.await, false),
+        },
+    }
+}
+
+fn to_proto(req: CanonicalAIRequest) -> CanonicalAiRequest {
+    let messages = req.messages.into_iter().map(to_proto_message).collect();
+
+    let tools = req
+        .tools
+        .into_iter()
+        .map(|t| ProtoTool {
+            name: t.name,
+            description: t.description.unwrap_or_default(),
+            parameters_json_schema: t
+                .parameters_json_schema
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            strict: t.strict,
+        })
+        .collect();
+
+    let constraints = ProtoGenerationConstraints {
+        max_tokens: req.constraints.max_tokens.unwrap_or_default(),
+        temperature: req.constraints.temperature,
+        top_p: req.constraints.top_p,
+        top_k: req.constraints.top_k.unwrap_or_default(),
+        stop_sequences: req.constraints.stop_sequences,
+        presence_penalty: req.constraints.presence_penalty,
+        frequency_penalty: req.constraints.frequency_penalty,
+        reasoning_effort: req.constraints.reasoning_effort.unwrap_or_default(),
+        stream: req.constraints.stream,
+    };
+
+    CanonicalAiRequest {
+        request_id: req.request_id.to_string(),
+        agent_id: req.agent_id.unwrap_or_default(),
+        session_id: req.session_id.unwrap_or_default(),
+        messages,
+        tools,
+        tool_choice: req.tool_choice.unwrap_or_default(),
+        constraints: Some(constraints),
+        preferred_model: req.preferred_model.unwrap_or_default(),
+        metadata: req.metadata.into_iter().collect(),
+        response_format_json_schema: req.response_format.map(|v| v.to_string()).unwrap_or_default(),
+    }
+}
+
+fn to_proto_message(m: Message) -> ProtoMessage {
+    ProtoMessage {
+        role: match m.role {
+            MessageRole::System => ProtoMessageRole::System,
+            MessageRole::User => ProtoMessageRole::User,
+            MessageRole::Assistant => ProtoMessageRole::Assistant,
+            MessageRole::Tool => ProtoMessageRole::Tool,
+        } as i32,
+        content: m
+            .content
+            .into_iter()
+            .map(|p| match p {
+                ContentPart::Text { text } => ProtoContentPart {
+                    part: Some(crate::proto::content_part::Part::Text(ProtoTextPart { text })),
+                },
+                ContentPart::Image { url } => ProtoContentPart {
+                    part: Some(crate::proto::content_part::Part::Image(ProtoImagePart { url })),
+                },
+                ContentPart::Audio { url } => ProtoContentPart {
+                    part: Some(crate::proto::content_part::Part::Audio(ProtoAudioPart { url })),
+                },
+                ContentPart::File { url, mime_type } => ProtoContentPart {
+                    part: Some(crate::proto::content_part::Part::File(ProtoFilePart { url, mime_type })),
+                },
+            })
+            .collect(),
+        name: m.name.unwrap_or_default(),
+        tool_call_id: m.tool_call_id.unwrap_or_default(),
+        tool_calls: m
+            .tool_calls
+            .into_iter()
+            .map(|c| ProtoToolCall { id: c.id, name: c.name, arguments_json: c.arguments })
+            .collect(),
+    }
+}
+
+/// A request received over the public gRPC `GatewayService`, as the ingress protocols produce
+/// it. Proto3 zero values mean "unset", except for the `optional` sampling fields; an empty
+/// `request_id` gets a fresh one. Unlike adapter output, every message must name its role.
+pub(crate) fn from_proto_request(req: CanonicalAiRequest) -> Result<CanonicalAIRequest, String> {
+    let mut out = CanonicalAIRequest::new();
+    if !req.request_id.is_empty() {
+        out.request_id = req.request_id.parse().map_err(|_| "request_id must be a UUID".to_string())?;
+    }
+    let json = |s: String| (!s.is_empty()).then(|| serde_json::from_str(&s).unwrap_or(serde_json::Value::String(s)));
+    let set = |s: String| Some(s).filter(|s| !s.is_empty());
+    let c = req.constraints.unwrap_or_default();
+    out.agent_id = set(req.agent_id);
+    out.session_id = set(req.session_id);
+    for (i, m) in req.messages.iter().enumerate() {
+        match ProtoMessageRole::try_from(m.role) {
+            Ok(ProtoMessageRole::Unspecified) | Err(_) => {
+                return Err(format!("messages[{i}].role must be SYSTEM, USER, ASSISTANT or TOOL"));
+            }
+            Ok(_) => {}
+        }
+    }
+    out.messages = req.messages.into_iter().map(from_proto_message).collect();
+    out.tools = req
+        .tools
+        .into_iter()
+        .map(|t| crate::canonical::Tool {
+            name: t.name,
+            description: set(t.description),
+            parameters_json_schema: json(t.parameters_json_schema),
+            strict: t.strict,
+        })
+        .collect();
+    out.tool_choice = set(req.tool_choice);
+    out.constraints = crate::canonical::GenerationConstraints {
+        max_tokens: Some(c.max_tokens).filter(|v| *v > 0),
+        temperature: c.temperature,
+        top_p: c.top_p,
+        top_k: Some(c.top_k).filter(|v| *v > 0),
+        stop_sequences: c.stop_sequences,
+        presence_penalty: c.presence_penalty,
+        frequency_penalty: c.frequency_penalty,
+        reasoning_effort: set(c.reasoning_effort),
+        stream: c.stream,
+    };
+    out.preferred_model = set(req.preferred_model);
+    out.metadata = req.metadata.into_iter().collect();
+    out.response_format = json(req.response_format_json_schema);
+    Ok(out)
+}
+
+/// Inverse of [`from_proto_response`], for the public gRPC `GatewayService`.
+pub(crate) fn to_proto_response(resp: CanonicalAIResponse) -> CanonicalAiResponse {
+    CanonicalAiResponse {
+        request_id: resp.request_id,
+        adapter_id: resp.adapter_id,
+        json: resp.json,
+        model: resp.model.unwrap_or_default(),
+        choices: resp
+            .choices
+            .into_iter()
+            .map(|c| crate::proto::Choice {
+                index: c.index,
+                message: Some(to_proto_message(c.message)),
+                finish_reason: match c.finish_reason {
+                    None => ProtoFinishReason::Unspecified,
+                    Some(FinishReason::Stop) => ProtoFinishReason::Stop,
+                    Some(FinishReason::Length) => ProtoFinishReason::Length,
+                    Some(FinishReason::ToolCalls) => ProtoFinishReason::ToolCalls,
+                    Some(FinishReason::ContentFilter) => ProtoFinishReason::ContentFilter,
+                    Some(FinishReason::Error) => ProtoFinishReason::Error,
+                } as i32,
+                stop_sequence: c.stop_sequence.unwrap_or_default(),
+            })
+            .collect(),
+        usage: resp.usage.map(to_proto_usage),
+    }
+}
+
+fn to_proto_usage(u: Usage) -> crate::proto::Usage {
+    crate::proto::Usage { prompt_tokens: u.prompt_tokens, completion_tokens: u.completion_tokens, cached_tokens: u.cached_tokens }
+}
+
+pub(crate) fn from_proto_usage(u: crate::proto::Usage) -> Usage {
+    Usage { prompt_tokens: u.prompt_tokens, completion_tokens: u.completion_tokens, cached_tokens: u.cached_tokens }
+}
+
+fn to_status(e: RegistryError) -> Status {
+    match e {
+        RegistryError::InvalidEndpoint(_) => Status::invalid_argument(e.to_string()),
+        RegistryError::Static(_) => Status::failed_precondition(e.to_string()),
+        RegistryError::NotFound(_) => Status::not_found(e.to_string()),
+    }
+}
+
+fn from_proto_response(resp: CanonicalAiResponse) -> CanonicalAIResponse {
+    let choices = resp
+        .choices
+        .into_iter()
+        .map(|c| Choice {
+            index: c.index,
+            message: c.message.map(from_proto_message).unwrap_or_else(|| Message {
+                role: MessageRole::Assistant,
+                content: vec![],
+                name: None,
+                tool_call_id: None,
+                tool_calls: vec![],
+            }),
+            finish_reason: match ProtoFinishReason::try_from(c.finish_reason) {
+                Ok(ProtoFinishReason::Stop) => Some(FinishReason::Stop),
+                Ok(ProtoFinishReason::Length) => Some(FinishReason::Length),
+                Ok(ProtoFinishReason::ToolCalls) => Some(FinishReason::ToolCalls),
+                Ok(ProtoFinishReason::ContentFilter) => Some(FinishReason::ContentFilter),
+                Ok(ProtoFinishReason::Error) => Some(FinishReason::Error),
+                Ok(ProtoFinishReason::Unspecified) | Err(_) => None,
+            },
+            stop_sequence: Some(c.stop_sequence).filter(|s| !s.is_empty()),
+        })
+        .collect();
+
+    let mut out = CanonicalAIResponse {
+        request_id: resp.request_id,
+        adapter_id: resp.adapter_id,
+        model: Some(resp.model).filter(|m| !m.is_empty()),
+        choices,
+        usage: resp.usage.map(from_proto_usage),
+        json: resp.json,
+    };
+    out.fill_from_legacy_json();
+    out
+}
+
+fn from_proto_message(m: ProtoMessage) -> Message {
+    use crate::proto::content_part::Part;
+
+    Message {
+        role: match ProtoMessageRole::try_from(m.role) {
+            Ok(ProtoMessageRole::System) => MessageRole::System,
+            Ok(ProtoMessageRole::User) => MessageRole::User,
+            Ok(ProtoMessageRole::Tool) => MessageRole::Tool,
+            Ok(ProtoMessageRole::Assistant | ProtoMessageRole::Unspecified) | Err(_) => MessageRole::Assistant,
+        },
+        content: m
+            .content
+            .into_iter()
+            .filter_map(|p| match p.part? {
+                Part::Text(t) => Some(ContentPart::Text { text: t.text }),
+                Part::Image(i) => Some(ContentPart::Image { url: i.url }),
+                Part::Audio(a) => Some(ContentPart::Audio { url: a.url }),
+                Part::File(f) => Some(ContentPart::File { url: f.url, mime_type: f.mime_type }),
+            })
+            .collect(),
+        name: Some(m.name).filter(|n| !n.is_empty()),
+        tool_call_id: Some(m.tool_call_id).filter(|id| !id.is_empty()),
+        tool_calls: m
+            .tool_calls
+            .into_iter()
+            .map(|c| ToolCall { id: c.id, name: c.name, arguments: c.arguments_json })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+impl AdapterRegistryState {
+    /// A registry holding just the streaming `echo` builtin, for the ingress tests.
+    pub(crate) async fn with_echo(replay: RequestReplayConfig, metrics: Metrics) -> Self {
+        let state = Self::new(replay, RegistryConfig::default(), ProvidersConfig::default(), metrics);
+        let echo = AdapterConfig {
+            id: "echo".to_string(),
+            kind: "in_process".to_string(),
+            endpoint: "echo".to_string(),
+            capabilities: crate::config::AdapterCapabilities { streaming: true, ..Default::default() },
+        };
+        state.register_static(&echo).await.unwrap();
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{AdapterHealth as ProtoAdapterHealth, BreakerState as ProtoBreakerState};
+    use crate::proto::adapter_service_server::AdapterServiceServer;
+    use std::net::SocketAddr;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    /// Minimal adapter that echoes the request id; used to exercise real gRPC forwarding.
+    /// It can be told to fail its first calls with `UNAVAILABLE` or to answer slowly.
+    #[derive(Default)]
+    struct MockAdapter {
+        id: &'static str,
+        fail_first: Arc<AtomicUsize>,
+        delay: Duration,
+    }
+
+    #[tonic::async_trait]
+    impl AdapterService for MockAdapter {
+        async fn process(&self, request: Request<CanonicalAiRequest>) -> Result<Response<CanonicalAiResponse>, Status> {
+            let r = request.into_inner();
+            tokio::time::sleep(self.delay).await;
+            if self.fail_first.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                return Err(Status::unavailable("mock warming up"));
+            }
+            Ok(Response::new(CanonicalAiResponse {
+                request_id: r.request_id,
+                adapter_id: self.id.to_string(),
+                json: r#"{"text":"ok"}"#.to_string(),
+                usage: Some(crate::proto::Usage { prompt_tokens: 2, completion_tokens: 1, cached_tokens: 0 }),
+                ..Default::default()
+            }))
+        }
+
+        type ProcessStreamStream = tokio_stream::Iter<std::vec::IntoIter<Result<CanonicalAiChunk, Status>>>;
+
+        async fn process_stream(
+            &self,
+            _request: Request<CanonicalAiRequest>,
+        ) -> Result<Response<Self::ProcessStreamStream>, Status> {
+            Err(Status::unimplemented("mock"))
+        }
+    }
+
+    async fn spawn_mock(id: &'static str) -> SocketAddr {
+        spawn_adapter(MockAdapter { id, ..Default::default() }).await
+    }
+
+    async fn spawn_adapter(mock: MockAdapter) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(AdapterServiceServer::new(mock))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        addr
+    }
+
+    fn info(id: &str, endpoint: String) -> AdapterInfo {
+        AdapterInfo { adapter_id: id.to_string(), endpoint, ..Default::default() }
+    }
+
+    fn new_state(config: RegistryConfig) -> AdapterRegistryState {
+        AdapterRegistryState::new(RequestReplayConfig::default(), config, ProvidersConfig::default(), Metrics::new())
+    }
+
+    #[tokio::test]
+    async fn registry_starts_empty() {
+        let st = new_state(RegistryConfig::default());
+        let adapters = st.inner.adapters.read().await;
+        assert!(adapters.is_empty());
+    }
+
+    #[test]
+    fn proto_response_maps_structured_fields() {
+        let resp = CanonicalAiResponse {
+            request_id: "r".to_string(),
+            adapter_id: "a".to_string(),
+            json: String::new(),
+            model: "m".to_string(),
+            choices: vec![crate::proto::Choice {
+                index: 0,
+                message: Some(ProtoMessage {
+                    role: ProtoMessageRole::Assistant as i32,
+                    content: vec![],
+                    name: String::new(),
+                    tool_call_id: String::new(),
+                    tool_calls: vec![ProtoToolCall { id: "c1".to_string(), name: "f".to_string(), arguments_json: "{}".to_string() }],
+                }),
+                finish_reason: ProtoFinishReason::ToolCalls as i32,
+                stop_sequence: String::new(),
+            }],
+            usage: Some(crate::proto::Usage { prompt_tokens: 3, completion_tokens: 2, cached_tokens: 1 }),
+        };
+        let out = from_proto_response(resp);
+        assert_eq!(out.model.as_deref(), Some("m"));
+        assert_eq!(out.choices[0].finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(out.choices[0].message.tool_calls[0].id, "c1");
+        assert_eq!(out.usage.unwrap().total_tokens(), 5);
+    }
+
+    #[tokio::test]
+    async fn forwards_over_pooled_channel() {
+        let addr = spawn_mock("mock").await;
+        let st = new_state(RegistryConfig::default());
+        st.register(info("mock", format!("http://{addr}"))).await.unwrap();
+
+        for _ in 0..3 {
+            let resp = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+            assert_eq!(resp.adapter_id, "mock");
+            assert_eq!(resp.text(), "ok");
+        }
+    }
+
+    #[tokio::test]
+    async fn non_streaming_adapter_streams_its_whole_reply() {
+        let addr = spawn_mock("mock").await;
+        let st = new_state(RegistryConfig::default());
+        st.register(info("mock", format!("http://{addr}"))).await.unwrap();
+
+        let mut stream = st.forward_stream(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+        let chunk = stream.chunks.recv().await.unwrap().unwrap();
+        assert_eq!(chunk.delta, "ok");
+        assert!(chunk.done);
+        assert_eq!(chunk.finish_reason, "stop");
+        assert_eq!(chunk.usage.map(|u| u.completion_tokens), Some(1));
+        assert!(stream.chunks.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reregister_with_new_endpoint_rebuilds_channel() {
+        let dead = "http://127.0.0.1:1".to_string();
+        let st = new_state(RegistryConfig::default());
+        st.register(info("mock", dead)).await.unwrap();
+        assert!(st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.is_err());
+
+        let addr = spawn_mock("mock").await;
+        st.register(info("mock", format!("http://{addr}"))).await.unwrap();
+        assert!(st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_endpoint() {
+        let st = new_state(RegistryConfig::default());
+        assert!(st.register(info("bad", "not a uri".to_string())).await.is_err());
+        assert!(st.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_marks_unhealthy_then_evicts() {
+        let cfg = RegistryConfig {
+            health: crate::config::HealthConfig { lease_ttl_secs: 10, evict_after_secs: 30 },
+            ..Default::default()
+        };
+        let st = new_state(cfg);
+        st.register(info("a", "http://127.0.0.1:1".to_string())).await.unwrap();
+        let t0 = Instant::now();
+
+        st.sweep(t0 + std::time::Duration::from_secs(11)).await;
+        assert_eq!(st.list().await[0].health, ProtoAdapterHealth::Unhealthy as i32);
+        assert!(st.candidates(&CanonicalAIRequest::new()).await.is_empty());
+
+        assert!(st.heartbeat("a").await);
+        assert_eq!(st.list().await[0].health, ProtoAdapterHealth::Healthy as i32);
+        assert_eq!(st.candidates(&CanonicalAIRequest::new()).await.len(), 1);
+
+        st.sweep(Instant::now() + std::time::Duration::from_secs(31)).await;
+        assert!(st.list().await.is_empty());
+        assert!(!st.heartbeat("a").await);
+    }
+
+    #[tokio::test]
+    async fn draining_adapter_gets_no_new_requests() {
+        let addr = spawn_mock("mock").await;
+        let st = new_state(RegistryConfig::default());
+        st.register(info("mock", format!("http://{addr}"))).await.unwrap();
+
+        let entry = st.candidates(&CanonicalAIRequest::new()).await.remove(0).1;
+        let in_flight = entry.begin(None);
+
+        assert_eq!(st.drain("mock").await, Some(1));
+        assert!(st.list().await[0].draining);
+        assert!(st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.is_err());
+
+        drop(in_flight);
+        assert_eq!(st.drain("mock").await, Some(0));
+
+        assert!(st.deregister("mock").await.is_ok());
+        assert!(st.list().await.is_empty());
+        assert_eq!(st.drain("mock").await, None);
+    }
+
+    #[tokio::test]
+    async fn providers_drive_order_and_model_mapping() {
+        let providers: ProvidersConfig = serde_yaml::from_str(
+            r#"
+            ollama: { failover: true, models_mapping: { "gpt-4o": "llama3" } }
+            openrouter: { default: true }
+            anthropic: { enabled: false, default: true }
+            "#,
+        )
+        .unwrap();
+        let st = AdapterRegistryState::new(
+            RequestReplayConfig::default(),
+            RegistryConfig::default(),
+            providers,
+            Metrics::new(),
+        );
+        for id in ["anthropic", "ollama", "openrouter"] {
+            st.register(info(id, "http://127.0.0.1:1".to_string())).await.unwrap();
+        }
+
+        let ids: Vec<_> = st.candidates(&CanonicalAIRequest::new()).await.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["openrouter", "ollama"]);
+
+        let req = CanonicalAiRequest { preferred_model: "gpt-4o".to_string(), ..Default::default() };
+        assert_eq!(st.request_for("openrouter", &req).preferred_model, "gpt-4o");
+        let fallback = st.request_for("ollama", &req);
+        assert_eq!(fallback.preferred_model, "llama3");
+        assert_eq!(fallback.metadata.get("requested_model").map(String::as_str), Some("gpt-4o"));
+    }
+
+    #[tokio::test]
+    async fn pipeline_selects_its_policy() {
+        let mut cfg = RegistryConfig::default();
+        cfg.routing.pipelines.insert("batch".to_string(), crate::config::RoutingPolicyKind::LeastOutstanding);
+        let st = new_state(cfg);
+        for id in ["a", "b"] {
+            st.register(info(id, "http://127.0.0.1:1".to_string())).await.unwrap();
+        }
+        let busy = st.candidates(&CanonicalAIRequest::new()).await.remove(0).1;
+        let _in_flight = busy.begin(None);
+
+        let ids = |c: Vec<(String, AdapterEntry)>| c.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(st.candidates(&CanonicalAIRequest::new()).await), vec!["a"]);
+        let mut req = CanonicalAIRequest::new();
+        req.metadata.insert("pipeline".to_string(), "batch".to_string());
+        assert_eq!(ids(st.candidates(&req).await), vec!["b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn open_breaker_is_skipped_and_listed() {
+        let mut cfg = RegistryConfig::default();
+        cfg.breaker.consecutive_failures = 2;
+        let st = new_state(cfg);
+        st.register(info("dead", "http://127.0.0.1:1".to_string())).await.unwrap();
+
+        for _ in 0..2 {
+            assert!(st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.is_err());
+        }
+        assert_eq!(st.list().await[0].breaker, ProtoBreakerState::Open as i32);
+        assert!(st.candidates(&CanonicalAIRequest::new()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_probe_frees_half_open_slot() {
+        let mut cfg = RegistryConfig::default();
+        cfg.forwarding.max_retries = 0;
+        cfg.breaker = BreakerConfig { consecutive_failures: 1, cooldown_ms: 0, ..Default::default() };
+        let st = new_state(cfg);
+        st.register(info("dead", "http://127.0.0.1:1".to_string())).await.unwrap();
+        assert!(st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.is_err());
+        assert_eq!(st.list().await[0].breaker, ProtoBreakerState::Open as i32);
+
+        // The probe is cancelled mid-call, as when the client goes away.
+        let candidates = st.candidates(&CanonicalAIRequest::new()).await;
+        let opts = ForwardOptions::default();
+        let probe = st.run(candidates, &opts, Priority::Normal, false, |_: Attempt| {
+            std::future::pending::<anyhow::Result<()>>()
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(50), probe).await.is_err());
+
+        assert_eq!(st.list().await[0].breaker, ProtoBreakerState::HalfOpen as i32);
+        assert_eq!(st.candidates(&CanonicalAIRequest::new()).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_failures() {
+        let fail_first = Arc::new(AtomicUsize::new(2));
+        let addr = spawn_adapter(MockAdapter { id: "flaky", fail_first: fail_first.clone(), ..Default::default() }).await;
+        let mut cfg = RegistryConfig::default();
+        cfg.forwarding.backoff_base_ms = 1;
+        let st = new_state(cfg);
+        st.register(info("flaky", format!("http://{addr}"))).await.unwrap();
+
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+        assert_eq!(resp.adapter_id, "flaky");
+        assert_eq!(fail_first.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn slow_adapter_hits_deadline() {
+        let addr =
+            spawn_adapter(MockAdapter { id: "slow", delay: Duration::from_secs(5), ..Default::default() }).await;
+        let st = new_state(RegistryConfig::default());
+        st.register(info("slow", format!("http://{addr}"))).await.unwrap();
+
+        let started = Instant::now();
+        let opts = ForwardOptions { timeout: Some(Duration::from_millis(200)), ..Default::default() };
+        let err = st.forward_with(CanonicalAIRequest::chat_text(None, "hi".to_string()), opts).await.unwrap_err();
+        assert!(err.downcast_ref::<DeadlineExceeded>().is_some(), "{err:#}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn hedge_beats_slow_primary() {
+        let slow = spawn_adapter(MockAdapter { id: "a", delay: Duration::from_secs(5), ..Default::default() }).await;
+        let fast = spawn_mock("b").await;
+        let mut cfg = RegistryConfig::default();
+        cfg.routing.policy = crate::config::RoutingPolicyKind::LeastOutstanding;
+        cfg.hedging = crate::config::HedgingConfig { enabled: true, delay_ms: 50, ..Default::default() };
+        let st = new_state(cfg);
+        st.register(info("a", format!("http://{slow}"))).await.unwrap();
+        st.register(info("b", format!("http://{fast}"))).await.unwrap();
+
+        let started = Instant::now();
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+        assert_eq!(resp.adapter_id, "b");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // The losing call was cancelled, so nothing is left in flight.
+        assert!(st.list().await.iter().all(|a| a.in_flight == 0));
+    }
+
+    #[tokio::test]
+    async fn race_only_credits_a_hedge_that_succeeds() {
+        let fail = || Box::pin(async { Err::<(), _>(anyhow::anyhow!("down")) });
+        let (r, hedge_won) = race(fail(), fail()).await;
+        assert!(r.is_err());
+        assert!(!hedge_won, "a failed request is not a hedge win");
+
+        let (r, hedge_won) = race(fail(), Box::pin(async { Ok(()) })).await;
+        assert!(r.is_ok() && hedge_won);
+        let (r, hedge_won) = race(Box::pin(async { Ok(()) }), fail()).await;
+        assert!(r.is_ok() && !hedge_won);
+    }
+
+    #[tokio::test]
+    async fn half_open_adapter_that_loses_hedge_can_be_probed_again() {
+        let slow = spawn_adapter(MockAdapter { id: "a", delay: Duration::from_secs(5), ..Default::default() }).await;
+        let fast = spawn_mock("b").await;
+        let mut cfg = RegistryConfig::default();
+        cfg.routing.policy = crate::config::RoutingPolicyKind::LeastOutstanding;
+        cfg.hedging = crate::config::HedgingConfig { enabled: true, delay_ms: 50, ..Default::default() };
+        cfg.breaker = BreakerConfig { consecutive_failures: 1, cooldown_ms: 0, ..Default::default() };
+        let st = new_state(cfg.clone());
+        st.register(info("a", format!("http://{slow}"))).await.unwrap();
+        st.register(info("b", format!("http://{fast}"))).await.unwrap();
+        let a = st.candidates(&CanonicalAIRequest::new()).await.remove(0).1;
+        a.stats.breaker.lock().unwrap().record(&cfg.breaker, true, Instant::now());
+
+        // "a" is probed half-open, is hedged against "b" and loses.
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+        assert_eq!(resp.adapter_id, "b");
+        assert_eq!(st.list().await[0].breaker, ProtoBreakerState::HalfOpen as i32);
+        let ids: Vec<_> = st.candidates(&CanonicalAIRequest::new()).await.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn busy_adapter_queues_then_times_out() {
+        let addr =
+            spawn_adapter(MockAdapter { id: "ollama", delay: Duration::from_millis(400), ..Default::default() }).await;
+        let mut cfg = RegistryConfig::default();
+        cfg.forwarding.max_retries = 0;
+        cfg.concurrency.adapters.insert("ollama".to_string(), 1);
+        cfg.concurrency.queue_timeout_ms = 50;
+        let st = new_state(cfg);
+        st.register(info("ollama", format!("http://{addr}"))).await.unwrap();
+
+        let first = tokio::spawn({
+            let st = st.clone();
+            async move { st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let err = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<QueueError>(), Some(QueueError::Timeout)), "{err:#}");
+        assert_eq!(first.await.unwrap().unwrap().adapter_id, "ollama");
+        // The slot is free again.
+        st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn forwards_over_unix_socket_endpoint() {
+        let path = std::env::temp_dir().join(format!("pagi-test-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(AdapterServiceServer::new(MockAdapter { id: "uds", ..Default::default() }))
+                .serve_with_incoming(tokio_stream::wrappers::UnixListenerStream::new(listener)),
+        );
+
+        let st = new_state(RegistryConfig::default());
+        st.register(info("uds", format!("unix://{}", path.display()))).await.unwrap();
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "hi".to_string())).await.unwrap();
+        assert_eq!(resp.adapter_id, "uds");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn registration_requires_configured_token() {
+        let mut cfg = RegistryConfig::default();
+        cfg.auth.tokens.insert(
+            "openrouter".to_string(),
+            crate::config::TokenConfig { token: Some("s3cret".to_string()), token_env: None },
+        );
+        let svc = AdapterRegistrySvc { state: new_state(cfg) };
+        let register = |token: Option<&str>| {
+            let mut req = Request::new(RegisterAdapterRequest {
+                adapter_id: "openrouter".to_string(),
+                endpoint: "http://127.0.0.1:1".to_string(),
+                ..Default::default()
+            });
+            if let Some(t) = token {
+                req.metadata_mut().insert("authorization", format!("Bearer {t}").parse().unwrap());
+            }
+            req
+        };
+
+        let err = svc.register(register(Some("hijack"))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        assert!(svc.state.list().await.is_empty());
+        assert!(svc.register(register(Some("s3cret"))).await.is_ok());
+        assert_eq!(svc.state.list().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn static_adapters_are_kept_apart() {
+        let cfg = RegistryConfig {
+            health: crate::config::HealthConfig { lease_ttl_secs: 10, evict_after_secs: 30 },
+            ..Default::default()
+        };
+        let st = new_state(cfg);
+        let echo = AdapterConfig {
+            id: "echo".to_string(),
+            kind: "in_process".to_string(),
+            endpoint: "echo".to_string(),
+            capabilities: Default::default(),
+        };
+        st.register_static(&echo).await.unwrap();
+
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "hello there".to_string())).await.unwrap();
+        assert_eq!(resp.text(), "hello there");
+
+        assert!(st.register(info("echo", "echo".to_string())).await.is_ok());
+        assert!(matches!(st.register(info("echo", "http://127.0.0.1:1".to_string())).await, Err(RegistryError::Static(_))));
+        assert!(matches!(st.deregister("echo").await, Err(RegistryError::Static(_))));
+
+        // Static adapters never heartbeat and are exempt from eviction.
+        st.sweep(Instant::now() + std::time::Duration::from_secs(60)).await;
+        let listed = st.list().await;
+        assert_eq!(listed[0].origin, ProtoAdapterOrigin::Static as i32);
+        assert_eq!(listed[0].health, ProtoAdapterHealth::Healthy as i32);
+    }
+
+    #[tokio::test]
+    async fn restarted_static_adapter_is_routable_after_drain() {
+        let st = new_state(RegistryConfig::default());
+        let echo = AdapterConfig {
+            id: "echo".to_string(),
+            kind: "in_process".to_string(),
+            endpoint: "echo".to_string(),
+            capabilities: Default::default(),
+        };
+        st.register_static(&echo).await.unwrap();
+
+        // The adapter drains on shutdown and registers again when it comes back.
+        assert_eq!(st.drain("echo").await, Some(0));
+        assert!(st.candidates(&CanonicalAIRequest::new()).await.is_empty());
+        st.register(info("echo", "echo".to_string())).await.unwrap();
+
+        assert!(!st.list().await[0].draining);
+        let resp = st.forward(CanonicalAIRequest::chat_text(None, "back".to_string())).await.unwrap();
+        assert_eq!(resp.text(), "back");
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_kind_and_builtin() {
+        let st = new_state(RegistryConfig::default());
+        let mut cfg = AdapterConfig {
+            id: "x".to_string(),
+            kind: "smtp".to_string(),
+            endpoint: "echo".to_string(),
+            capabilities: Default::default(),
+        };
+        assert!(st.register_static(&cfg).await.is_err());
+        cfg.kind = "in_process".to_string();
+        cfg.endpoint = "nope".to_string();
+        assert!(st.register_static(&cfg).await.is_err());
+    }
+}
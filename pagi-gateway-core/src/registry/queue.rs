@@ -0,0 +1,233 @@
+//! Per-adapter concurrency limits.
+//!
+//! An adapter with a `max_in_flight` limit takes that many calls at once. Further calls wait in
+//! a bounded queue, higher priority first and in arrival order within a priority, for at most
+//! `queue_timeout_ms`. A finished call hands its slot straight to the next waiter, so new
+//! arrivals cannot overtake the queue.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::ConcurrencyConfig;
+
+/// Queue class of a request, from `metadata.priority` or the caller's API key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "high" => Some(Priority::High),
+            "normal" => Some(Priority::Normal),
+            "low" => Some(Priority::Low),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// `metadata.priority` of `req`; normal if absent or unrecognized.
+    pub fn of(req: &CanonicalAIRequest) -> Self {
+        req.metadata.get("priority").and_then(|p| Self::parse(p)).unwrap_or_default()
+    }
+}
+
+impl ConcurrencyConfig {
+    /// In-flight limit for `adapter_id`; 0 means unlimited.
+    pub fn limit_for(&self, adapter_id: &str) -> usize {
+        self.adapters.get(adapter_id).copied().unwrap_or(self.max_in_flight)
+    }
+
+    pub fn queue_timeout(&self) -> Duration {
+        Duration::from_millis(self.queue_timeout_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum QueueError {
+    #[error("adapter queue is full")]
+    Full,
+    #[error("timed out waiting for an adapter slot")]
+    Timeout,
+}
+
+impl QueueError {
+    pub fn label(self) -> &'static str {
+        match self {
+            QueueError::Full => "full",
+            QueueError::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Gate {
+    state: Mutex<GateState>,
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+    running: usize,
+    next_seq: u64,
+    waiters: BTreeMap<(Priority, u64), oneshot::Sender<()>>,
+}
+
+pub(crate) enum Admission {
+    Ready(Permit),
+    Queued(Waiter),
+}
+
+impl Gate {
+    /// Take a slot under `limit` (0 for none), or join the queue if it has fewer than `depth`
+    /// waiters.
+    pub(crate) fn enter(self: &Arc<Self>, limit: usize, depth: usize, priority: Priority) -> Result<Admission, QueueError> {
+        if limit == 0 {
+            return Ok(Admission::Ready(Permit(None)));
+        }
+        let mut st = self.state.lock().unwrap();
+        if st.running < limit && st.waiters.is_empty() {
+            st.running += 1;
+            return Ok(Admission::Ready(Permit(Some(self.clone()))));
+        }
+        if st.waiters.len() >= depth {
+            return Err(QueueError::Full);
+        }
+        let key = (priority, st.next_seq);
+        st.next_seq += 1;
+        let (tx, rx) = oneshot::channel();
+        st.waiters.insert(key, tx);
+        Ok(Admission::Queued(Waiter { gate: self.clone(), key: Some(key), rx }))
+    }
+
+    pub(crate) fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiters.len()
+    }
+
+    /// Pass a finished call's slot to the first live waiter, or free it.
+    fn release(&self) {
+        let mut st = self.state.lock().unwrap();
+        while let Some((_, tx)) = st.waiters.pop_first() {
+            if tx.send(()).is_ok() {
+                return;
+            }
+        }
+        st.running = st.running.saturating_sub(1);
+    }
+}
+
+/// A place in an adapter's queue. Dropping it leaves the queue.
+pub(crate) struct Waiter {
+    gate: Arc<Gate>,
+    key: Option<(Priority, u64)>,
+    rx: oneshot::Receiver<()>,
+}
+
+impl Waiter {
+    pub(crate) async fn wait(mut self, timeout: Duration) -> Result<Permit, QueueError> {
+        match tokio::time::timeout(timeout, &mut self.rx).await {
+            Ok(Ok(())) => {
+                self.key = None;
+                Ok(Permit(Some(self.gate.clone())))
+            }
+            _ => Err(QueueError::Timeout),
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let removed = self.gate.state.lock().unwrap().waiters.remove(&key).is_some();
+        if !removed {
+            // A slot was handed over just as we gave up; pass it on.
+            self.gate.release();
+        }
+    }
+}
+
+/// One slot of an adapter's concurrency limit, held until dropped.
+pub(crate) struct Permit(Option<Arc<Gate>>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(gate) = self.0.take() {
+            gate.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready(a: Result<Admission, QueueError>) -> Permit {
+        match a {
+            Ok(Admission::Ready(p)) => p,
+            _ => panic!("expected a free slot"),
+        }
+    }
+
+    fn queued(a: Result<Admission, QueueError>) -> Waiter {
+        match a {
+            Ok(Admission::Queued(w)) => w,
+            _ => panic!("expected to queue"),
+        }
+    }
+
+    #[tokio::test]
+    async fn freed_slots_go_to_higher_priority_first() {
+        let gate = Arc::new(Gate::default());
+        let running = ready(gate.enter(1, 4, Priority::Normal));
+        let low = queued(gate.enter(1, 4, Priority::Low));
+        let high = queued(gate.enter(1, 4, Priority::High));
+        assert_eq!(gate.queued(), 2);
+
+        drop(running);
+        let high = high.wait(Duration::from_millis(50)).await.unwrap();
+        assert!(matches!(low.wait(Duration::from_millis(20)).await, Err(QueueError::Timeout)));
+        assert_eq!(gate.queued(), 0);
+
+        drop(high);
+        let _next = ready(gate.enter(1, 4, Priority::Low));
+    }
+
+    #[tokio::test]
+    async fn full_queue_rejects_and_unlimited_never_queues() {
+        let gate = Arc::new(Gate::default());
+        let _running = ready(gate.enter(1, 1, Priority::Normal));
+        let _waiting = queued(gate.enter(1, 1, Priority::Normal));
+        assert!(matches!(gate.enter(1, 1, Priority::High), Err(QueueError::Full)));
+
+        let _a = ready(gate.enter(0, 0, Priority::Low));
+        let _b = ready(gate.enter(0, 0, Priority::Low));
+    }
+
+    #[test]
+    fn priority_comes_from_metadata() {
+        let mut req = CanonicalAIRequest::new();
+        assert_eq!(Priority::of(&req), Priority::Normal);
+        req.metadata.insert("priority".to_string(), "HIGH".to_string());
+        assert_eq!(Priority::of(&req), Priority::High);
+        req.metadata.insert("priority".to_string(), "urgent".to_string());
+        assert_eq!(Priority::of(&req), Priority::Normal);
+    }
+}
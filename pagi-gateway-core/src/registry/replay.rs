@@ -0,0 +1,116 @@
+//! Request replay log.
+//!
+//! With `core.request_replay.enabled`, every forwarded request is appended to `path` as one JSON
+//! line. Each line is the canonical request plus `received_at` (unix seconds), so it can be fed
+//! back in as-is. Lines written before `received_at` was added have no timestamp.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::canonical::CanonicalAIRequest;
+
+#[derive(Serialize)]
+struct Record<'a> {
+    received_at: u64,
+    #[serde(flatten)]
+    request: &'a CanonicalAIRequest,
+}
+
+/// One request read back from the log.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplayEntry {
+    #[serde(default)]
+    pub received_at: Option<u64>,
+    #[serde(flatten)]
+    pub request: CanonicalAIRequest,
+}
+
+/// Which logged requests to return. Time bounds are unix seconds, inclusive, and exclude
+/// entries without a timestamp.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayFilter {
+    pub agent_id: Option<String>,
+    pub session_id: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl ReplayFilter {
+    fn matches(&self, e: &ReplayEntry) -> bool {
+        let eq = |want: &Option<String>, have: &Option<String>| want.is_none() || want == have;
+        let within = |bound: Option<u64>, ok: fn(u64, u64) -> bool| match bound {
+            None => true,
+            Some(b) => e.received_at.is_some_and(|t| ok(t, b)),
+        };
+        eq(&self.agent_id, &e.request.agent_id)
+            && eq(&self.session_id, &e.request.session_id)
+            && within(self.since, |t, b| t >= b)
+            && within(self.until, |t, b| t <= b)
+    }
+}
+
+pub(crate) async fn append(path: &str, req: &CanonicalAIRequest) -> std::io::Result<()> {
+    let received_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let mut line = serde_json::to_vec(&Record { received_at, request: req })?;
+    line.push(b'\n');
+    let mut f = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    f.write_all(&line).await
+}
+
+/// The last `limit` entries matching `filter`, newest first. A missing log is empty, and lines
+/// that do not parse are skipped.
+pub(crate) async fn read(path: &str, filter: &ReplayFilter, limit: usize) -> std::io::Result<Vec<ReplayEntry>> {
+    let f = match tokio::fs::File::open(path).await {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut lines = BufReader::new(f).lines();
+    let mut last = VecDeque::with_capacity(limit);
+    while let Some(line) = lines.next_line().await? {
+        let Ok(entry) = serde_json::from_str::<ReplayEntry>(&line) else {
+            continue;
+        };
+        if limit == 0 || !filter.matches(&entry) {
+            continue;
+        }
+        if last.len() == limit {
+            last.pop_front();
+        }
+        last.push_back(entry);
+    }
+    Ok(last.into_iter().rev().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_back_newest_matching_first() {
+        let dir = std::env::temp_dir().join(format!("pagi-replay-{}", uuid::Uuid::new_v4()));
+        let path = dir.to_string_lossy().into_owned();
+        assert!(read(&path, &ReplayFilter::default(), 10).await.unwrap().is_empty());
+
+        for (agent, text) in [("a", "one"), ("b", "two"), ("a", "three")] {
+            append(&path, &CanonicalAIRequest::chat_text(Some(agent.to_string()), text.to_string())).await.unwrap();
+        }
+        let legacy = serde_json::to_string(&CanonicalAIRequest::chat_text(Some("a".to_string()), "old".to_string())).unwrap();
+        tokio::fs::write(&path, format!("{legacy}\nnot json\n{}", tokio::fs::read_to_string(&path).await.unwrap()))
+            .await
+            .unwrap();
+
+        let only_a = ReplayFilter { agent_id: Some("a".to_string()), ..Default::default() };
+        let got = read(&path, &only_a, 2).await.unwrap();
+        let texts: Vec<_> = got.iter().map(|e| e.request.messages[0].text()).collect();
+        assert_eq!(texts, ["three", "one"]);
+
+        // A time bound drops the untimestamped legacy line.
+        let since = ReplayFilter { since: Some(1), ..only_a };
+        assert_eq!(read(&path, &since, 10).await.unwrap().len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,140 @@
+//! Deadlines, retry backoff and the retry budget.
+//!
+//! Every forward runs under a total deadline (`x-pagi-timeout-ms` or `timeout_ms`), split into
+//! per-attempt timeouts. When all candidates fail with a retryable status the list is tried
+//! again after a jittered exponential backoff, up to `max_retries` times. Each retry spends a
+//! token from a shared [`RetryBudget`], so during an outage retries stay a small fraction of
+//! traffic instead of multiplying it.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tonic::{Code, Status};
+
+use crate::config::ForwardingConfig;
+
+use super::QueueError;
+
+impl ForwardingConfig {
+    /// Total deadline for one forward: the client's request clamped to `max_timeout_ms`, or
+    /// `timeout_ms` when the client asked for nothing.
+    pub fn deadline(&self, requested: Option<Duration>) -> Duration {
+        requested.unwrap_or(Duration::from_millis(self.timeout_ms)).min(Duration::from_millis(self.max_timeout_ms))
+    }
+
+    /// Per-attempt timeout given what is left of the deadline.
+    pub(crate) fn attempt_timeout(&self, remaining: Duration) -> Duration {
+        match self.attempt_timeout_ms {
+            0 => remaining,
+            ms => Duration::from_millis(ms).min(remaining),
+        }
+    }
+
+    /// Full-jitter exponential backoff before retry number `retry` (1-based).
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let cap = self
+            .backoff_base_ms
+            .saturating_mul(1u64 << retry.saturating_sub(1).min(20))
+            .min(self.backoff_max_ms);
+        Duration::from_millis(fastrand::u64(0..=cap))
+    }
+}
+
+/// Whether another attempt could succeed where this one failed. Transient gRPC codes, transport
+/// errors and a full adapter queue qualify. Anything else is final: errors about the request
+/// itself, and a queue timeout, which has already waited out `queue_timeout_ms`.
+pub(crate) fn is_retryable(err: &anyhow::Error) -> bool {
+    if let Some(status) = err.downcast_ref::<Status>() {
+        return matches!(
+            status.code(),
+            Code::Unavailable | Code::ResourceExhausted | Code::Aborted | Code::DeadlineExceeded
+        );
+    }
+    if let Some(queue) = err.downcast_ref::<QueueError>() {
+        return *queue == QueueError::Full;
+    }
+    err.downcast_ref::<tonic::transport::Error>().is_some() || err.downcast_ref::<hyper::Error>().is_some()
+}
+
+/// Token bucket shared by all forwards. Each request deposits `budget_ratio` tokens, a floor of
+/// `budget_min_per_sec` tokens accrues over time, and each retry withdraws one. The bucket holds
+/// at most ten seconds' worth of the floor.
+pub(crate) struct RetryBudget {
+    ratio: f64,
+    min_per_sec: f64,
+    cap: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RetryBudget {
+    pub(crate) fn new(cfg: &ForwardingConfig) -> Self {
+        let cap = (cfg.budget_min_per_sec * 10.0).max(1.0);
+        Self {
+            ratio: cfg.budget_ratio,
+            min_per_sec: cfg.budget_min_per_sec,
+            cap,
+            state: Mutex::new((cap, Instant::now())),
+        }
+    }
+
+    pub(crate) fn deposit(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = (state.0 + self.ratio).min(self.cap);
+    }
+
+    pub(crate) fn try_withdraw(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.saturating_duration_since(state.1).as_secs_f64();
+        state.0 = (state.0 + elapsed * self.min_per_sec).min(self.cap);
+        state.1 = now;
+        if state.0 >= 1.0 {
+            state.0 -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadline_is_clamped() {
+        let cfg = ForwardingConfig { timeout_ms: 1000, max_timeout_ms: 5000, ..Default::default() };
+        assert_eq!(cfg.deadline(None), Duration::from_secs(1));
+        assert_eq!(cfg.deadline(Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(cfg.deadline(Some(Duration::from_secs(60))), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn backoff_stays_under_cap() {
+        let cfg = ForwardingConfig { backoff_base_ms: 10, backoff_max_ms: 100, ..Default::default() };
+        for retry in 1..40 {
+            assert!(cfg.backoff(retry) <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn budget_limits_retries() {
+        let cfg = ForwardingConfig { budget_ratio: 0.5, budget_min_per_sec: 0.1, ..Default::default() };
+        let budget = RetryBudget::new(&cfg);
+        let now = Instant::now();
+        // Starts with one token (the floor's ten-second allowance).
+        assert!(budget.try_withdraw(now));
+        assert!(!budget.try_withdraw(now));
+        budget.deposit();
+        budget.deposit();
+        assert!(budget.try_withdraw(now));
+    }
+
+    #[test]
+    fn classifies_retryable() {
+        assert!(is_retryable(&Status::unavailable("down").into()));
+        assert!(!is_retryable(&Status::invalid_argument("bad").into()));
+        assert!(is_retryable(&QueueError::Full.into()));
+        assert!(!is_retryable(&QueueError::Timeout.into()));
+        assert!(!is_retryable(&anyhow::anyhow!("unknown adapter kind")));
+    }
+}
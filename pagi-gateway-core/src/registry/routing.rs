@@ -0,0 +1,234 @@
+//! Adapter selection.
+//!
+//! A [`RoutingPolicy`] orders the routable adapters for one request; the registry then tries
+//! them in that order until one succeeds. Policies are chosen by name in
+//! `core.registry.routing`, either globally (`policy`) or per pipeline (`pipelines`), where a
+//! request picks its pipeline with `metadata.pipeline`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::canonical::CanonicalAIRequest;
+use crate::config::{ProvidersConfig, RoutingConfig, RoutingPolicyKind};
+use crate::proto::AdapterCapabilities;
+
+/// What a policy sees of one routable adapter.
+#[derive(Debug, Clone, Default)]
+pub struct AdapterSnapshot {
+    pub adapter_id: String,
+    pub capabilities: AdapterCapabilities,
+    pub in_flight: usize,
+}
+
+pub trait RoutingPolicy: Send + Sync {
+    /// Order `adapters` (every routable adapter, sorted by id) for `req`. Adapters left out of
+    /// the result are not tried.
+    fn route(&self, req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String>;
+}
+
+/// `default` providers, then `failover` providers (each in config order), then the first other
+/// adapter as a last resort.
+pub struct Priority {
+    providers: ProvidersConfig,
+}
+
+impl Priority {
+    pub fn new(providers: ProvidersConfig) -> Self {
+        Self { providers }
+    }
+}
+
+impl RoutingPolicy for Priority {
+    fn route(&self, _req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String> {
+        let present = |id: &str| adapters.iter().any(|a| a.adapter_id == id);
+        let defaults = self.providers.0.iter().filter(|(_, p)| p.default);
+        let failovers = self.providers.0.iter().filter(|(_, p)| p.failover && !p.default);
+
+        let mut out: Vec<String> =
+            defaults.chain(failovers).map(|(id, _)| id).filter(|id| present(id)).cloned().collect();
+        if let Some(a) = adapters.iter().find(|a| !out.contains(&a.adapter_id)) {
+            out.push(a.adapter_id.clone());
+        }
+        out
+    }
+}
+
+/// Rotates the starting adapter on every request; the rest follow as failover.
+#[derive(Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoutingPolicy for RoundRobin {
+    fn route(&self, _req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String> {
+        if adapters.is_empty() {
+            return Vec::new();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % adapters.len();
+        adapters.iter().cycle().skip(start).take(adapters.len()).map(|a| a.adapter_id.clone()).collect()
+    }
+}
+
+/// Random order biased by `providers.<id>.weight` (default 1). Weight 0 takes an adapter out of
+/// rotation.
+pub struct WeightedRandom {
+    weights: HashMap<String, u32>,
+}
+
+impl WeightedRandom {
+    pub fn new(providers: &ProvidersConfig) -> Self {
+        Self { weights: providers.0.iter().map(|(id, p)| (id.clone(), p.weight)).collect() }
+    }
+
+    fn weight(&self, id: &str) -> u32 {
+        self.weights.get(id).copied().unwrap_or(1)
+    }
+}
+
+impl RoutingPolicy for WeightedRandom {
+    fn route(&self, _req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String> {
+        let mut pool: Vec<(&str, u64)> = adapters
+            .iter()
+            .map(|a| (a.adapter_id.as_str(), u64::from(self.weight(&a.adapter_id))))
+            .filter(|(_, w)| *w > 0)
+            .collect();
+        let mut out = Vec::with_capacity(pool.len());
+        // Sampling without replacement, so failover order is weighted too.
+        while !pool.is_empty() {
+            let total: u64 = pool.iter().map(|(_, w)| w).sum();
+            let mut pick = fastrand::u64(0..total);
+            let idx = pool
+                .iter()
+                .position(|(_, w)| {
+                    if pick < *w {
+                        return true;
+                    }
+                    pick -= w;
+                    false
+                })
+                .unwrap_or(0);
+            out.push(pool.remove(idx).0.to_string());
+        }
+        out
+    }
+}
+
+/// Fewest in-flight forwards first; ties keep id order.
+#[derive(Default)]
+pub struct LeastOutstanding;
+
+impl RoutingPolicy for LeastOutstanding {
+    fn route(&self, _req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String> {
+        let mut sorted: Vec<&AdapterSnapshot> = adapters.iter().collect();
+        sorted.sort_by_key(|a| a.in_flight);
+        sorted.into_iter().map(|a| a.adapter_id.clone()).collect()
+    }
+}
+
+/// Drops adapters lacking a capability the request needs (currently `streaming` when
+/// `constraints.stream` is set), then orders the rest with `then`.
+pub struct CapabilityMatch<P> {
+    then: P,
+}
+
+impl<P: RoutingPolicy> CapabilityMatch<P> {
+    pub fn new(then: P) -> Self {
+        Self { then }
+    }
+}
+
+fn satisfies(req: &CanonicalAIRequest, caps: &AdapterCapabilities) -> bool {
+    !req.constraints.stream || caps.streaming
+}
+
+impl<P: RoutingPolicy> RoutingPolicy for CapabilityMatch<P> {
+    fn route(&self, req: &CanonicalAIRequest, adapters: &[AdapterSnapshot]) -> Vec<String> {
+        let eligible: Vec<AdapterSnapshot> =
+            adapters.iter().filter(|a| satisfies(req, &a.capabilities)).cloned().collect();
+        self.then.route(req, &eligible)
+    }
+}
+
+pub fn build(kind: RoutingPolicyKind, providers: &ProvidersConfig) -> Arc<dyn RoutingPolicy> {
+    match kind {
+        RoutingPolicyKind::Priority => Arc::new(Priority::new(providers.clone())),
+        RoutingPolicyKind::RoundRobin => Arc::new(RoundRobin::default()),
+        RoutingPolicyKind::WeightedRandom => Arc::new(WeightedRandom::new(providers)),
+        RoutingPolicyKind::LeastOutstanding => Arc::new(LeastOutstanding),
+        RoutingPolicyKind::CapabilityMatch => Arc::new(CapabilityMatch::new(Priority::new(providers.clone()))),
+    }
+}
+
+/// The configured default policy plus per-pipeline overrides.
+pub(crate) struct Router {
+    default: Arc<dyn RoutingPolicy>,
+    pipelines: HashMap<String, Arc<dyn RoutingPolicy>>,
+}
+
+impl Router {
+    pub(crate) fn new(config: &RoutingConfig, providers: &ProvidersConfig) -> Self {
+        Self {
+            default: build(config.policy, providers),
+            pipelines: config.pipelines.iter().map(|(name, kind)| (name.clone(), build(*kind, providers))).collect(),
+        }
+    }
+
+    /// The policy for `metadata.pipeline`, or the default when unset or unknown.
+    pub(crate) fn policy_for(&self, req: &CanonicalAIRequest) -> &dyn RoutingPolicy {
+        req.metadata
+            .get("pipeline")
+            .and_then(|name| self.pipelines.get(name))
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(id: &str, streaming: bool, in_flight: usize) -> AdapterSnapshot {
+        AdapterSnapshot {
+            adapter_id: id.to_string(),
+            capabilities: AdapterCapabilities { streaming, ..Default::default() },
+            in_flight,
+        }
+    }
+
+    #[test]
+    fn round_robin_rotates() {
+        let rr = RoundRobin::default();
+        let adapters = [snap("a", false, 0), snap("b", false, 0), snap("c", false, 0)];
+        let req = CanonicalAIRequest::new();
+        assert_eq!(rr.route(&req, &adapters), vec!["a", "b", "c"]);
+        assert_eq!(rr.route(&req, &adapters), vec!["b", "c", "a"]);
+        assert_eq!(rr.route(&req, &adapters), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn least_outstanding_prefers_idle() {
+        let adapters = [snap("a", false, 3), snap("b", false, 0), snap("c", false, 1)];
+        assert_eq!(LeastOutstanding.route(&CanonicalAIRequest::new(), &adapters), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn weighted_random_skips_zero_weight() {
+        let providers: ProvidersConfig = serde_yaml::from_str("a: { weight: 0 }\nb: { weight: 5 }").unwrap();
+        let policy = WeightedRandom::new(&providers);
+        let adapters = [snap("a", false, 0), snap("b", false, 0), snap("c", false, 0)];
+        let mut order = policy.route(&CanonicalAIRequest::new(), &adapters);
+        order.sort();
+        assert_eq!(order, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn capability_match_requires_streaming() {
+        let policy = CapabilityMatch::new(LeastOutstanding);
+        let adapters = [snap("a", false, 0), snap("b", true, 2)];
+        let mut req = CanonicalAIRequest::new();
+        assert_eq!(policy.route(&req, &adapters), vec!["a", "b"]);
+        req.constraints.stream = true;
+        assert_eq!(policy.route(&req, &adapters), vec!["b"]);
+    }
+}
@@ -0,0 +1,104 @@
+//! Adapter transports.
+//!
+//! Every transport yields a lazily-connecting [`Channel`], so forwarding does not care whether
+//! the adapter is a remote gRPC server, a sidecar on a Unix socket, or a service running inside
+//! the core process.
+
+use std::str::FromStr;
+
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Server, Uri};
+use tower::service_fn;
+
+use crate::proto::adapter_service_server::{AdapterService, AdapterServiceServer};
+
+/// Transport selected by `adapters[].kind` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    /// HTTP/2 gRPC; `endpoint` is a URL such as `http://127.0.0.1:6000`, or a
+    /// `unix:///path/to.sock` URL.
+    Grpc,
+    /// gRPC over a Unix domain socket; `endpoint` is the socket path (with or without `unix://`).
+    Unix,
+    /// A service hosted inside the core; `endpoint` names a built-in adapter.
+    InProcess,
+}
+
+impl FromStr for TransportKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grpc" => Ok(Self::Grpc),
+            "unix" | "uds" => Ok(Self::Unix),
+            "in_process" | "in-process" => Ok(Self::InProcess),
+            other => Err(anyhow::anyhow!("unknown adapter kind {other:?} (expected grpc, unix or in_process)")),
+        }
+    }
+}
+
+/// Channel for a registered endpoint: `unix:///path/to.sock` dials a Unix socket, anything
+/// else is treated as an HTTP/2 URL.
+pub(crate) fn endpoint_channel(endpoint: &str) -> anyhow::Result<Channel> {
+    match unix_path(endpoint) {
+        Some(path) => unix_channel(path),
+        None => grpc_channel(endpoint),
+    }
+}
+
+/// The socket path of a `unix://` endpoint.
+pub fn unix_path(endpoint: &str) -> Option<&str> {
+    endpoint.strip_prefix("unix://")
+}
+
+pub(crate) fn grpc_channel(endpoint: &str) -> anyhow::Result<Channel> {
+    Ok(Endpoint::from_shared(endpoint.to_string())?.connect_lazy())
+}
+
+pub(crate) fn unix_channel(path: &str) -> anyhow::Result<Channel> {
+    if path.is_empty() {
+        anyhow::bail!("unix socket path required");
+    }
+    let path = path.to_string();
+    // The URI is only used for the HTTP/2 `:authority`; the connector ignores it.
+    Ok(Endpoint::from_static("http://localhost").connect_with_connector_lazy(service_fn(move |_: Uri| {
+        UnixStream::connect(path.clone())
+    })))
+}
+
+/// Channel to a gRPC service running in this process over an in-memory duplex pipe.
+pub(crate) fn in_process_channel<S: AdapterService>(svc: S) -> Channel {
+    let svc = AdapterServiceServer::new(svc);
+    Endpoint::from_static("http://in-process").connect_with_connector_lazy(service_fn(move |_: Uri| {
+        let svc = svc.clone();
+        async move {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(
+                Server::builder()
+                    .add_service(svc)
+                    .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server))),
+            );
+            Ok::<_, std::io::Error>(client)
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_kinds() {
+        assert_eq!("grpc".parse::<TransportKind>().unwrap(), TransportKind::Grpc);
+        assert_eq!("unix".parse::<TransportKind>().unwrap(), TransportKind::Unix);
+        assert_eq!("in_process".parse::<TransportKind>().unwrap(), TransportKind::InProcess);
+        assert!("carrier-pigeon".parse::<TransportKind>().is_err());
+    }
+
+    #[test]
+    fn recognises_unix_endpoints() {
+        assert_eq!(unix_path("unix:///run/pagi/python.sock"), Some("/run/pagi/python.sock"));
+        assert_eq!(unix_path("http://127.0.0.1:6000"), None);
+        assert!(endpoint_channel("unix://").is_err());
+    }
+}